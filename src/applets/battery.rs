@@ -0,0 +1,187 @@
+use std::path::Path;
+
+use crate::applets::sysfs::{entries, number, read};
+use crate::panel::PanelIcon;
+
+//Battery applet. Reads the batteries in <sysfs>/class/power_supply and adds them together.
+
+pub const PROGRAM: &str = "tullamore:battery";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Status {
+	Charging,
+	Discharging,
+	Full,
+	Unknown, //Plugged in but not charging, or the battery doesn't say.
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Reading {
+	pub percent: u64,
+	pub status: Status,
+	pub remaining: Option<u64>, //Seconds until empty, or until full when charging.
+}
+
+pub fn sample(sysfs: &str) -> Option<Reading> {
+	//None if there is no battery at all.
+	let mut found = false;
+	let (mut now, mut full, mut rate) = (0, 0, 0);
+	let mut capacities = Vec::new();
+	let mut statuses = Vec::new();
+	for supply in entries(&Path::new(sysfs).join("class/power_supply")) {
+		if read(&supply.join("type")).as_deref() != Some("Battery") || number(&supply.join("present")) == Some(0) {
+			continue;
+		}
+		found = true;
+		//Energy in µWh with power in µW, or charge in µAh with current in µA. Either gives hours the same way.
+		let pair = |a: &str, b: &str, c: &str| Some((number(&supply.join(a))?, number(&supply.join(b))?, number(&supply.join(c)).unwrap_or(0)));
+		match pair("energy_now", "energy_full", "power_now").or_else(|| pair("charge_now", "charge_full", "current_now")) {
+			Some((batterynow, batteryfull, batteryrate)) => {
+				now += batterynow;
+				full += batteryfull;
+				rate += batteryrate;
+			}
+			None => capacities.extend(number(&supply.join("capacity"))),
+		}
+		statuses.push(read(&supply.join("status")).unwrap_or_default());
+	}
+	if !found {
+		return None;
+	}
+
+	let percent = (now * 100).checked_div(full).map(|percent| percent.min(100))
+		.or_else(|| capacities.iter().sum::<u64>().checked_div(capacities.len() as u64))
+		.unwrap_or(0);
+	let status = if statuses.iter().any(|status| status == "Charging") {
+		Status::Charging
+	} else if statuses.iter().any(|status| status == "Discharging") {
+		Status::Discharging
+	} else if statuses.iter().all(|status| status == "Full") {
+		Status::Full
+	} else {
+		Status::Unknown
+	};
+	let remaining = match status {
+		_ if rate == 0 || full == 0 => None,
+		Status::Discharging => Some(now * 3600 / rate),
+		Status::Charging => Some(full.saturating_sub(now) * 3600 / rate),
+		_ => None,
+	};
+	Some(Reading { percent, status, remaining })
+}
+
+impl Reading {
+	pub fn icon(&self, low: u64, critical: u64) -> String {
+		//Named like the freedesktop battery icons.
+		let level = match self.percent {
+			_ if self.status == Status::Full => return "battery-full-charged.png".to_string(),
+			percent if percent <= critical => "empty",
+			percent if percent <= low => "caution",
+			percent if percent < 40 => "low",
+			percent if percent < 80 => "good",
+			_ => "full",
+		};
+		if self.status == Status::Charging {
+			format!("battery-{}-charging.png", level)
+		} else {
+			format!("battery-{}.png", level)
+		}
+	}
+
+	pub fn tooltip(&self) -> String {
+		let time = self.remaining.map(|seconds| format!("{}:{:02}", seconds / 3600, seconds / 60 % 60));
+		match (self.status, time) {
+			(Status::Full, _) => "Battery full".to_string(),
+			(Status::Charging, Some(time)) => format!("Battery {}%, charging, {} until full", self.percent, time),
+			(Status::Charging, None) => format!("Battery {}%, charging", self.percent),
+			(_, Some(time)) => format!("Battery {}%, {} left", self.percent, time),
+			(_, None) => format!("Battery {}%", self.percent),
+		}
+	}
+
+	pub fn trayicon(&self, low: u64, critical: u64) -> PanelIcon {
+		PanelIcon { program: PROGRAM.to_string(), label: "Battery".to_string(), tooltip: self.tooltip(), icon: self.icon(low, critical), ..Default::default() }
+	}
+
+	pub fn warning(&self, low: u64, critical: u64, warned: &mut u8) -> Option<String> {
+		//A warning when the battery first runs down past low, then again past critical.
+		//Plugging it in resets them. warned keeps track of how far it has got: 0, 1 for low or 2 for critical.
+		let level = match self.percent {
+			_ if self.status != Status::Discharging => 0,
+			percent if percent <= critical => 2,
+			percent if percent <= low => 1,
+			_ => 0,
+		};
+		let previous = std::mem::replace(warned, level);
+		match level {
+			_ if level <= previous => None,
+			2 => Some(format!("Battery critical: {}% left. Plug in now!", self.percent)),
+			_ => Some(format!("Battery low: {}% left.", self.percent)),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::applets::sysfs::FakeTree;
+
+	#[test]
+	fn energyandpowergivetimeleft() {
+		let tree = FakeTree::new("battery-energy");
+		tree.values("class/power_supply/AC", &[("type", "Mains"), ("online", "0")]);
+		tree.values("class/power_supply/BAT0", &[("type", "Battery"), ("status", "Discharging"), ("energy_now", "30000000"), ("energy_full", "40000000"), ("power_now", "10000000")]);
+		let reading = sample(&tree.path("")).unwrap();
+		assert_eq!(reading, Reading { percent: 75, status: Status::Discharging, remaining: Some(3 * 3600) });
+		assert_eq!(reading.tooltip(), "Battery 75%, 3:00 left");
+		assert_eq!(reading.icon(20, 5), "battery-good.png");
+	}
+
+	#[test]
+	fn chargingcountsuptofull() {
+		let tree = FakeTree::new("battery-charge");
+		tree.values("class/power_supply/BAT0", &[("type", "Battery"), ("status", "Charging"), ("charge_now", "1000000"), ("charge_full", "2000000"), ("current_now", "2000000")]);
+		let reading = sample(&tree.path("")).unwrap();
+		assert_eq!(reading.remaining, Some(1800));
+		assert_eq!(reading.tooltip(), "Battery 50%, charging, 0:30 until full");
+		assert_eq!(reading.icon(20, 5), "battery-good-charging.png");
+	}
+
+	#[test]
+	fn capacityonlyhasnotime() {
+		let tree = FakeTree::new("battery-capacity");
+		tree.values("class/power_supply/battery", &[("type", "Battery"), ("status", "Not charging"), ("capacity", "15")]);
+		let reading = sample(&tree.path("")).unwrap();
+		assert_eq!(reading, Reading { percent: 15, status: Status::Unknown, remaining: None });
+		assert_eq!(reading.icon(20, 5), "battery-caution.png");
+	}
+
+	#[test]
+	fn nobatterynoicon() {
+		let tree = FakeTree::new("battery-mains");
+		tree.values("class/power_supply/AC", &[("type", "Mains"), ("online", "1")]);
+		assert_eq!(sample(&tree.path("")), None);
+		let tree = FakeTree::new("battery-absent");
+		tree.values("class/power_supply/BAT0", &[("type", "Battery"), ("present", "0"), ("capacity", "0")]);
+		assert_eq!(sample(&tree.path("")), None);
+		assert_eq!(sample("/nonexistent"), None);
+	}
+
+	#[test]
+	fn warnsonceperlevel() {
+		let mut warned = 0;
+		let mut reading = Reading { percent: 30, status: Status::Discharging, remaining: None };
+		assert_eq!(reading.warning(20, 5, &mut warned), None);
+		reading.percent = 20;
+		assert_eq!(reading.warning(20, 5, &mut warned), Some("Battery low: 20% left.".to_string()));
+		reading.percent = 19;
+		assert_eq!(reading.warning(20, 5, &mut warned), None);
+		reading.percent = 5;
+		assert!(reading.warning(20, 5, &mut warned).unwrap().starts_with("Battery critical"));
+		reading.status = Status::Charging;
+		assert_eq!(reading.warning(20, 5, &mut warned), None);
+		assert_eq!(warned, 0);
+		reading.status = Status::Discharging;
+		assert!(reading.warning(20, 5, &mut warned).is_some());
+	}
+}
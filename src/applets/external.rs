@@ -0,0 +1,324 @@
+use std::{
+	io::{ErrorKind, Read, Write},
+	os::unix::io::{AsRawFd, RawFd},
+	process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+	time::{Duration, Instant},
+};
+
+use crate::panel::PanelIcon;
+use crate::system::{launch::expandhome, poll::nonblocking};
+
+//Tray applets run by another program, declared with [applet] in the settings.
+//Each line the program prints changes its icon, label or tooltip, either as key = value like the settings file,
+//or as a JSON object like {"icon": "battery-low.png", "tooltip": "Nearly empty"}. An empty icon takes it out of the tray.
+//Clicks go back on its stdin as button = 1, or {"button": 1} once it has spoken JSON.
+
+const RESTART: u64 = 5000; //How long to leave an applet that quit before running it again.
+
+struct Applet {
+	icon: PanelIcon, //What it shows now. program is its command, which is how the tray knows it.
+	child: Option<Child>,
+	stdin: Option<ChildStdin>,
+	stdout: Option<ChildStdout>, //Nonblocking, so the event loop can read whatever is there when poll says so.
+	buffer: Vec<u8>, //Output that hasn't made a whole line yet.
+	json: bool,
+	restartat: Option<Instant>,
+}
+
+pub struct Applets {
+	applets: Vec<Applet>,
+	display: Option<String>,
+}
+
+impl Applets {
+	pub fn start(applets: &[PanelIcon], display: Option<&str>) -> Option<Applets> {
+		//None if the settings don't have any.
+		if applets.is_empty() {
+			return None;
+		}
+		let mut external = Applets {
+			applets: applets.iter().map(|icon| Applet { icon: icon.clone(), child: None, stdin: None, stdout: None, buffer: Vec::new(), json: false, restartat: None }).collect(),
+			display: display.map(|display| display.to_string()),
+		};
+		for n in 0..external.applets.len() {
+			external.run(n);
+		}
+		Some(external)
+	}
+
+	pub fn wake(&self) -> Option<Instant> {
+		//When the next one that quit is due to run again.
+		self.applets.iter().filter_map(|applet| applet.restartat).min()
+	}
+
+	pub fn fds(&self) -> Vec<RawFd> {
+		//What to wait on for them to say something.
+		self.applets.iter().filter_map(|applet| applet.stdout.as_ref().map(|stdout| stdout.as_raw_fd())).collect()
+	}
+
+	pub fn icons(&self) -> Vec<(String, Option<PanelIcon>)> {
+		//Every applet's tray icon, by program, or None where it has nothing to show.
+		self.applets.iter().map(|applet| (applet.icon.program.clone(), Some(applet.icon.clone()).filter(|icon| !icon.icon.is_empty()))).collect()
+	}
+
+	pub fn service(&mut self, now: Instant) -> Vec<(String, Option<PanelIcon>)> {
+		//Read what they've said, and run any that quit again. Gives back the icons that changed.
+		let mut changed = Vec::new();
+		for (n, applet) in self.applets.iter_mut().enumerate() {
+			let Some(stdout) = applet.stdout.as_mut() else {
+				continue;
+			};
+			let mut closed = false;
+			let mut chunk = [0; 4096];
+			loop {
+				match stdout.read(&mut chunk) {
+					Ok(0) => {
+						closed = true;
+						break;
+					}
+					Ok(length) => applet.buffer.extend_from_slice(&chunk[..length]),
+					Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+					Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+					Err(_) => {
+						closed = true;
+						break;
+					}
+				}
+			}
+			let before = applet.icon.clone();
+			while let Some(end) = applet.buffer.iter().position(|&byte| byte == b'\n') {
+				let line: Vec<u8> = applet.buffer.drain(..=end).collect();
+				applet.json |= apply(&mut applet.icon, &String::from_utf8_lossy(&line));
+			}
+			if applet.icon != before {
+				changed.push(n);
+			}
+			if closed {
+				//Its output closed, so it has quit, or is no use to us if it hasn't. Reap it and try again in a while.
+				if let Some(mut child) = applet.child.take() {
+					match child.try_wait() {
+						Ok(Some(status)) => eprintln!("Applet '{}' quit with {}", applet.icon.program, status),
+						_ => {
+							eprintln!("Applet '{}' closed its output", applet.icon.program);
+							let _ = child.kill();
+							let _ = child.wait();
+						}
+					}
+				}
+				applet.stdin = None;
+				applet.stdout = None;
+				applet.buffer.clear();
+				applet.restartat = Some(now + Duration::from_millis(RESTART));
+			}
+		}
+		for n in 0..self.applets.len() {
+			if self.applets[n].restartat.is_some_and(|when| now >= when) {
+				self.run(n);
+			}
+		}
+		changed.into_iter().map(|n| {
+			let icon = &self.applets[n].icon;
+			(icon.program.clone(), Some(icon.clone()).filter(|icon| !icon.icon.is_empty()))
+		}).collect()
+	}
+
+	pub fn click(&mut self, program: &str, button: u8) -> bool {
+		//Tell the applet with this tray icon it was clicked. False if it isn't one of ours.
+		let Some(applet) = self.applets.iter_mut().find(|applet| applet.icon.program == program) else {
+			return false;
+		};
+		let line = if applet.json { format!("{{\"button\": {}}}\n", button) } else { format!("button = {}\n", button) };
+		if let Some(stdin) = applet.stdin.as_mut() {
+			if let Err(e) = stdin.write_all(line.as_bytes()).and_then(|_| stdin.flush()) {
+				//It isn't listening. Its output closing will tell us if it has quit.
+				eprintln!("Applet '{}' didn't take the click: {}", applet.icon.program, e);
+				applet.stdin = None;
+			}
+		}
+		true
+	}
+
+	fn run(&mut self, n: usize) {
+		//Start the nth applet through the shell, reading its output without blocking so the event loop can wait on it.
+		let applet = &mut self.applets[n];
+		applet.restartat = None;
+		let mut command = Command::new("/bin/sh");
+		command.arg("-c").arg(&applet.icon.program).stdin(Stdio::piped()).stdout(Stdio::piped());
+		if !applet.icon.directory.is_empty() {
+			command.current_dir(expandhome(&applet.icon.directory));
+		}
+		if let Some(display) = &self.display {
+			command.env("DISPLAY", display);
+		}
+		command.envs(applet.icon.environment.iter().map(|(name, value)| (name, value)));
+		let mut child = match command.spawn() {
+			Ok(child) => child,
+			Err(e) => {
+				eprintln!("Can't run applet '{}': {}", applet.icon.program, e);
+				applet.restartat = Some(Instant::now() + Duration::from_millis(RESTART));
+				return;
+			}
+		};
+		applet.stdin = child.stdin.take();
+		applet.stdout = child.stdout.take().filter(|stdout| match nonblocking(stdout.as_raw_fd()) {
+			Ok(()) => true,
+			Err(e) => {
+				//Reading it as it is could hang the whole desktop, so go without.
+				eprintln!("Can't read applet '{}': {}", applet.icon.program, e);
+				false
+			}
+		});
+		applet.child = Some(child);
+	}
+}
+
+impl Drop for Applets {
+	fn drop(&mut self) {
+		//Don't leave them running behind us, or a restart would start a second lot.
+		for applet in &mut self.applets {
+			if let Some(mut child) = applet.child.take() {
+				let _ = child.kill();
+				let _ = child.wait();
+			}
+		}
+	}
+}
+
+fn apply(icon: &mut PanelIcon, line: &str) -> bool {
+	//Change the icon from one line of output. True if the line was JSON.
+	let line = line.trim();
+	let (fields, json) = match line.starts_with('{') {
+		true => match jsonobject(line) {
+			Some(fields) => (fields, true),
+			None => {
+				eprintln!("Applet '{}' said something that isn't JSON: {}", icon.program, line);
+				return true;
+			}
+		},
+		false => match line.split_once('=') {
+			Some((key, value)) => (vec![(key.trim().to_string(), value.trim().to_string())], false),
+			None => return false, //Anything else is just chatter.
+		},
+	};
+	for (key, value) in fields {
+		match key.as_str() {
+			"icon" => icon.icon = value,
+			"label" => icon.label = value,
+			"tooltip" => icon.tooltip = value,
+			_ => {}
+		}
+	}
+	json
+}
+
+fn jsonobject(text: &str) -> Option<Vec<(String, String)>> {
+	//Just enough JSON for one flat object. Strings come back unescaped, numbers and the like as they were written.
+	let mut chars = text.trim().chars().peekable();
+	let mut fields = Vec::new();
+	let skip = |chars: &mut std::iter::Peekable<std::str::Chars>| while chars.peek().is_some_and(|c| c.is_whitespace()) {
+		chars.next();
+	};
+	let string = |chars: &mut std::iter::Peekable<std::str::Chars>| -> Option<String> {
+		let mut string = String::new();
+		loop {
+			match chars.next()? {
+				'"' => return Some(string),
+				'\\' => match chars.next()? {
+					'n' => string.push('\n'),
+					't' => string.push('\t'),
+					'r' => string.push('\r'),
+					'b' => string.push('\u{8}'),
+					'f' => string.push('\u{c}'),
+					'u' => {
+						let hex: String = (0..4).filter_map(|_| chars.next()).collect();
+						string.push(char::from_u32(u32::from_str_radix(&hex, 16).ok()?).unwrap_or('?'));
+					}
+					c => string.push(c),
+				},
+				c => string.push(c),
+			}
+		}
+	};
+	(chars.next()? == '{').then_some(())?;
+	skip(&mut chars);
+	if chars.peek() == Some(&'}') {
+		chars.next();
+	} else {
+		loop {
+			skip(&mut chars);
+			(chars.next()? == '"').then_some(())?;
+			let key = string(&mut chars)?;
+			skip(&mut chars);
+			(chars.next()? == ':').then_some(())?;
+			skip(&mut chars);
+			let value = if chars.peek() == Some(&'"') {
+				chars.next();
+				string(&mut chars)?
+			} else {
+				let mut value = String::new();
+				while let Some(&c) = chars.peek().filter(|&&c| c != ',' && c != '}') {
+					value.push(c);
+					chars.next();
+				}
+				value.trim().to_string()
+			};
+			fields.push((key, value));
+			skip(&mut chars);
+			match chars.next()? {
+				',' => continue,
+				'}' => break,
+				_ => return None,
+			}
+		}
+	}
+	skip(&mut chars);
+	chars.next().is_none().then_some(fields)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn linesandjsonchangetheicon() {
+		let mut icon = PanelIcon { program: "weather".to_string(), label: "weather".to_string(), ..Default::default() };
+		assert!(!apply(&mut icon, "icon = weather-snow.png"));
+		assert!(!apply(&mut icon, "just saying hello"));
+		assert_eq!(icon.icon, "weather-snow.png");
+		assert!(apply(&mut icon, r#"{"tooltip": "Snowing, \"heavily\"", "label": "Weather", "temperature": -2}"#));
+		assert_eq!((icon.label.as_str(), icon.tooltip.as_str()), ("Weather", "Snowing, \"heavily\""));
+		assert!(apply(&mut icon, "{\"icon\": \"\"}"));
+		assert_eq!(icon.icon, "");
+	}
+
+	#[test]
+	fn jsonisflat() {
+		assert_eq!(jsonobject("{}"), Some(vec![]));
+		assert_eq!(jsonobject(r#" {"a":"café", "b" : true } "#), Some(vec![("a".to_string(), "café".to_string()), ("b".to_string(), "true".to_string())]));
+		assert_eq!(jsonobject(r#"{"a": "unfinished"#), None);
+		assert_eq!(jsonobject(r#"{"a": "b"} trailing"#), None);
+	}
+
+	#[test]
+	fn appletsrunandhearclicks() {
+		//A shell applet that shows an icon, then answers each click by changing its tooltip.
+		let script = "echo icon = first.png; while read line; do echo \"tooltip = got $line\"; done";
+		let mut applets = Applets::start(&[PanelIcon { program: script.to_string(), ..Default::default() }], None).unwrap();
+		let wait = |applets: &mut Applets| {
+			let deadline = Instant::now() + Duration::from_secs(5);
+			loop {
+				let changed = applets.service(Instant::now());
+				if !changed.is_empty() || Instant::now() > deadline {
+					return changed;
+				}
+				crate::system::poll::wait(&applets.fds(), Some(Duration::from_millis(100))).unwrap();
+			}
+		};
+		let changed = wait(&mut applets);
+		assert_eq!(changed[0].1.as_ref().unwrap().icon, "first.png");
+		assert!(applets.click(script, 1));
+		assert!(!applets.click("something else", 1));
+		let changed = wait(&mut applets);
+		assert_eq!(changed[0].1.as_ref().unwrap().tooltip, "got button = 1");
+	}
+}
@@ -0,0 +1,158 @@
+use std::{collections::VecDeque, error::Error, path::Path};
+use x11rb::{connection::Connection, protocol::xproto::*};
+
+use crate::applets::sysfs::{entries, read};
+use crate::panel::PanelIcon;
+
+//System monitor applet. CPU from <proc>/stat, memory from <proc>/meminfo and the hottest of <sysfs>/class/thermal/thermal_zone*/temp.
+//It draws its own icon: the last few CPU readings as a little bar graph, newest on the right.
+
+pub const PROGRAM: &str = "tullamore:monitor";
+pub const HISTORY: usize = 16; //One bar per pixel across the icon.
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Reading {
+	pub cpu: u64, //Percent busy since the last sample.
+	pub memoryused: u64, //kB
+	pub memorytotal: u64,
+	pub temperature: Option<i64>, //Millidegrees Celsius. None without any thermal zones.
+}
+
+pub struct Monitor {
+	previous: Option<(u64, u64)>, //Busy and total jiffies last time, to take the difference from.
+	pub history: VecDeque<u64>, //CPU percentages, oldest first.
+	pub reading: Option<Reading>,
+}
+
+impl Monitor {
+	pub fn new() -> Self {
+		Monitor { previous: None, history: VecDeque::with_capacity(HISTORY), reading: None }
+	}
+
+	pub fn sample(&mut self, proc: &str, sysfs: &str) -> Option<Reading> {
+		self.reading = self.measure(proc, sysfs);
+		self.reading
+	}
+
+	fn measure(&mut self, proc: &str, sysfs: &str) -> Option<Reading> {
+		//None if there's no /proc/stat to go on. The first CPU reading is since boot.
+		let stat = read(&Path::new(proc).join("stat"))?;
+		let jiffies: Vec<u64> = stat.lines().next()?.strip_prefix("cpu ")?.split_whitespace().filter_map(|n| n.parse().ok()).collect();
+		//user nice system idle iowait irq softirq steal. Guest time is already counted in user and nice.
+		let total: u64 = jiffies.iter().take(8).sum();
+		let idle = jiffies.get(3).copied().unwrap_or(0) + jiffies.get(4).copied().unwrap_or(0);
+		let busy = total.saturating_sub(idle);
+		let (lastbusy, lasttotal) = self.previous.unwrap_or((0, 0));
+		let cpu = (busy.saturating_sub(lastbusy) * 100).checked_div(total.saturating_sub(lasttotal)).unwrap_or(0).min(100);
+		self.previous = Some((busy, total));
+
+		let meminfo = read(&Path::new(proc).join("meminfo")).unwrap_or_default();
+		let field = |name: &str| meminfo.lines().find_map(|line| line.strip_prefix(name)?.strip_prefix(':')?.split_whitespace().next()?.parse::<u64>().ok());
+		let memorytotal = field("MemTotal").unwrap_or(0);
+		//Older kernels don't say what's available, so count free memory and what's only caching.
+		let available = field("MemAvailable").unwrap_or_else(|| field("MemFree").unwrap_or(0) + field("Buffers").unwrap_or(0) + field("Cached").unwrap_or(0));
+		let memoryused = memorytotal.saturating_sub(available);
+
+		let temperature = entries(&Path::new(sysfs).join("class/thermal")).iter()
+			.filter(|zone| zone.file_name().is_some_and(|name| name.to_string_lossy().starts_with("thermal_zone")))
+			.filter_map(|zone| read(&zone.join("temp"))?.parse::<i64>().ok())
+			.max();
+
+		if self.history.len() == HISTORY {
+			self.history.pop_front();
+		}
+		self.history.push_back(cpu);
+		Some(Reading { cpu, memoryused, memorytotal, temperature })
+	}
+
+	pub fn trayicon(&self) -> Option<PanelIcon> {
+		//No png. The panel calls draw instead.
+		let reading = self.reading?;
+		Some(PanelIcon { program: PROGRAM.to_string(), label: "System Monitor".to_string(), tooltip: reading.tooltip(), ..Default::default() })
+	}
+
+	pub fn draw<C: Connection>(&self, xconnection: &C, window: Window, x: i16, y: i16, hot: bool, gc_lowlight: Gcontext, gc_highlight: Gcontext, gc_warning: Gcontext) -> Result<(), Box<dyn Error>> {
+		//A 16x16 graph, bars rising from the bottom. Red while anything is over its warning.
+		xconnection.poly_fill_rectangle(window, gc_lowlight, &[Rectangle { x, y, width: 16, height: 16 }])?;
+		let bars: Vec<Rectangle> = self.history.iter().rev().enumerate().map(|(n, &cpu)| {
+			let height = (cpu * 16).div_ceil(100) as i16;
+			Rectangle { x: x + 15 - n as i16, y: y + 16 - height, width: 1, height: height as u16 }
+		}).filter(|bar| bar.height > 0).collect();
+		if !bars.is_empty() {
+			xconnection.poly_fill_rectangle(window, if hot { gc_warning } else { gc_highlight }, &bars)?;
+		}
+		Ok(())
+	}
+}
+
+impl Reading {
+	pub fn memorypercent(&self) -> u64 {
+		(self.memoryused * 100).checked_div(self.memorytotal).unwrap_or(0)
+	}
+
+	pub fn hot(&self, cpu: u64, memory: u64, temperature: i64) -> bool {
+		//Warnings are in percent and whole degrees.
+		self.cpu >= cpu || self.memorypercent() >= memory || self.temperature.is_some_and(|millidegrees| millidegrees >= temperature * 1000)
+	}
+
+	pub fn tooltip(&self) -> String {
+		let mut tooltip = format!("CPU {}%, memory {}% ({} of {} MB)", self.cpu, self.memorypercent(), self.memoryused / 1024, self.memorytotal / 1024);
+		if let Some(millidegrees) = self.temperature {
+			tooltip += &format!(", {} C", millidegrees / 1000);
+		}
+		tooltip
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::applets::sysfs::FakeTree;
+
+	#[test]
+	fn cpucountsthedifference() {
+		let tree = FakeTree::new("monitor-cpu");
+		tree.file("proc/stat", "cpu  100 0 100 800 0 0 0 0 0 0\ncpu0 100 0 100 800 0 0 0 0 0 0\n");
+		tree.file("proc/meminfo", "MemTotal: 2048000 kB\nMemAvailable: 512000 kB\n");
+		tree.values("sys/class/thermal/thermal_zone0", &[("temp", "45000")]);
+		tree.values("sys/class/thermal/thermal_zone1", &[("temp", "61500")]);
+		tree.directory("sys/class/thermal/cooling_device0");
+		let mut monitor = Monitor::new();
+		let first = monitor.sample(&tree.path("proc"), &tree.path("sys")).unwrap();
+		assert_eq!(first, Reading { cpu: 20, memoryused: 1536000, memorytotal: 2048000, temperature: Some(61500) });
+		assert_eq!(first.tooltip(), "CPU 20%, memory 75% (1500 of 2000 MB), 61 C");
+
+		tree.file("proc/stat", "cpu  250 0 250 900 0 0 0 0 0 0\n");
+		let second = monitor.sample(&tree.path("proc"), &tree.path("sys")).unwrap();
+		assert_eq!(second.cpu, 75);
+		assert_eq!(monitor.history, [20, 75]);
+		assert!(second.hot(75, 90, 80));
+		assert!(!second.hot(90, 90, 80));
+		assert!(second.hot(90, 90, 60));
+	}
+
+	#[test]
+	fn oldmeminfoandnothermalzones() {
+		let tree = FakeTree::new("monitor-old");
+		tree.file("proc/stat", "cpu  0 0 0 0\n");
+		tree.file("proc/meminfo", "MemTotal: 1000 kB\nMemFree: 200 kB\nBuffers: 100 kB\nCached: 200 kB\n");
+		tree.directory("sys/class/thermal/cooling_device0");
+		let reading = Monitor::new().sample(&tree.path("proc"), &tree.path("sys")).unwrap();
+		assert_eq!(reading, Reading { cpu: 0, memoryused: 500, memorytotal: 1000, temperature: None });
+		assert_eq!(reading.tooltip(), "CPU 0%, memory 50% (0 of 0 MB)");
+		assert_eq!(Monitor::new().sample("/nonexistent", "/nonexistent"), None);
+	}
+
+	#[test]
+	fn historyscrolls() {
+		let tree = FakeTree::new("monitor-history");
+		tree.file("proc/stat", "cpu  1 0 0 0\n");
+		tree.file("proc/meminfo", "");
+		let mut monitor = Monitor::new();
+		for _ in 0..HISTORY + 5 {
+			monitor.sample(&tree.path("proc"), &tree.path("sys"));
+		}
+		assert_eq!(monitor.history.len(), HISTORY);
+		assert_eq!(monitor.history[0], 0);
+	}
+}
@@ -0,0 +1,195 @@
+use std::{
+	net::{Ipv4Addr, Ipv6Addr},
+	path::Path,
+};
+
+use crate::applets::sysfs::{entries, number, read};
+use crate::panel::PanelIcon;
+
+//Network applet. Interfaces and their state come from <sysfs>/class/net, addresses from <proc>/net.
+
+pub const PROGRAM: &str = "tullamore:network";
+
+const LOOPBACK: u64 = 772; //ARPHRD_LOOPBACK, in the interface's type file.
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Interface {
+	pub name: String,
+	pub wireless: bool,
+	pub physical: bool, //Has a device behind it, unlike bridges, tunnels and the like.
+	pub up: bool,
+	pub addresses: Vec<String>, //IPv4 first, then IPv6.
+}
+
+impl Interface {
+	pub fn connected(&self) -> bool {
+		self.up && !self.addresses.is_empty()
+	}
+
+	pub fn describe(&self) -> String {
+		//One line for the click list, like "eth0: 192.168.1.5".
+		if !self.up {
+			format!("{}: not connected", self.name)
+		} else if self.addresses.is_empty() {
+			format!("{}: no address", self.name)
+		} else {
+			format!("{}: {}", self.name, self.addresses.join(", "))
+		}
+	}
+}
+
+pub fn sample(sysfs: &str, proc: &str) -> Vec<Interface> {
+	//Every interface but loopback, in name order.
+	let ipv4 = ipv4addresses(proc);
+	let ipv6 = ipv6addresses(proc);
+	entries(&Path::new(sysfs).join("class/net")).into_iter().filter(|path| number(&path.join("type")) != Some(LOOPBACK)).map(|path| {
+		let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+		//Some drivers never say up, only that there's a cable in.
+		let up = match read(&path.join("operstate")).as_deref() {
+			Some("up") => true,
+			Some("unknown") => number(&path.join("carrier")) == Some(1),
+			_ => false,
+		};
+		let addresses = ipv4.iter().chain(ipv6.iter()).filter(|(interface, _)| *interface == name).map(|(_, address)| address.clone()).collect();
+		Interface { wireless: path.join("wireless").exists() || path.join("phy80211").exists(), physical: path.join("device").exists(), up, addresses, name }
+	}).collect()
+}
+
+pub fn trayicon(interfaces: &[Interface]) -> PanelIcon {
+	//Wired wins over wireless when both are connected. Bridges and tunnels don't count as being online.
+	let connected = |wireless: bool| interfaces.iter().find(|interface| interface.physical && interface.wireless == wireless && interface.connected());
+	let (icon, tooltip) = match (connected(false), connected(true)) {
+		(Some(wired), _) => ("network-wired.png", format!("Wired: {}, {}", wired.name, wired.addresses[0])),
+		(None, Some(wireless)) => ("network-wireless.png", format!("Wireless: {}, {}", wireless.name, wireless.addresses[0])),
+		(None, None) => ("network-offline.png", "Network Offline".to_string()),
+	};
+	PanelIcon { program: PROGRAM.to_string(), label: "Network".to_string(), tooltip, icon: icon.to_string(), ..Default::default() }
+}
+
+fn ipv4addresses(proc: &str) -> Vec<(String, String)> {
+	//(interface, address) pairs. fib_trie has our addresses as "/32 host LOCAL" under them, but not which interface they are on,
+	//so each one goes to the interface with the most specific route to it.
+	let trie = read(&Path::new(proc).join("net/fib_trie")).unwrap_or_default();
+	let mut locals: Vec<Ipv4Addr> = Vec::new();
+	let mut last = None;
+	for line in trie.lines() {
+		let line = line.trim();
+		if let Some(address) = line.strip_prefix("|-- ") {
+			last = address.parse().ok();
+		} else if line.ends_with("host LOCAL") {
+			if let Some(address) = last.take().filter(|address: &Ipv4Addr| !address.is_loopback() && !locals.contains(address)) {
+				locals.push(address);
+			}
+		}
+	}
+
+	//Routes are interface, destination, gateway, flags, refcnt, use, metric, mask, all in hex and in host order.
+	let table = read(&Path::new(proc).join("net/route")).unwrap_or_default();
+	let routes: Vec<(String, u32, u32)> = table.lines().skip(1).filter_map(|line| {
+		let fields: Vec<&str> = line.split_whitespace().collect();
+		let hex = |n: usize| fields.get(n).and_then(|field| u32::from_str_radix(field, 16).ok()).map(u32::swap_bytes);
+		Some((fields.first()?.to_string(), hex(1)?, hex(7)?))
+	}).filter(|(_, _, mask)| *mask != 0).collect();
+
+	locals.into_iter().filter_map(|address| {
+		let number = u32::from(address);
+		let (interface, _, _) = routes.iter().filter(|(_, destination, mask)| number & mask == *destination).max_by_key(|(_, _, mask)| mask.count_ones())?;
+		Some((interface.clone(), address.to_string()))
+	}).collect()
+}
+
+fn ipv6addresses(proc: &str) -> Vec<(String, String)> {
+	//if_inet6 is address, index, prefix length, scope, flags and interface, one per line.
+	let list = read(&Path::new(proc).join("net/if_inet6")).unwrap_or_default();
+	list.lines().filter_map(|line| {
+		let fields: Vec<&str> = line.split_whitespace().collect();
+		let address = Ipv6Addr::from(u128::from_str_radix(fields.first()?, 16).ok()?);
+		Some((fields.get(5)?.to_string(), address.to_string())).filter(|_| !address.is_loopback())
+	}).collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::applets::sysfs::FakeTree;
+
+	const FIBTRIE: &str = "Main:
+  +-- 0.0.0.0/0 3 0 5
+     |-- 0.0.0.0
+        /0 universe UNICAST
+     +-- 127.0.0.0/8 2 0 2
+        |-- 127.0.0.1
+           /32 host LOCAL
+     +-- 192.168.1.0/24 2 0 2
+        |-- 192.168.1.5
+           /32 host LOCAL
+        |-- 192.168.1.255
+           /32 link BROADCAST
+Local:
+  +-- 0.0.0.0/0 3 0 5
+        |-- 192.168.1.5
+           /32 host LOCAL
+        |-- 10.0.0.7
+           /32 host LOCAL
+";
+
+	const ROUTE: &str = "Iface	Destination	Gateway 	Flags	RefCnt	Use	Metric	Mask		MTU	Window	IRTT
+eth0	00000000	0101A8C0	0003	0	0	100	00000000	0	0	0
+eth0	0001A8C0	00000000	0001	0	0	100	00FFFFFF	0	0	0
+wlan0	0000000A	00000000	0001	0	0	600	000000FF	0	0	0
+";
+
+	#[test]
+	fn addressesfindtheirinterfaces() {
+		//Real interfaces have a device directory, and wireless ones a wireless or phy80211 one as well.
+		let tree = FakeTree::new("network-addresses");
+		tree.values("sys/class/net/lo", &[("type", "772"), ("operstate", "unknown"), ("carrier", "1")]);
+		tree.values("sys/class/net/eth0", &[("type", "1"), ("operstate", "up")]);
+		tree.directory("sys/class/net/eth0/device");
+		tree.values("sys/class/net/wlan0", &[("type", "1"), ("operstate", "up")]);
+		tree.directory("sys/class/net/wlan0/device");
+		tree.directory("sys/class/net/wlan0/wireless");
+		tree.file("proc/net/fib_trie", FIBTRIE);
+		tree.file("proc/net/route", ROUTE);
+		tree.file("proc/net/if_inet6", "fe80000000000000021122fffe334455 02 40 20 80     eth0\n00000000000000000000000000000001 01 80 10 80       lo\n");
+		let interfaces = sample(&tree.path("sys"), &tree.path("proc"));
+		assert_eq!(interfaces.iter().map(|interface| interface.name.as_str()).collect::<Vec<_>>(), vec!["eth0", "wlan0"]);
+		assert_eq!(interfaces[0].addresses, vec!["192.168.1.5", "fe80::211:22ff:fe33:4455"]);
+		assert_eq!(interfaces[1].addresses, vec!["10.0.0.7"]);
+		assert!(interfaces[1].wireless && !interfaces[0].wireless);
+		let icon = trayicon(&interfaces);
+		assert_eq!((icon.icon.as_str(), icon.tooltip.as_str()), ("network-wired.png", "Wired: eth0, 192.168.1.5"));
+		assert_eq!(interfaces[1].describe(), "wlan0: 10.0.0.7");
+	}
+
+	#[test]
+	fn wirelesswhenthecableisout() {
+		let tree = FakeTree::new("network-wireless");
+		tree.values("sys/class/net/eth0", &[("type", "1"), ("operstate", "down")]);
+		tree.directory("sys/class/net/eth0/device");
+		tree.values("sys/class/net/wlan0", &[("type", "1"), ("operstate", "up")]);
+		tree.directory("sys/class/net/wlan0/device");
+		tree.directory("sys/class/net/wlan0/phy80211");
+		tree.file("proc/net/fib_trie", FIBTRIE);
+		tree.file("proc/net/route", ROUTE);
+		let interfaces = sample(&tree.path("sys"), &tree.path("proc"));
+		assert_eq!(trayicon(&interfaces).icon, "network-wireless.png");
+		assert_eq!(interfaces[0].describe(), "eth0: not connected");
+	}
+
+	#[test]
+	fn bridgesdontcountasonline() {
+		let tree = FakeTree::new("network-bridge");
+		tree.values("sys/class/net/docker0", &[("type", "1"), ("operstate", "up")]);
+		tree.values("sys/class/net/eth0", &[("type", "1"), ("operstate", "unknown"), ("carrier", "0")]);
+		tree.directory("sys/class/net/eth0/device");
+		tree.file("proc/net/fib_trie", "Local:\n  |-- 172.17.0.1\n     /32 host LOCAL\n");
+		tree.file("proc/net/route", "Iface\tDestination\tGateway\tFlags\tRefCnt\tUse\tMetric\tMask\ndocker0\t000011AC\t00000000\t0001\t0\t0\t0\t0000FFFF\n");
+		let interfaces = sample(&tree.path("sys"), &tree.path("proc"));
+		assert!(interfaces[0].connected());
+		assert!(!interfaces[1].up);
+		let icon = trayicon(&interfaces);
+		assert_eq!((icon.icon.as_str(), icon.tooltip.as_str()), ("network-offline.png", "Network Offline"));
+		assert_eq!(trayicon(&sample("/nonexistent", "/nonexistent")).icon, "network-offline.png");
+	}
+}
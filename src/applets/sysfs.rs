@@ -0,0 +1,67 @@
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
+
+//Reading the little one-value files in /sys and /proc. The roots come from the settings, so tests can point them at a fake tree.
+
+pub fn read(path: &Path) -> Option<String> {
+	//The file's contents without the trailing newline, or None if it isn't there.
+	fs::read_to_string(path).ok().map(|text| text.trim().to_string())
+}
+
+pub fn number(path: &Path) -> Option<u64> {
+	read(path)?.parse().ok()
+}
+
+pub fn entries(directory: &Path) -> Vec<PathBuf> {
+	//Everything in directory, in name order. Nothing if it doesn't exist.
+	let mut entries: Vec<PathBuf> = fs::read_dir(directory).map(|entries| entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect()).unwrap_or_default();
+	entries.sort();
+	entries
+}
+
+#[cfg(test)]
+pub struct FakeTree {
+	//A pretend tree of files under the temporary directory for the applets' tests. It goes away when the test is done with it.
+	root: PathBuf,
+}
+
+#[cfg(test)]
+impl FakeTree {
+	pub fn new(name: &str) -> FakeTree {
+		let root = std::env::temp_dir().join(format!("tullamore-{}-{}", name, std::process::id()));
+		let _ = fs::remove_dir_all(&root);
+		fs::create_dir_all(&root).unwrap();
+		FakeTree { root }
+	}
+
+	pub fn file(&self, path: &str, contents: &str) {
+		//Along with any directories it is in.
+		let path = self.root.join(path);
+		fs::create_dir_all(path.parent().unwrap()).unwrap();
+		fs::write(path, contents).unwrap();
+	}
+
+	pub fn values(&self, directory: &str, files: &[(&str, &str)]) {
+		//Files holding one value each, newline and all, the way sysfs has them.
+		for (file, value) in files {
+			self.file(&format!("{}/{}", directory, file), &format!("{}\n", value));
+		}
+	}
+
+	pub fn directory(&self, path: &str) {
+		fs::create_dir_all(self.root.join(path)).unwrap();
+	}
+
+	pub fn path(&self, path: &str) -> String {
+		self.root.join(path).to_string_lossy().to_string()
+	}
+}
+
+#[cfg(test)]
+impl Drop for FakeTree {
+	fn drop(&mut self) {
+		let _ = fs::remove_dir_all(&self.root);
+	}
+}
@@ -28,7 +28,7 @@ use crate::trundle::drawbookerframe;
 use crate::trundle::drawradiobutton;
 use crate::trundle::drawcheckbox;
 use crate::drawdepressedbumpyframe;
-use crate::insertpanelwindow;
+use crate::panel::Panel;
 use crate::focuswindow;
 use crate::drawpng;
 use crate::drawpngcover;
@@ -100,7 +100,7 @@ impl GameState {
     }
 }
 
-pub fn startprogram(xconnection: &impl Connection, screen: &Screen, panel: Window, width: i16, height: i16, panelindex: &mut [u8; 6], panelitems: &mut [[u8; 1]; 128], panelcoordinates: &mut [[i16; 2]; 128], panelwindows: &mut [[u32; 1]; 128], panelicons: &mut [[String; 4]; 32], gc_highlight: Gcontext, gc_lowlight: Gcontext, gc_highbackground: Gcontext, gc_lowbackground: Gcontext, gc_titlebar: Gcontext, gc_titlebartext: Gcontext, wm: &mut WindowManager, poly_lowlight: &mut Vec<Segment>, poly_index: &mut Vec<u8>, poly_windoworcolour: &mut Vec<u32>) -> Window { 
+pub fn startprogram(xconnection: &impl Connection, screen: &Screen, panel: Window, width: i16, height: i16, panelstate: &mut Panel, gc_highlight: Gcontext, gc_lowlight: Gcontext, gc_highbackground: Gcontext, gc_lowbackground: Gcontext, gc_titlebar: Gcontext, gc_titlebartext: Gcontext, gc_attention: Gcontext, gc_attentiontext: Gcontext, wm: &mut WindowManager, poly_lowlight: &mut Vec<Segment>, poly_index: &mut Vec<u8>, poly_windoworcolour: &mut Vec<u32>) -> Window { 
 	let inputwidth = 450;
 	let inputheight = 400;
 
@@ -123,9 +123,9 @@ pub fn startprogram(xconnection: &impl Connection, screen: &Screen, panel: Windo
 
 	match createwindow(xconnection, screen, 50, 50, gamewidth, gameheight, b"Superbun", width, height, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, wm) {
 		Ok(superbun) => {
-			insertpanelwindow(panelindex, superbun,  panelitems, panelcoordinates, panelwindows, panelicons);
+			panelstate.insertwindow(superbun);
 			basicscreen(xconnection, superbun, gamewidth, gameheight, buttonwidth, buttonheight, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground);
-			match focuswindow(wm, xconnection, panel, superbun, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, poly_lowlight, poly_index, poly_windoworcolour) {
+			match focuswindow(wm, xconnection, panel, superbun, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, gc_attention, gc_attentiontext, poly_lowlight, poly_index, poly_windoworcolour) {
 				Ok(_) => superbun,
 				Err(_) => 0,
 			}
@@ -2,8 +2,8 @@ use std::{
     error::Error,
     fs::File,
     io::{self, Read, BufReader},
-    thread,
-    time::{SystemTime, Duration},
+    os::unix::io::AsRawFd,
+    time::{SystemTime, Duration, Instant},
 };
 use lazy_static::lazy_static;
 use png::Decoder;
@@ -16,11 +16,24 @@ use x11rb::{
     },
     wrapper::ConnectionExt as _,
     COPY_DEPTH_FROM_PARENT,
+    NONE,
 };
 use std::collections::HashMap;
 
 mod system {
+   pub mod calendar;
    pub mod clicker;
+   pub mod clock;
+   pub mod dbus;
+   pub mod launch;
+   pub mod notifications;
+   pub mod poll;
+   pub mod popup;
+   pub mod settings;
+   pub mod startup;
+   pub mod timers;
+   pub mod tooltip;
+   pub mod tray;
 	
 }
 
@@ -33,12 +46,34 @@ mod games {
     pub mod superbun;
 }
 
+mod applets {
+    pub mod battery;
+    pub mod external;
+    pub mod monitor;
+    pub mod network;
+    pub mod sysfs;
+}
+
+mod panel;
+use panel::{Panel, PanelElement, ElementState, PanelIcon, Redraw};
 mod window;
 use window::redrawframes;
 use window::updateborder;
+use window::updategui;
 use window::createborder;
 use window::createwmborder;
 use window::drawwindowbuttons;
+use window::snapframe;
+use window::ownwindow;
+use window::releasewindows;
+use window::Atoms;
+use window::windowurgent;
+use window::setnetwmstate;
+use window::windowicon;
+use window::userplaced;
+use window::placeframe;
+use window::wantsdecorations;
+use window::windowclass;
 mod trundle;
 use trundle::windowborder;
 use trundle::drawtitlebar;
@@ -52,10 +87,17 @@ use trundle::drawclicky;
 use trundle::drawpng;
 use trundle::drawpngcover;
 use trundle::drawclock;
-use trundle::updateclock;
+use trundle::drawpanelarrow;
 use trundle::squishtext;
 use trundle::drawclickmenu;
 
+use system::settings::{SETTINGS, DragMode, FocusMode, PanelEdge};
+use system::startup::Exit;
+use system::popup::{Popup, PopupItem};
+use system::calendar::{Alarms, Calendar, Palette};
+use system::tooltip::Tooltip;
+use system::timers::{Timer, Timers};
+
 use trundle::{
     COLOURS,
     HIGHBACKGROUND_COLOUR,
@@ -63,7 +105,9 @@ use trundle::{
     HIGHLIGHT_COLOUR,
     LOWLIGHT_COLOUR,
     WALLPAPER_COLOUR,
-    TITLEBAR_COLOUR
+    TITLEBAR_COLOUR,
+    WARNING_COLOUR,
+    ATTENTION_COLOUR
 };
 
 struct WindowState {
@@ -78,51 +122,164 @@ struct WindowState {
     map: u8, //0 for hidden taskbar, 1 for hidden notification tray, 2 for visible and focused (taskbar), 3 for visble and not focused (taskbar)
 			 //4 for visible and focused (notification tray), 5 for visible and not focused (notification tray)
     order: u8,
+	urgent: bool, //Asking for attention with WM_HINTS or _NET_WM_STATE_DEMANDS_ATTENTION. Cleared when focused.
+	icon: Option<Vec<u32>>, //ICON_SIZE square ARGB from _NET_WM_ICON or the WM_HINTS pixmap. None draws the default icon.
+	decorated: bool, //False for windows that asked for no frame with _MOTIF_WM_HINTS. They are their own frame.
+	class: String, //From WM_CLASS. Taskbar buttons of the same class are grouped when they don't fit.
 }
 
 pub struct WindowManager {
     windows: HashMap<Window, WindowState>,
-    frames: HashMap<Window, Window>
+    frames: HashMap<Window, Window>,
+	atoms: Atoms,
 }
 
-fn grabexternalwindows<C: Connection>(xconnection: &C, wm: &mut WindowManager, root_window: Window,) -> Result<(), Box<dyn Error>> {
-    let tree = xconnection.query_tree(root_window)?.reply()?;
+fn grabexternalwindows<C: Connection>(xconnection: &C, screen: &Screen, wm: &mut WindowManager, panel: Window, width: i16, height: i16, panelstate: &mut Panel, gc_highlight: Gcontext, gc_lowlight: Gcontext, gc_highbackground: Gcontext, gc_lowbackground: Gcontext, gc_titlebar: Gcontext, gc_titlebartext: Gcontext, gc_attention: Gcontext, gc_attentiontext: Gcontext, poly_lowlight: &mut Vec<Segment>) -> Result<(), Box<dyn Error>> {
+	//Adopt whatever is already on screen. After a restart, these are the clients the old Tullamore handed back to the root.
+    let tree = xconnection.query_tree(screen.root)?.reply()?;
     for window in tree.children {
-        if wm.getwindow(&window).is_some() || wm.frames.contains_key(&window) {
+        if ownwindow(xconnection, window) {
+			//The panel and friends.
         } else if let Ok(attributes) = xconnection.get_window_attributes(window)?.reply() {
-            if attributes.map_state == MapState::VIEWABLE && !attributes.override_redirect {
-                if let Ok(geometry) = xconnection.get_geometry(window)?.reply() {
-                    let title = xconnection.get_property(false, window, AtomEnum::WM_NAME, AtomEnum::STRING, 0, u32::MAX,)?.reply().ok().and_then(|prop| String::from_utf8(prop.value).ok()).unwrap_or_else(|| String::from("Unknown"));
-					//I think we may be able to get away with eventually removing the below line.
-					wm.installexternalwindow(window, window, title, geometry.x, geometry.y, geometry.width as i16, geometry.height as i16, 0);
-                }
+            if attributes.map_state == MapState::VIEWABLE {
+				managewindow(xconnection, screen, wm, panel, window, width, height, panelstate, false, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, gc_attention, gc_attentiontext, poly_lowlight)?;
             }
         }
     }
     Ok(())
 }
 
+fn managewindow<C: Connection>(xconnection: &C, screen: &Screen, wm: &mut WindowManager, panel: Window, window: Window, width: i16, height: i16, panelstate: &mut Panel, place: bool, gc_highlight: Gcontext, gc_lowlight: Gcontext, gc_highbackground: Gcontext, gc_lowbackground: Gcontext, gc_titlebar: Gcontext, gc_titlebartext: Gcontext, gc_attention: Gcontext, gc_attentiontext: Gcontext, poly_lowlight: &mut Vec<Segment>) -> Result<bool, Box<dyn Error>> {
+	//Frame a client window and give it a taskbar button. Returns false if the window isn't ours to manage.
+	//With place, windows that didn't pick a position get one from the placement setting.
+	let external = if let Ok(attributes) = xconnection.get_window_attributes(window)?.reply() {
+		!attributes.override_redirect && wm.getwindow(&window).is_none() && !wm.frames.contains_key(&window)
+	} else {
+		false
+	};
+	if !external {
+		return Ok(false);
+	}
+	if let Ok(geom) = xconnection.get_geometry(window)?.reply() {
+		println!("Window Geometry Details:");
+		println!("  x: {}, y: {}", geom.x, geom.y);
+		println!("  width: {}, height: {}", geom.width as i16, geom.height as i16);
+		println!("  border_width: {}", geom.border_width);
+		println!("  depth: {}", geom.depth);
+		println!("  root: {:?}", geom.root);
+		
+		let title = xconnection.get_property(false, window, AtomEnum::WM_NAME, AtomEnum::STRING, 0, u32::MAX)?.reply().ok().and_then(|prop| String::from_utf8(prop.value).ok()).unwrap_or_else(|| String::from("Unknown"));
+		println!("  title: {}", title);
+		
+		let decorated = wantsdecorations(xconnection, &wm.atoms, window);
+		let (mut x, mut y) = (geom.x, geom.y);
+		if place && !userplaced(xconnection, window) {
+			let (fwidth, fheight) = if decorated {
+				(geom.width as i16 + (2 * BORDER) as i16, geom.height as i16 + (TITLEBAR + 2 * BORDER) as i16)
+			} else {
+				(geom.width as i16, geom.height as i16)
+			};
+			let area = panelstate.workarea(width, height, SETTINGS.autohide);
+			let frames = framegeometries(xconnection, wm, 0)?;
+			let pointer = xconnection.query_pointer(screen.root)?.reply().map_or((0, 0), |pointer| (pointer.root_x, pointer.root_y));
+			if let Some((fx, fy)) = placeframe(SETTINGS.placement, fwidth, fheight, area, &frames, pointer) {
+				if decorated {
+					//The client sits inside the frame, which createwmborder puts back around it.
+					x = fx + BORDER as i16;
+					y = fy + (TITLEBAR + BORDER) as i16;
+				} else {
+					(x, y) = (fx, fy);
+					xconnection.configure_window(window, &ConfigureWindowAux::new().x(x as i32).y(y as i32))?;
+				}
+			}
+		}
+		wm.installexternalwindow(window, window, title, x, y, geom.width as i16, geom.height as i16, 0);
+		//Hear about WM_HINTS, _NET_WM_STATE and _NET_WM_ICON changes, for urgency and icons. Without a frame, the client has to tell us about the pointer too.
+		let mask = if decorated { EventMask::PROPERTY_CHANGE } else { EventMask::PROPERTY_CHANGE | EventMask::ENTER_WINDOW };
+		xconnection.change_window_attributes(window, &ChangeWindowAttributesAux::default().event_mask(mask))?;
+		let icon = windowicon(xconnection, &wm.atoms, window);
+		let class = windowclass(xconnection, window);
+		if let Some(state) = wm.windows.get_mut(&window) {
+			state.icon = icon;
+			state.decorated = decorated;
+			state.class = class;
+		}
+		panelstate.insertwindow(window);
+		if !decorated {
+			//No frame to click on, so catch clicks on the window itself to focus it, then pass them on.
+			xconnection.grab_button(false, window, EventMask::BUTTON_PRESS, GrabMode::SYNC, GrabMode::ASYNC, NONE, NONE, ButtonIndex::ANY, ModMask::ANY)?;
+			xconnection.map_window(window)?;
+			wm.focus(xconnection, window, panel)?;
+		} else if let Ok(frame) = createwmborder(xconnection, screen, wm, window, geom.width, geom.height, width, height, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, gc_attention, gc_attentiontext, poly_lowlight) {
+			if let Some(state) = wm.windows.get_mut(&window) {
+				state.frame = frame;
+			}
+			wm.frames.insert(frame, window);
+			//New windows take the focus, to match the engaged button insertpanelwindow gave them.
+			wm.focus(xconnection, frame, panel)?;
+		}
+		return Ok(true);
+	}
+	Ok(false)
+}
+
+fn framegeometries<C: Connection>(xconnection: &C, wm: &WindowManager, except: Window) -> Result<Vec<(i16, i16, i16, i16)>, Box<dyn Error>> {
+	//Where every frame on screen is right now, as (x, y, width, height), for snapping and placement.
+	let frames: Vec<Window> = wm.windows.values().filter(|state| (state.map == 2 || state.map == 3) && state.frame != except && state.frame != 0).map(|state| state.frame).collect();
+	let mut geometries = Vec::new();
+	for frame in frames {
+		if let Ok(geom) = xconnection.get_geometry(frame)?.reply() {
+			geometries.push((geom.x, geom.y, geom.width as i16, geom.height as i16));
+		}
+	}
+	Ok(geometries)
+}
+
 const BORDER: u16 = 4;
 const TITLEBAR: u16 = 18;
+const FLASH: u64 = 500; //Milliseconds between flashes of an urgent taskbar button.
+const HIDEDELAY: u64 = 600; //Milliseconds after the pointer leaves an autohidden panel before it slides away.
+const APPLETPOLL: u64 = 5000; //Milliseconds between looking at the battery, network and the like again.
+const MONITORPOLL: u64 = 1000; //The system monitor's graph moves along once a second.
+const DRAGSTART: i16 = 4; //Pixels a link or window button has to move before it is being dragged rather than clicked.
+const LINKORDER: &str = "linkorder.txt";
 
 impl WindowManager {
     pub fn new() -> Self {
         WindowManager {
             windows: HashMap::new(),
-            frames: HashMap::new()
+            frames: HashMap::new(),
+            atoms: Atoms::default(),
         }
     }
 	
 	pub fn installexternalwindow(&mut self, window: Window, frame: Window, title: String, x: i16, y: i16, width: i16, height: i16, order: u8) {
-		let state = WindowState {window, frame, title, x, y, z: 0, width: width as i16, height: height as i16, map: 2, order,};
+		let state = WindowState {window, frame, title, x, y, z: 0, width: width as i16, height: height as i16, map: 2, order, urgent: false, icon: None, decorated: true, class: String::new()};
 		self.insertwindow(state);
 	}
 	
     pub fn focus<C: Connection>(&mut self, xconnection: &C, target: Window, panel: Window) -> Result<(), Box<dyn Error>> {
+        self.raise(xconnection, target, panel)?;
+        self.givefocus(xconnection, target)
+    }
+
+    pub fn raise<C: Connection>(&self, xconnection: &C, target: Window, panel: Window) -> Result<(), Box<dyn Error>> {
+		//Top of the pile, but still under the panel.
         xconnection.configure_window(target, &ConfigureWindowAux::default().sibling(panel).stack_mode(StackMode::BELOW))?;
+        Ok(())
+    }
+
+    pub fn givefocus<C: Connection>(&mut self, xconnection: &C, target: Window) -> Result<(), Box<dyn Error>> {
+		//Keyboard focus and the focused title bar, without touching the stacking order.
         for state in self.windows.values_mut() {
             if state.frame == target {
                 state.map = 2; //Focus
+				xconnection.set_input_focus(InputFocus::POINTER_ROOT, state.window, x11rb::CURRENT_TIME)?;
+				if state.urgent {
+					//It has the user's attention now.
+					state.urgent = false;
+					setnetwmstate(xconnection, &self.atoms, state.window, self.atoms._NET_WM_STATE_DEMANDS_ATTENTION, false);
+				}
             } else if state.map == 2 {
                 state.map = 3; //Old focused window is now just visible.
             }
@@ -131,6 +288,57 @@ impl WindowManager {
         Ok(())
     }
 
+    pub fn dropfocus<C: Connection>(&mut self, xconnection: &C) -> Result<(), Box<dyn Error>> {
+		//Nothing is focused, keystrokes go to whatever is under the pointer.
+        for state in self.windows.values_mut() {
+            if state.map == 2 {
+                state.map = 3;
+            }
+        }
+		xconnection.set_input_focus(InputFocus::POINTER_ROOT, u32::from(InputFocus::POINTER_ROOT), x11rb::CURRENT_TIME)?;
+        Ok(())
+    }
+
+    pub fn minimise<C: Connection>(&mut self, xconnection: &C, client: Window) -> Result<bool, Box<dyn Error>> {
+		//Hide the frame and client. The taskbar button stays, so it can be brought back. Returns false if there was nothing to hide.
+        if let Some(state) = self.windows.get_mut(&client).filter(|state| state.map != 0 && state.frame != 0) {
+            state.map = 0;
+            xconnection.unmap_window(state.frame)?;
+            xconnection.unmap_window(client)?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    pub fn restore<C: Connection>(&mut self, xconnection: &C, client: Window) -> Result<(), Box<dyn Error>> {
+		//Bring back a minimised window, without focusing it.
+        if let Some(state) = self.windows.get_mut(&client).filter(|state| state.map == 0) {
+            state.map = 3;
+            xconnection.map_window(client)?;
+            xconnection.map_window(state.frame)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn focused(&self) -> Option<&WindowState> {
+        self.windows.values().find(|state| state.map == 2)
+    }
+
+    pub fn setattention(&mut self, window: Window, urgent: bool) -> bool {
+		//Returns true if anything needs redrawing. The focused window already has the user's attention.
+        match self.windows.get_mut(&window) {
+            Some(state) if state.urgent != (urgent && state.map != 2) => {
+                state.urgent = !state.urgent;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn anyurgent(&self) -> bool {
+        self.windows.values().any(|state| state.urgent)
+    }
+
     pub fn getwindow(&self, window: &Window) -> Option<&WindowState> {
         self.windows.get(window)
     }
@@ -199,56 +407,75 @@ impl WindowManager {
         }
     }
 	
+	pub fn reorder(&mut self, clients: impl Iterator<Item = Window>) {
+		//Number the windows in taskbar order, after a button has been dragged somewhere else.
+		for (order, client) in clients.enumerate() {
+			if let Some(state) = self.windows.get_mut(&client) {
+				state.order = order as u8 + 1;
+			}
+		}
+	}
+	
 }
 
-const FASTDRAG: bool = true;
-
-
 fn main() -> Result<(), Box<dyn Error>> {
+	let options = match system::startup::parsearguments(std::env::args().skip(1)) {
+		Ok(options) => options,
+		Err(e) => {
+			eprintln!("{}\n{}", e, system::startup::USAGE);
+			std::process::exit(2);
+		}
+	};
+	
+	let (xconnection, screenid) = match x11rb::connect(options.display.as_deref()) {
+		Ok(connection) => connection,
+		Err(e) => {
+			eprintln!("Can't open display {}: {}", options.display.as_deref().unwrap_or("$DISPLAY"), e);
+			std::process::exit(1);
+		}
+	};
+	let root = xconnection.setup().roots[screenid].root;
+	let mut wm = WindowManager::new();
+	
     //let handle = thread::spawn(|| { //async this maybe, or remove
-        if let Err(e) = desktop() {
-            eprintln!("Error in panel: {}", e);
-        }
+        let outcome = desktop(&xconnection, screenid, &options, &mut wm);
     //});
     //handle.join().unwrap();
+	
+	//However we got here, give the clients back to the root so they don't die with their frames.
+	releasewindows(&xconnection, &wm, root);
+	drop(xconnection);
 
-    Ok(())
+	match outcome {
+		Ok(Exit::Quit) => Ok(()),
+		Ok(Exit::Restart) => Err(system::startup::restart()),
+		Err(e) => {
+            eprintln!("Error in panel: {}", e);
+            std::process::exit(1);
+		}
+	}
 }
 
-fn drawchunkyxoroutline<C: Connection>(xconnection: &C, window: u32, gc: u32, x: i16, y: i16, width: u16, height: u16) -> Result<(), Box<dyn Error>> {
+fn drawchunkyxoroutline<C: Connection>(xconnection: &C, window: u32, gc: u32, area: (i16, i16, i16, i16), x: i16, y: i16, width: u16, height: u16) -> Result<(), Box<dyn Error>> {
     const THICKNESS: u16 = 4;
 	
-    let screen = xconnection.setup().roots.first().unwrap();
-	
-    //let screenheight = screen.height_in_pixels as i16;
-	//TODO: This isn't needed, we already know the screen height. Just pass it in.
-    let panely = (screen.height_in_pixels as i16) - 28;
-
-    let mut rectangles = Vec::new();
-    if y < panely {
-        let visible_top_height = (panely - y).min(THICKNESS as i16).max(0) as u16;
-        if visible_top_height > 0 {
-            rectangles.push(Rectangle { x, y, width, height: visible_top_height });
-        }
-    }
-    let bottom_y = y + height as i16;
-    if bottom_y > panely {
-        let visible_bottom_height = (THICKNESS as i16 - (bottom_y - panely)).max(0) as u16;
-        if visible_bottom_height > 0 {
-            rectangles.push(Rectangle { x, y: panely - visible_bottom_height as i16, width, height: visible_bottom_height });
-        }
-    } else {
-		rectangles.push(Rectangle { x, y: bottom_y - THICKNESS as i16, width, height: THICKNESS });
-    }
-
-    let visible_left_height = ((height as i16 - 2 * THICKNESS as i16).min(panely - y - THICKNESS as i16)).max(0);
-	//eprintln!("visible_left_height: {}", visible_left_height);
-	//eprintln!("height: {}", height);
-	//eprintln!("y: {}", y);
-    if visible_left_height > 0 {
-        rectangles.push(Rectangle { x, y: y + THICKNESS as i16, width: THICKNESS, height: visible_left_height as u16 }); //Left Border
-        rectangles.push(Rectangle { x: x + width as i16 - THICKNESS as i16, y: y + THICKNESS as i16, width: THICKNESS, height: visible_left_height as u16 }); //Right Border
-    }
+	//Only inside the work area, so the outline never scribbles over the panel.
+	let (areax, areay, areawidth, areaheight) = area;
+	let clip = |rectangle: Rectangle| -> Option<Rectangle> {
+		let left = rectangle.x.max(areax);
+		let top = rectangle.y.max(areay);
+		let right = (rectangle.x + rectangle.width as i16).min(areax + areawidth);
+		let bottom = (rectangle.y + rectangle.height as i16).min(areay + areaheight);
+		(right > left && bottom > top).then_some(Rectangle { x: left, y: top, width: (right - left) as u16, height: (bottom - top) as u16 })
+	};
+
+	let sideheight = height.saturating_sub(2 * THICKNESS);
+    let rectangles: Vec<Rectangle> = [
+		Rectangle { x, y, width, height: THICKNESS }, //Top Border
+		Rectangle { x, y: y + height as i16 - THICKNESS as i16, width, height: THICKNESS }, //Bottom Border
+		Rectangle { x, y: y + THICKNESS as i16, width: THICKNESS, height: sideheight }, //Left Border
+		Rectangle { x: x + width as i16 - THICKNESS as i16, y: y + THICKNESS as i16, width: THICKNESS, height: sideheight }, //Right Border
+	].into_iter().filter_map(clip).collect();
     if !rectangles.is_empty() {
         xconnection.poly_fill_rectangle(window, gc, &rectangles)?;
     }
@@ -337,14 +564,14 @@ fn createwindow<C: Connection>(xconnection: &C, screen: &Screen, x: i16, y: i16,
     let window = createwindowbasics(xconnection, screen, x, y, width, height, title)?;
 	let frame = createborder(xconnection, screen, window, reswidth, resheight, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext)?;
     xconnection.map_window(window)?;
-    let state = WindowState {window, frame, title: String::from_utf8_lossy(title).to_string(), x, y, z: 0, width: width as i16, height: height as i16, map: 2, order: 0};
+    let state = WindowState {window, frame, title: String::from_utf8_lossy(title).to_string(), x, y, z: 0, width: width as i16, height: height as i16, map: 2, order: 0, urgent: false, icon: None, decorated: true, class: String::new()};
     windowmanager.insertwindow(state);
     Ok(window)
 }
 
 fn createframelesswindow<C: Connection>(xconnection: &C, screen: &Screen, x: i16, y: i16, width: u16, height: u16, title: &[u8], reswidth: i16, resheight: i16, gc_highlight: Gcontext, gc_lowlight: Gcontext, gc_highbackground: Gcontext, gc_lowbackground: Gcontext, gc_titlebar: Gcontext, gc_titlebartext: Gcontext, windowmanager: &mut WindowManager) -> Result<Window, Box<dyn Error>> {
     let window = createwindowbasics(xconnection, screen, x, y, width, height, title)?;
-    let state = WindowState { window, frame: 0, title: String::from_utf8_lossy(title).to_string(), x, y, z: 0, width: width as i16, height: height as i16, map: 2, order: 0 };
+    let state = WindowState { window, frame: 0, title: String::from_utf8_lossy(title).to_string(), x, y, z: 0, width: width as i16, height: height as i16, map: 2, order: 0, urgent: false, icon: None, decorated: false, class: String::new() };
     windowmanager.insertwindow(state);
     Ok(window)
 }
@@ -384,7 +611,8 @@ fn definemenuitems(clickmenuitems: &mut [[String; 3]; 16], clickmenusize: &mut u
 		("Help", "programs.png", "whatever"),
 		("Run", "programs.png", "whatever"),
 		("Divider", "", ""),
-		("Log Off", "programs.png", ""),
+		("Restart Tullamore", "programs.png", "restart"),
+		("Log Off", "programs.png", "logoff"),
 		("Shut Down", "programs.png", ""),
 	];
 
@@ -393,324 +621,94 @@ fn definemenuitems(clickmenuitems: &mut [[String; 3]; 16], clickmenusize: &mut u
 	}
 }
 
-fn definepanelitems(panelitems: &mut [[u8; 1]; 128], panelcoordinates: &mut [[i16; 2]; 128], panelwindows: &mut [[u32; 1]; 128], width: i16, icons: u8, panelindex: &mut [u8; 6]) {
-    //Type and Actions
-	// 0 = Start Button Ready
-	// 1 = Start Button Pressed
-	// 2 = Start Button Hover
-	// 3 = Start Button Engaged
-	// 4 = Start Button Reset
-	//10 = Task List
-	//20 = Quick Laucher
-	//30 = Icon Link
-	//31 = Icon Link Pressed
-	//32 = Icon Link Hover
-	//33 = Icon Link Engaged (Not Used)
-	//34 = Icon Link Reset??????
-	
-	//40 = Taskbar Button Ready
-	//41 = Taskbar Button Pressed
-	//42 = Taskbar Button Hover (Not Used)
-	//43 = Taskbar Button Engaged
-	//44 = Taskbar Reset??????? No????
-	//50 = Taskbar Button Arrows
-	//60 = Notification Area
-	
-	//[Type and Action, Window (if under 255, links to another array which will define icon and link)]
-	panelitems[0] = [1]; //Start button!
-	//[X, Width]
-	panelcoordinates[0] = [2, 54];
-	panelwindows[0] = [0];
-	panelindex[0] = 0;
-	
-	//The icons start at 60, and are 23 wide each.
-	
-	
-	//panelitems[4] = [40]; 
-	//panelcoordinates[4] = [120, 100];
-	//panelwindows[4] = [999];
-	
-	//panelitems[5] = [40];
-	//panelcoordinates[5] = [230, 100];
-	//panelwindows[5] = [999];
-	
-	
-	//panelitems[6] = [41];
-	//panelcoordinates[6] = [340, 100];
-	//panelwindows[6] = [999];
-	
-	let notification = ((icons as i16 *20) + 60) as i16; //took ages to work out icons was causing a buffer overflow
-
-	panelindex[1] = 0;
-	panelindex[2] = 0;
-	panelindex[3] = 0;
-	panelindex[4] = 0;
-	panelindex[5] = 1;
-	
-	//LINKSTART 1;
-	//LINKEND 2;
-	//WINDOWSTART 3;
-	//WINDOWEND 4;
-	//NOTIFICATIONSTART 5;
-	
-
-
-	//println!("Width ({}) Coordinates ({}) Notification ({}) Icons ({})", width, width - notification - 3, notification, icons);
-
-	panelitems[panelindex[5] as usize] = [60]; //Notification Area
-	panelcoordinates[panelindex[5] as usize] = [width - notification - 3, notification];
-	panelwindows[panelindex[5] as usize] = [0];
+fn definepanelicons(panelstate: &mut Panel) {
+	//Icons in the notification area, right to left.
+	panelstate.addtrayicon(PanelIcon { label: "Sound".to_string(), tooltip: "Sound Muted".to_string(), icon: "audio-volume-muted.png".to_string(), ..Default::default() });
+	panelstate.addtrayicon(PanelIcon { label: "Weather".to_string(), tooltip: "Snowing".to_string(), icon: "weather-snow.png".to_string(), ..Default::default() });
 }
 
-fn definepanelicons(link: &mut [[String; 4]; 32], icons:  &mut u8) {
-	//0-15 is for quick links in the task bar.
-	//16-31 is for icons in the tray.
-	
-	
-	link[0][0] = "".to_string(); //Program to open, nul means Tullamore is hardcoded with an action.
-	link[0][1] = "Start".to_string(); //Text
-	link[0][2] = "Click here to begin.".to_string(); //Tool Tip
-	link[0][3] = "computer.png".to_string(); //Icon
-	
-	link[1] = [String::from(""), "Web Browser".to_string(), "The Internet is for the weak!".to_string(), "audio-volume-muted.png".to_string()];
-	link[2] = [String::from(""), "Web Browser".to_string(), "The Internet is for the weak!".to_string(), "network-offline.png".to_string()];
-	link[3] = [String::from(""), "Web Browser".to_string(), "The Internet is for the weak!".to_string(), "weather-snow.png".to_string()];
-	
-	
-	link[31] = [String::from(""), "Sound".to_string(), "Sound Muted".to_string(), "audio-volume-muted.png".to_string()];
-	link[30] = [String::from(""), "Network".to_string(), "Network Offline".to_string(), "network-offline.png".to_string()];
-	link[29] = [String::from(""), "Weather".to_string(), "Snowing".to_string(), "weather-snow.png".to_string()];
-	
-
-	*icons = 3;
-}
-
-fn updatepanelindex(index: usize, panelindex: &mut [u8; 6]) {
-	//This deals with updating the indexes. It is a lot more complicated due to initialization.
-    if index == 2 {
-        panelindex[1] = 1;
-    }
-    match index {
-        2 => {
-            panelindex[2] += 1;
-            panelindex[3] = panelindex[2];
-            panelindex[4] = panelindex[2];
-            panelindex[5] = panelindex[2] + 1;
-        },
-        4 => {
-            if panelindex[3] == panelindex[2] {
-                panelindex[3] = panelindex[2] + 1;
-                panelindex[4] = panelindex[3];
-                panelindex[5] = panelindex[4] + 1;
-            } else {
-                panelindex[4] += 1;
-                panelindex[5] = panelindex[4] + 1;
-            }
-        },
-        _ => {}
-    }
-
-   // println!("panelindex contents: [0]={}, [1]={}, [2]={}, [3]={}, [4]={}, [5]={}", panelindex[0], panelindex[1], panelindex[2], panelindex[3], panelindex[4], panelindex[5]);
-}
-
-fn shiftpanelicon(mut index: usize, panelindex: &mut [u8; 6], panelitems: &mut [[u8; 1]; 128], panelcoordinates: &mut [[i16; 2]; 128], panelwindows: &mut [[u32; 1]; 128], panelicons: &mut [[String; 4]; 32]) {
-
-	//This function does not adjust panelindex. They should be updated before calling this function.
-
-    //Move everything from index to the right.
-
-    for i in (index..=panelindex[5] as usize).rev() {
-        panelitems[i + 1] = panelitems[i];
-		panelcoordinates[i + 1][0] = panelcoordinates[i][0];
-
-			
-        if panelitems[i][0] < 60 as u8 {
-            panelcoordinates[i + 1][0] = panelcoordinates[i][0] + 23;
-        } else {
-            panelcoordinates[i + 1][0] = panelcoordinates[i][0];
-        }
-		panelcoordinates[i + 1][1] = panelcoordinates[i][1];
-        panelwindows[i + 1] = panelwindows[i];
-    }
-	//Clear index.
-    panelitems[index] = [0];
-	if index > 0 {
-		//New coords are previous index's start location plus width.
-		if index == 1 {
-			//First icon next to the start button.
-			panelcoordinates[index][0] = 60;
-		} else {
-			panelcoordinates[index][0] = panelcoordinates[index - 1][0] + panelcoordinates[index - 1][1];
+fn updateapplet<C: Connection>(xconnection: &C, panel: Window, panelstate: &mut Panel, program: &str, icon: Option<PanelIcon>) -> Result<(), Box<dyn Error>> {
+	//Put a built-in applet's latest icon in the tray, or take it out if there is nothing to show.
+	//Coming or going reflows the panel, and clearing it gets an Expose to redraw the lot.
+	match (panelstate.applet(program), icon) {
+		(Some(n), Some(icon)) => {
+			if panelstate.trayicons[n].icon != icon.icon {
+				let (x, y) = panelstate.trayicon(n);
+				drawpng(xconnection, panel, &icon.icon, x, y, 16, 16, COLOURS[HIGHBACKGROUND_COLOUR])?;
+			}
+			panelstate.trayicons[n] = icon;
 		}
-		
+		(None, Some(icon)) => {
+			panelstate.addtrayicon(icon);
+			system::tray::place(xconnection, panelstate)?;
+			xconnection.clear_area(true, panel, 0, 0, 0, 0)?;
+		}
+		(Some(n), None) => {
+			panelstate.removetrayiconat(n);
+			system::tray::place(xconnection, panelstate)?;
+			xconnection.clear_area(true, panel, 0, 0, 0, 0)?;
+		}
+		(None, None) => {}
 	}
-    panelwindows[index] = [0];
-	
-
+	Ok(())
 }
 
-fn insertpanelicon(mut index: usize, panelindex: &mut [u8; 6], icon: u32, panelitems: &mut [[u8; 1]; 128], panelcoordinates: &mut [[i16; 2]; 128], panelwindows: &mut [[u32; 1]; 128], panelicons: &mut [[String; 4]; 32]) {
-	//let px = panelcoordinates[index - 1][0] + panelcoordinates[index - 1][1];
-	//
-	
-	updatepanelindex(2, panelindex); //Increment indexes from the end of the link area.
-	
-
-	if index == 0 {
-		index = 1;
-	}
-
-	shiftpanelicon(index, panelindex, panelitems, panelcoordinates, panelwindows, panelicons);
-	
-    panelitems[index] = [30];
-    panelcoordinates[index] = [panelcoordinates[index][0], 23];
-    panelwindows[index] = [icon];
-	
+fn addpanelicon(panelstate: &mut Panel, program: &str, label: &str, tooltip: &str, icon: &str) {
+	//Add to Quick Links! Programs starting with tullamore: are built in.
+	panelstate.addlink(PanelIcon { program: program.to_string(), label: label.to_string(), tooltip: tooltip.to_string(), icon: icon.to_string(), ..Default::default() });
 }
 
+fn showpanel<C: Connection>(xconnection: &C, panel: Window, panelstate: &Panel, width: i16, height: i16, shown: bool) -> Result<(), Box<dyn Error>> {
+	//Slide an autohidden panel out on top of everything, or tuck it away leaving a sliver at the edge of the screen.
+	let (x, y, _, _) = panelstate.geometry(width, height, shown);
+	xconnection.configure_window(panel, &ConfigureWindowAux::new().x(x as i32).y(y as i32).stack_mode(StackMode::ABOVE))?;
+	Ok(())
+}
 
-
-fn insertpanelwindow(panelindex: &mut [u8; 6], window: u32, panelitems: &mut [[u8; 1]; 128], panelcoordinates: &mut [[i16; 2]; 128], panelwindows: &mut [[u32; 1]; 128], panelicons: &mut [[String; 4]; 32]) {
-	//let px = panelcoordinates[index - 1][0] + panelcoordinates[index - 1][1];
-	//
-	
-    println!("Panel indices before insert:");
-    println!("panelindex[3] (windowstart): {}", panelindex[3]);
-    println!("panelindex[4] (last window): {}", panelindex[4]);
-    println!("panelindex[5] (notification): {}", panelindex[5]);
-	
-	updatepanelindex(4, panelindex); //Increment indexes from the end of the window area.
-	let mut index = panelindex[4] as usize;
-	
-	if index == 0 {
-		index = 1;
-	}
-	
-	for i in (panelindex[3] as usize..=(panelindex[4] as usize)) {
-		if panelitems[i][0] == 43 {
-			//Unfocus all window buttons on the panel.
-			panelitems[i][0] = 40;
-		}
-	}
-	
-    shiftpanelicon(index, panelindex, panelitems, panelcoordinates, panelwindows, panelicons);
-    panelitems[index][0] = 43;
-    panelwindows[index] = [window];
-
-    
-    //panelcoordinates[index] = [panelcoordinates[index][0] + 3, panelcoordinates[index][1]];
-	
-
-	windowbuttonlength(index, panelindex, panelitems, panelcoordinates);
-
+fn drawdropmarker<C: Connection>(xconnection: &C, panel: Window, gc_xorcheckers: Gcontext, (x, y, width, height): (i16, i16, i16, i16)) -> Result<(), Box<dyn Error>> {
+	//XOR, so drawing it again in the same place takes it away.
+	xconnection.poly_fill_rectangle(panel, gc_xorcheckers, &[Rectangle { x, y, width: width as u16, height: height as u16 }])?;
+	Ok(())
 }
 
-pub fn windowbuttonlength(index: usize, panelindex: &mut [u8; 6], panelitems: &mut [[u8; 1]; 128], panelcoordinates: &mut [[i16; 2]; 128]) {
-	let windowindexstart = panelindex[3] as usize;
-	
-	if (panelitems[windowindexstart] < [40] || panelitems[windowindexstart] > [45]) {
-		println!("GET OUT OF HERE!");
+fn loadlinkorder(panelstate: &mut Panel) {
+	//Put the links back the way they were last arranged. Links the file doesn't mention stay at the end, in the order they were added.
+	let Ok(contents) = std::fs::read_to_string(LINKORDER) else {
 		return;
-	}
-	
-	
-	const WINDOWBUTTONLENGTH: i16 = 160;
-	
-	let linksindexend = panelindex[2] as usize;
-	let windowindexend = panelindex[4] as usize;
-	let trayindexstart = panelindex[5] as usize;
-	let startx = panelcoordinates[linksindexend][0] + panelcoordinates[linksindexend][1] + 3; //panelcoordinates[windowindexstart][0];
-	let tray = panelcoordinates[trayindexstart][0];
-	
-	println!("{} - {} > ({} - {} + 1) * 160", tray, startx, windowindexend, windowindexstart);
-	
-	//Lets check to see if all window buttons will fit in the task bar with the default window button length.
-	if tray - startx > (windowindexend - windowindexstart + 1) as i16 * WINDOWBUTTONLENGTH {
-		println!("Full size! {}", WINDOWBUTTONLENGTH);
-		//We can make all the window buttons the default size.
-		for i in windowindexstart..=windowindexend {
-			if (panelitems[i] >= [40] && panelitems[i] < [45]) {
-				//This is very heavy! Much more heavy than the below code. It'll do for now.
-				panelcoordinates[i][1] = WINDOWBUTTONLENGTH;
-				panelcoordinates[i] = [panelcoordinates[i - 1][0] + panelcoordinates[i - 1][1] + 3, WINDOWBUTTONLENGTH];
-			} else {
-				println!("ERROR! ERROR! OUT OF BOUNDS! FIX ME! {}", 1);
-			}
+	};
+	let mut to = panelstate.links().start;
+	for label in contents.lines() {
+		if let Some(from) = (to..panelstate.links().end).find(|&i| panelstate.link(i).is_some_and(|link| link.label == label)) {
+			panelstate.moveelement(from, to);
+			to += 1;
 		}
-	} else {
-	
-		//Get the startx,
-		
-		
-		//let previousindex = index - 1;
-		//let previouswidth = if (panelitems[previousindex] >= [40] && panelitems[previousindex] < [45]) {
-		//	panelcoordinates[previousindex][1]
-		//} else {
-		//	WINDOWBUTTONLENGTH //Default Window Button Length
-		//};
-
-		//let currentx = panelcoordinates[index][0];
-		//if currentx + previouswidth < tray {
-		//	panelcoordinates[index] = [currentx + 3, previouswidth];
-		//} else {
-			//Window buttons go into the notification area. Let's calculate a new width for them.
-
-			//The window area ends where the notification area begins.
-			//It must be panelindex[5] because that is where the notification area starts, thus where the window buttons end.
-			//panelindex[4] would give the location of the start of the final window button.
-			
-			
-			
-			let count = trayindexstart as i16 - windowindexstart as i16;
-			println!("Windows: {}", count);
-			if count > 0 {
-				let width = ((tray - startx) / count) - 3;
-				let mut x = startx;
-				for i in windowindexstart..=windowindexend {
-					panelcoordinates[i] = [x, width];
-					x += width + 3;
-				} 
-			}
-		//}
 	}
 }
 
-fn addpanelicon(tray: u8, label: String, tooltip: String, icon: String, link: &mut [[String; 4]; 32], panelindex: &mut [u8; 6], panelitems: &mut [[u8; 1]; 128], panelcoordinates: &mut [[i16; 2]; 128], panelwindows: &mut [[u32; 1]; 128]) {
-	//Will do nothing if out of space. Probably want to return an error or something.
-    //Add to Quick Links!
-    for i in 0..15 {
-        if link[i][3].is_empty() {
-            link[i] = [String::from(""), label, tooltip, icon];
-            insertpanelicon(tray as usize, panelindex, i as u32, panelitems, panelcoordinates, panelwindows, link);
-            break;
-        }
-    }
-}
-
-fn addnotificationicon(label: String, tooltip: String, icon: String, link: &mut [[String; 4]; 32], icons: &mut u8) {
-	//Will do nothing if out of space. Probably want to return an error or something.
-    for i in (16..32).rev() {
-        if link[i][3].is_empty() {
-            link[i] = [String::from(""), label, tooltip, icon];
-            *icons += 1;
-            break;
-        }
-    }
+fn savelinkorder(panelstate: &Panel) {
+	//One label per line, left to right.
+	let mut contents = String::new();
+	for link in panelstate.links().filter_map(|i| panelstate.link(i)) {
+		contents.push_str(&link.label);
+		contents.push('\n');
+	}
+	if let Err(error) = std::fs::write(LINKORDER, contents) {
+		eprintln!("Couldn't save the link order to {}: {}", LINKORDER, error);
+	}
 }
 
-
-
-fn desktop() -> Result<(), Box<dyn Error>> {
+fn desktop(xconnection: &x11rb::rust_connection::RustConnection, screenid: usize, options: &system::startup::Options, wm: &mut WindowManager) -> Result<Exit, Box<dyn Error>> {
 	//let width = 640 as i16;
 	//let height = 480 as i16;
 	
-	let mut wm = WindowManager::new();
-	
 	//This code is freakin' awful. Really needs a refactor, but need a MVP before the refactor.
 
-    let (xconnection, screenid) = x11rb::connect(Some(":0"))?;
     let mut screen = &xconnection.setup().roots[screenid];
 	
+	//Become the window manager before touching anything, so we don't fight one that is already running.
+	let (manager, managerselection) = system::startup::acquiremanager(&xconnection, screen, screenid, options.replace)?;
+	wm.atoms = Atoms::new(xconnection)?.reply()?;
+	
 	let mut width = screen.width_in_pixels as i16;
 	let mut height = screen.height_in_pixels as i16;
 	
@@ -719,21 +717,13 @@ fn desktop() -> Result<(), Box<dyn Error>> {
 	
 
 	
-	//What links and notification tray icons do we have?
-	let mut panelicons: [[String; 4]; 32] = Default::default();
-	let mut icons = 0 as u8;
-	definepanelicons(&mut panelicons, &mut icons);
-	addnotificationicon("yo".to_string(), "yo".to_string(), "computer.png".to_string(), &mut panelicons, &mut icons,);
-	addnotificationicon("yo".to_string(), "yo".to_string(), "computer.png".to_string(), &mut panelicons, &mut icons,);
-
-
-	
-	//Panel Items
-	let mut panelitems: [[u8; 1]; 128] = [[0; 1]; 128];
-	let mut panelcoordinates: [[i16; 2]; 128] = [[0; 2]; 128];
-	let mut panelwindows: [[u32; 1]; 128] = [[0; 1]; 128];
-	let mut panelindex: [u8; 6] = [0; 6];
-	definepanelitems(&mut panelitems, &mut panelcoordinates, &mut panelwindows, width, icons, &mut panelindex);
+	//Panel Items, and what links and notification tray icons do we have?
+	let mut panelstate = Panel::new(SETTINGS.paneledge, panel::thickness(SETTINGS.paneledge, SETTINGS.panelsize), width, height);
+	let clockcharacters = system::clock::widest(SETTINGS.clock, SETTINGS.clockseconds, SETTINGS.clockdate);
+	panelstate.setclock(clockcharacters);
+	definepanelicons(&mut panelstate);
+	panelstate.addtrayicon(PanelIcon { label: "yo".to_string(), tooltip: "yo".to_string(), icon: "computer.png".to_string(), ..Default::default() });
+	panelstate.addtrayicon(PanelIcon { label: "yo".to_string(), tooltip: "yo".to_string(), icon: "computer.png".to_string(), ..Default::default() });
 	
 	//Click Menu
 	let mut clickmenusize = 0 as u8;
@@ -747,11 +737,23 @@ fn desktop() -> Result<(), Box<dyn Error>> {
 		println!("  Action: {}", clickmenuitems[i][2]);
 	}
 	
-	addpanelicon(panelindex[2], "yo".to_string(), "yo".to_string(), "computer.png".to_string(), &mut panelicons, &mut panelindex, &mut panelitems, &mut panelcoordinates, &mut panelwindows);
-	addpanelicon(panelindex[2], "yo".to_string(), "yo".to_string(), "computer.png".to_string(), &mut panelicons, &mut panelindex, &mut panelitems, &mut panelcoordinates, &mut panelwindows);
-	addpanelicon(panelindex[2], "yo".to_string(), "yo".to_string(), "computer.png".to_string(), &mut panelicons, &mut panelindex, &mut panelitems, &mut panelcoordinates, &mut panelwindows);
-	addpanelicon(panelindex[2], "Booker".to_string(), "Booker".to_string(), "computer.png".to_string(), &mut panelicons, &mut panelindex, &mut panelitems, &mut panelcoordinates, &mut panelwindows);
-	addpanelicon(panelindex[2], "Superbun".to_string(), "Superbun".to_string(), "computer.png".to_string(), &mut panelicons, &mut panelindex, &mut panelitems, &mut panelcoordinates, &mut panelwindows);
+	//Quick-launch links from settings.txt, or our own if it doesn't have any.
+	if SETTINGS.links.is_empty() {
+		addpanelicon(&mut panelstate, "tullamore:test1", "test1", "Test Window 1", "computer.png");
+		addpanelicon(&mut panelstate, "tullamore:test2", "test2", "Test Window 2", "computer.png");
+		addpanelicon(&mut panelstate, "tullamore:test3", "test3", "Test Window 3", "computer.png");
+		addpanelicon(&mut panelstate, "tullamore:booker", "Booker", "Booker", "computer.png");
+		addpanelicon(&mut panelstate, "tullamore:superbun", "Superbun", "Superbun", "computer.png");
+		addpanelicon(&mut panelstate, "tullamore:showdesktop", "Show Desktop", "Show Desktop", "user-desktop.png");
+	} else {
+		for link in &SETTINGS.links {
+			panelstate.addlink(link.clone());
+		}
+	}
+	loadlinkorder(&mut panelstate);
+	
+	//The system tray, for other programs' icons. Before redirecting the root, as it waits for an event.
+	let mut tray = system::tray::Tray::acquire(xconnection, screen, screenid, panelstate.vertical())?;
 
 	
 	
@@ -763,17 +765,15 @@ fn desktop() -> Result<(), Box<dyn Error>> {
 	
     //let window = xconnection.generate_id()?; 
 	let window = screen.root;
-    xconnection.create_window(0, window, screen.root, 0, 0, width as u16, height as u16, 0, WindowClass::INPUT_OUTPUT, screen.root_visual, &CreateWindowAux::default().background_pixel(COLOURS[WALLPAPER_COLOUR]),)?;
+	system::startup::redirectroot(&xconnection, window, COLOURS[WALLPAPER_COLOUR])?;
+	system::startup::grabmovebutton(&xconnection, window)?;
 	let panel = xconnection.generate_id()?;
 	
-	let panelheight = 28;
-	
-	
-	xconnection.create_window(0, panel, window, 0, height - panelheight as i16, width as u16, panelheight, 0, WindowClass::INPUT_OUTPUT, screen.root_visual, &CreateWindowAux::new().event_mask(EventMask::EXPOSURE | EventMask::BUTTON_PRESS | EventMask::BUTTON_RELEASE | EventMask::POINTER_MOTION),)?;
+	//With autohide, the panel starts tucked away. Crossing events tell us when to bring it out.
+	let (panelx, panely, panelwidth, panelheight) = panelstate.geometry(width, height, !SETTINGS.autohide);
+	xconnection.create_window(0, panel, window, panelx, panely, panelwidth as u16, panelheight as u16, 0, WindowClass::INPUT_OUTPUT, screen.root_visual, &CreateWindowAux::new().event_mask(EventMask::EXPOSURE | EventMask::BUTTON_PRESS | EventMask::BUTTON_RELEASE | EventMask::POINTER_MOTION | EventMask::ENTER_WINDOW | EventMask::LEAVE_WINDOW),)?;
 	
 
-	xconnection.change_window_attributes(window, &ChangeWindowAttributesAux::default().event_mask(EventMask::BUTTON_PRESS | EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY).background_pixel(COLOURS[WALLPAPER_COLOUR]).override_redirect(1),)?;
-
     //Graphic contexts...
     let gc_highbackground = xconnection.generate_id()?;
 	let gc_lowbackground = xconnection.generate_id()?;
@@ -781,6 +781,9 @@ fn desktop() -> Result<(), Box<dyn Error>> {
 	let gc_lowlight = xconnection.generate_id()?;	
 	let gc_titlebar = xconnection.generate_id()?;	
 	let gc_titlebartext = xconnection.generate_id()?;
+	let gc_attention = xconnection.generate_id()?; //Title bars and taskbar buttons of windows asking for attention.
+	let gc_attentiontext = xconnection.generate_id()?;
+	let gc_warning = xconnection.generate_id()?;
 	let gc_xor = xconnection.generate_id()?;
 	let gc_highcheckers = xconnection.generate_id()?;
 	let gc_lowcheckers = xconnection.generate_id()?;
@@ -804,6 +807,9 @@ fn desktop() -> Result<(), Box<dyn Error>> {
 	xconnection.create_gc(gc_highlight, window, &CreateGCAux::default().foreground(COLOURS[HIGHLIGHT_COLOUR]))?;
 	xconnection.create_gc(gc_titlebar, window, &CreateGCAux::default().foreground(COLOURS[TITLEBAR_COLOUR]).background(COLOURS[HIGHBACKGROUND_COLOUR]))?;
 	xconnection.create_gc(gc_titlebartext, window, &CreateGCAux::default().foreground(COLOURS[HIGHLIGHT_COLOUR]).background(COLOURS[TITLEBAR_COLOUR]))?;
+	xconnection.create_gc(gc_attention, window, &CreateGCAux::default().foreground(COLOURS[ATTENTION_COLOUR]))?;
+	xconnection.create_gc(gc_attentiontext, window, &CreateGCAux::default().foreground(COLOURS[HIGHLIGHT_COLOUR]).background(COLOURS[ATTENTION_COLOUR]))?;
+	xconnection.create_gc(gc_warning, window, &CreateGCAux::default().foreground(COLOURS[WARNING_COLOUR]))?;
 	xconnection.create_gc(gc_xor, window, &CreateGCAux::default().function(Some(GX::XOR)).foreground(0xFFFFFF).subwindow_mode(SubwindowMode::INCLUDE_INFERIORS),)?;
 
 	let gc_highcheckers = makepattern(&xconnection, window, COLOURS[HIGHLIGHT_COLOUR].unwrap(), COLOURS[HIGHBACKGROUND_COLOUR].unwrap())?;
@@ -815,12 +821,13 @@ fn desktop() -> Result<(), Box<dyn Error>> {
 
 	//test windows
 	
-
-
+	
+	//Adopt anything already on screen, such as the windows a previous Tullamore handed back when it restarted.
+	grabexternalwindows(&xconnection, &screen, wm, panel, width, height, &mut panelstate, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, gc_attention, gc_attentiontext, &mut poly_lowlight)?;
 
 	wm.fillblanks();
 
-	redrawframes(&xconnection, &wm, panel, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, &mut poly_lowlight);
+	redrawframes(&xconnection, &wm, panel, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, gc_attention, gc_attentiontext, &mut poly_lowlight);
 
 	
 	
@@ -838,8 +845,10 @@ fn desktop() -> Result<(), Box<dyn Error>> {
 
 
 
-	let clockheight = 20;
-	let (mut epoch, mut pminute, mut phour) = drawclock(&xconnection, panel, gc_lowlight, width, clockheight)?;
+	let (clockx, clocky) = panelstate.clock();
+	let timezone = system::clock::TimeZone::local();
+	let mut clocktext = clocknow(&timezone);
+	drawclock(&xconnection, panel, gc_lowlight, clockx, clocky, &clocktext, clockcharacters)?;
 
 	
 	
@@ -866,6 +875,67 @@ fn desktop() -> Result<(), Box<dyn Error>> {
 	let mut dragpressoffsety = 0;
 
 	let mut xordrawn: Option<(i16, i16, u16, u16)> = None;
+	
+	//Size of the frame being dragged, and the frames it can snap to. Both are collected when the drag starts.
+	let mut dragsize: (u16, u16) = (0, 0);
+	let mut snaptargets: Vec<(i16, i16, i16, i16)> = Vec::new();
+	
+	//An event we pulled off the queue early while skipping stale motion events.
+	let mut pending: Option<Event> = None;
+	
+	//Everything that has to happen later rather than in answer to an event. The loop sleeps until the soonest, or until there is something to read.
+	let mut timers = Timers::new();
+	
+	//Frame to raise once the pointer has rested on it long enough, for autoraise.
+	let mut raise: Option<Window> = None;
+	
+	//Taskbar buttons of urgent windows flash on and off while anything is urgent.
+	let mut flash = false;
+	
+	//Windows the Show Desktop button minimised, so the next press can bring them back.
+	let mut desktophidden: Vec<Window> = Vec::new();
+	
+	//Clicking a taskbar group opens the popup.
+	let mut popup: Option<Popup> = None;
+	//Group button whose popup was closed by the press, so the release doesn't open it again.
+	let mut popupclosed: Option<usize> = None;
+	
+	//Clicking the clock opens the calendar, which sets the alarms.
+	let mut calendar: Option<Calendar> = None;
+	let calendarpalette = Palette { gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext };
+	let mut alarms = Alarms::load(system::calendar::ALARMS);
+	timers.set(Timer::Clock, Some(clockwake(&alarms)));
+	
+	//Tooltip for whatever the pointer rests on in the panel. Before it shows, what it will be for and where along the panel.
+	let mut tooltip: Option<Tooltip> = None;
+	let mut tooltipat: Option<((usize, Option<usize>), i16)> = None;
+	
+	//Notification bubbles from other programs, if we could get on the session bus.
+	let mut notifications = system::notifications::Notifications::start();
+	
+	//The built-in tray applets, which take their first look straight away, and how far the battery warnings have got.
+	if SETTINGS.battery || SETTINGS.network {
+		timers.after(Timer::Applets, 0);
+	}
+	if SETTINGS.monitor {
+		timers.after(Timer::Monitor, 0);
+	}
+	let mut batterywarned = 0;
+	let mut monitor = applets::monitor::Monitor::new();
+	
+	//Tray applets that other programs drive, from the settings. They show whatever the settings give them until they say otherwise.
+	let mut external = applets::external::Applets::start(&SETTINGS.applets, options.display.as_deref());
+	for (program, icon) in external.as_ref().map(|external| external.icons()).unwrap_or_default() {
+		updateapplet(&xconnection, panel, &mut panelstate, &program, icon)?;
+	}
+	
+	//Whether the panel is out. It is always out unless autohide is on.
+	let mut panelshown = !SETTINGS.autohide;
+	
+	//Link or window button pressed on the panel as (index, how far along the panel it was pressed), in case it gets dragged somewhere else.
+	let mut paneldrag: Option<(usize, i16)> = None;
+	//Where it would be dropped while it is being dragged, as (index, marker position along the panel).
+	let mut dropat: Option<(usize, i16)> = None;
 
 
 
@@ -875,22 +945,20 @@ fn desktop() -> Result<(), Box<dyn Error>> {
 
 
 
-	let mut draw = 254 as u8;
+	let mut draw = Redraw::Panel;
 
 
 
-	let mut elementreset = 255 as u8; //
+	let mut elementreset: Option<usize> = None; //Panel element to pop back out when the pointer leaves it.
 	
-	let mut windowactive = 255 as u8;
-	let mut windowlast = 255 as u8;
+	let mut windowactive: Option<usize> = None;
+	let mut windowlast: Option<usize> = None;
 
-	let mut sleep = true;
-	
 	let mut game = 0 as Window;
 	
 	let mut system = 0 as Window;
 
-    loop {
+    let exit = loop {
 		
 		
 		
@@ -926,11 +994,139 @@ fn desktop() -> Result<(), Box<dyn Error>> {
 
 		
 		
-		let epoch = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
-		let cminute = ((epoch / 60) % 60) as u8;
-		updateclock(&xconnection, panel, gc_lowlight, phour, pminute, cminute, width, clockheight);
-		
-        let event = xconnection.wait_for_event()?;
+        let event = match pending.take() {
+			Some(event) => event,
+			None => match xconnection.poll_for_event()? {
+				Some(event) => event,
+				None => {
+					//Nothing from X. Sleep until it, the session bus or an applet has something for us, or the next timer is due.
+					xconnection.flush()?;
+					timers.set(Timer::Notifications, notifications.as_ref().and_then(|notifications| notifications.wake()));
+					timers.set(Timer::External, external.as_ref().and_then(|external| external.wake()));
+					let busfd = notifications.as_ref().map(|notifications| notifications.fd());
+					let appletfds = external.as_ref().map(|external| external.fds()).unwrap_or_default();
+					let fds: Vec<_> = [xconnection.stream().as_raw_fd()].into_iter().chain(busfd).chain(appletfds.iter().copied()).collect();
+					let ready = system::poll::wait(&fds, timers.next().map(|when| when.saturating_duration_since(Instant::now())))?;
+					let busready = busfd.is_some() && ready[1];
+					let appletsready = ready[fds.len() - appletfds.len()..].contains(&true);
+					let now = Instant::now();
+					let due = timers.due(now);
+					if due.contains(&Timer::Clock) {
+						//Only draw the clock when what it says changes.
+						let text = clocknow(&timezone);
+						if text != clocktext {
+							clocktext = text;
+							let (clockx, clocky) = panelstate.clock();
+							drawclock(&xconnection, panel, gc_lowlight, clockx, clocky, &clocktext, clockcharacters)?;
+						}
+						let due = alarms.due(epochnow());
+						if !due.is_empty() {
+							let texts: Vec<String> = due.iter().map(|&at| {
+								let local = timezone.localtime(at);
+								format!("{}, {}", system::clock::text(local, SETTINGS.clock, false, false), system::clock::fulldate(local))
+							}).collect();
+							//A notification bubble each if we are showing them. Otherwise they pop up over the tray like the battery warnings.
+							let mut posted = false;
+							if let Some(bubbles) = notifications.as_mut() {
+								match texts.iter().try_for_each(|text| bubbles.post(&xconnection, screen, &panelstate, "Alarm", text)) {
+									Ok(()) => posted = true,
+									Err(e) => {
+										eprintln!("Notifications have stopped: {}", e);
+										bubbles.closeall(&xconnection);
+										notifications = None;
+									}
+								}
+							}
+							if !posted {
+								if let Some(open) = popup.take() {
+									open.close(&xconnection);
+								}
+								let items = texts.into_iter().map(|text| PopupItem { label: format!("Alarm: {}", text), icon: None, value: 0 }).collect();
+								popup = Some(Popup::open(&xconnection, screen, &panelstate, panelstate.notification(), items)?);
+							}
+							//Any the calendar was showing are gone now.
+							if let Some(open) = calendar.as_ref() {
+								open.draw(&xconnection, &alarms, &timezone, &calendarpalette)?;
+							}
+						}
+						timers.set(Timer::Clock, Some(clockwake(&alarms)));
+					}
+					if let Some(frame) = raise.filter(|_| due.contains(&Timer::Raise)) {
+						raise = None;
+						wm.raise(&xconnection, frame, panel)?;
+					}
+					if due.contains(&Timer::Flash) {
+						flash = !flash;
+						if wm.anyurgent() {
+							timers.after(Timer::Flash, FLASH);
+						}
+						for i in panelstate.windows() {
+							//Only buttons with something urgent behind them, which for a group means any of its windows.
+							if panelstate.members(i).iter().filter_map(|&member| panelstate.window(member)).any(|client| wm.getwindow(&client).is_some_and(|state| state.urgent)) {
+								drawtaskbutton(&xconnection, panel, wm, i, &panelstate, flash, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_highcheckers, gc_attention, gc_attentiontext, &mut poly_lowlight);
+							}
+						}
+						updategui(&xconnection, panel, gc_lowlight, &mut poly_lowlight);
+					}
+					if let Some((target, at)) = tooltipat.filter(|_| due.contains(&Timer::Tooltip)) {
+						tooltipat = None;
+						if let Some(text) = tooltiptext(wm, &panelstate, &timezone, target) {
+							tooltip = Some(Tooltip::open(&xconnection, screen, &panelstate, target, at, text)?);
+						}
+					}
+					if let Some(bubbles) = notifications.as_mut().filter(|_| busready || due.contains(&Timer::Notifications)) {
+						if let Err(e) = bubbles.service(&xconnection, screen, &panelstate, now) {
+							eprintln!("Notifications have stopped: {}", e);
+							bubbles.closeall(&xconnection);
+							notifications = None;
+						}
+					}
+					if due.contains(&Timer::Applets) {
+						timers.after(Timer::Applets, APPLETPOLL);
+						if SETTINGS.network {
+							updateapplet(&xconnection, panel, &mut panelstate, applets::network::PROGRAM, Some(applets::network::trayicon(&applets::network::sample(&SETTINGS.sysfsroot, &SETTINGS.procroot))))?;
+						}
+						if SETTINGS.battery {
+							let battery = applets::battery::sample(&SETTINGS.sysfsroot);
+							updateapplet(&xconnection, panel, &mut panelstate, applets::battery::PROGRAM, battery.map(|reading| reading.trayicon(SETTINGS.batterylow, SETTINGS.batterycritical)))?;
+							if let Some(warning) = battery.and_then(|reading| reading.warning(SETTINGS.batterylow, SETTINGS.batterycritical, &mut batterywarned)) {
+								//Pops up over the tray, like a taskbar group's list.
+								if let Some(open) = popup.take() {
+									open.close(&xconnection);
+								}
+								popup = Some(Popup::open(&xconnection, screen, &panelstate, panelstate.notification(), vec![PopupItem { label: warning, icon: None, value: 0 }])?);
+							}
+						}
+					}
+					if let Some(external) = external.as_mut().filter(|_| appletsready || due.contains(&Timer::External)) {
+						for (program, icon) in external.service(now) {
+							updateapplet(&xconnection, panel, &mut panelstate, &program, icon)?;
+						}
+					}
+					if due.contains(&Timer::Monitor) {
+						//Straight onto the panel rather than a full redraw every second.
+						timers.after(Timer::Monitor, MONITORPOLL);
+						monitor.sample(&SETTINGS.procroot, &SETTINGS.sysfsroot);
+						updateapplet(&xconnection, panel, &mut panelstate, applets::monitor::PROGRAM, monitor.trayicon())?;
+						if let (Some(n), Some(reading)) = (panelstate.applet(applets::monitor::PROGRAM), monitor.reading) {
+							let (x, y) = panelstate.trayicon(n);
+							monitor.draw(&xconnection, panel, x, y, reading.hot(SETTINGS.cpuwarning, SETTINGS.memorywarning, SETTINGS.temperaturewarning), gc_lowlight, gc_highlight, gc_warning)?;
+						}
+					}
+					if due.contains(&Timer::Hide) {
+						if popup.is_some() || calendar.is_some() || system != 0 || paneldrag.is_some() {
+							//Still busy with the panel. Try again later.
+							timers.after(Timer::Hide, HIDEDELAY);
+						} else {
+							panelshown = false;
+							showpanel(&xconnection, panel, &panelstate, width, height, false)?;
+						}
+					}
+					xconnection.flush()?;
+					continue;
+				}
+			},
+		};
         match event {
 				
 				
@@ -940,45 +1136,80 @@ fn desktop() -> Result<(), Box<dyn Error>> {
 			Event::MapRequest(target) => {
 				println!("MapRequest Target: {:?}", target.window);
 				
-				let external = if let Ok(attributes) = xconnection.get_window_attributes(target.window)?.reply() {
-					!attributes.override_redirect && !wm.getwindow(&target.window).is_some() && !wm.frames.contains_key(&target.window)
-				} else {
-					false
-				};
-				if external {
-					if let Ok(geom) = xconnection.get_geometry(target.window)?.reply() {
-						println!("Window Geometry Details:");
-						println!("  x: {}, y: {}", geom.x, geom.y);
-						println!("  width: {}, height: {}", geom.width as i16, geom.height as i16);
-						println!("  border_width: {}", geom.border_width);
-						println!("  depth: {}", geom.depth);
-						println!("  root: {:?}", geom.root);
-						
-						let title = xconnection.get_property(false, target.window, AtomEnum::WM_NAME, AtomEnum::STRING, 0, u32::MAX)?.reply().ok().and_then(|prop| String::from_utf8(prop.value).ok()).unwrap_or_else(|| String::from("Unknown"));
-						println!("  title: {}", title);
-						
-						wm.installexternalwindow(target.window, target.window, title, geom.x, geom.y, geom.width as i16, geom.height as i16, 0);
-						insertpanelwindow(&mut panelindex, target.window, &mut panelitems, &mut panelcoordinates, &mut panelwindows, &mut panelicons);
-							if let Ok(frame) = createwmborder(&xconnection, &screen, &wm, target.window, geom.width, geom.height, width, height, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, &mut poly_lowlight) {
-							if let Some(state) = wm.windows.get_mut(&target.window) {
-								state.frame = frame;
-							}
-							wm.frames.insert(frame, target.window);
-						}
-						draw = 40;
-					}
+				if managewindow(&xconnection, &screen, wm, panel, target.window, width, height, &mut panelstate, true, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, gc_attention, gc_attentiontext, &mut poly_lowlight)? {
+					draw = Redraw::Windows;
 				}
 			}
 				
 			
 			
 			
+			Event::EnterNotify(enter) => {
+				if enter.event == panel {
+					//Touching the panel's edge of the screen brings an autohidden panel out.
+					if SETTINGS.autohide {
+						timers.set(Timer::Hide, None);
+						if !panelshown {
+							panelshown = true;
+							showpanel(&xconnection, panel, &panelstate, width, height, true)?;
+						}
+					}
+				//Pointer focus. Grab crossings (like dragging a window) don't count.
+				} else if SETTINGS.focusmode != FocusMode::Click && enter.mode == NotifyMode::NORMAL && moving.is_none() {
+					if let Some((frame, client)) = wm.getframe(&enter.event).filter(|state| state.frame == enter.event).map(|state| (state.frame, state.window)) {
+						if wm.focused().map(|state| state.frame) != Some(frame) {
+							wm.givefocus(&xconnection, frame)?;
+							activatepanelwindow(client, &mut panelstate, &mut windowactive, &mut windowlast);
+							redrawframes(&xconnection, wm, panel, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, gc_attention, gc_attentiontext, &mut poly_lowlight);
+							draw = Redraw::Windows;
+						}
+						raise = SETTINGS.autoraise.map(|_| frame);
+						timers.set(Timer::Raise, SETTINGS.autoraise.map(|delay| Instant::now() + Duration::from_millis(delay)));
+					} else if enter.event == screen.root && enter.detail == NotifyDetail::INFERIOR {
+						//Back on the desktop. Sloppy focus leaves the focus where it was.
+						raise = None;
+						timers.set(Timer::Raise, None);
+						if SETTINGS.focusmode == FocusMode::Mouse && wm.focused().is_some() {
+							wm.dropfocus(&xconnection)?;
+							activatepanelwindow(0, &mut panelstate, &mut windowactive, &mut windowlast);
+							redrawframes(&xconnection, wm, panel, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, gc_attention, gc_attentiontext, &mut poly_lowlight);
+							draw = Redraw::Windows;
+						}
+					}
+				}
+			}
+			Event::LeaveNotify(leave) => {
+				//Going into a docked tray icon is still in the panel.
+				if leave.event == panel && leave.detail != NotifyDetail::INFERIOR {
+					tooltipat = None;
+					timers.set(Timer::Tooltip, None);
+					if let Some(open) = tooltip.take() {
+						open.close(&xconnection);
+					}
+					if SETTINGS.autohide && leave.mode == NotifyMode::NORMAL {
+						timers.after(Timer::Hide, HIDEDELAY);
+					}
+				}
+			}
 			Event::ConfigureRequest(target) => {
 				let aux = ConfigureWindowAux::from_configure_request(&target);
 				xconnection.configure_window(target.window, &aux)?;
 				xconnection.flush()?;
 			}
 			Event::DestroyNotify(destroy) => {
+				if let Some(tray) = tray.as_mut() {
+					if tray.undock(&xconnection, &mut panelstate, destroy.window)? {
+						draw = Redraw::Panel;
+					}
+				}
+			}
+			Event::ReparentNotify(reparent) => {
+				//A docked tray icon taken by another tray.
+				if let Some(tray) = tray.as_mut().filter(|_| reparent.parent != panel) {
+					if tray.undock(&xconnection, &mut panelstate, reparent.window)? {
+						draw = Redraw::Panel;
+					}
+				}
 			}
 			
 				
@@ -987,38 +1218,68 @@ fn desktop() -> Result<(), Box<dyn Error>> {
 			Event::MotionNotify(motion) => {
 					
 				if motion.event == panel {
+					let position = panelstate.along(motion.event_x, motion.event_y);
+					//Moving onto something else starts its tooltip over. Straight away if one was already showing.
+					let target = panelstate.hit(motion.event_x, motion.event_y).filter(|_| paneldrag.is_none()).map(|index| (index, if index == panelstate.notification() { panelstate.trayiconat(motion.event_x, motion.event_y) } else { None }));
+					if target != tooltip.as_ref().map(|open| open.target).or(tooltipat.map(|(target, _)| target)) {
+						let delay = if tooltip.is_some() { 0 } else { system::tooltip::DELAY };
+						if let Some(open) = tooltip.take() {
+							open.close(&xconnection);
+						}
+						tooltipat = target.map(|target| (target, position));
+						timers.set(Timer::Tooltip, target.map(|_| Instant::now() + Duration::from_millis(delay)));
+					}
+					if let Some((from, _)) = paneldrag.filter(|&(_, start)| dropat.is_some() || (position - start).abs() > DRAGSTART) {
+						//Dragging a link or window button along the panel. Show where it would land.
+						let (to, markerx) = panelstate.dropslot(position, from);
+						if dropat.map(|(_, oldx)| oldx) != Some(markerx) {
+							if let Some((_, oldx)) = dropat {
+								drawdropmarker(&xconnection, panel, gc_xorcheckers, panelstate.marker(oldx))?;
+							}
+							drawdropmarker(&xconnection, panel, gc_xorcheckers, panelstate.marker(markerx))?;
+						}
+						dropat = Some((to, markerx));
 					//Hover over panel links.
-					if let Some((index, elementtype)) = checkelement(motion.event_x, motion.event_y, &panelindex, &panelcoordinates) {
-						if elementtype == 30 {
-							//Hovering over a link!
-							if let Some(state) = updateelement(index, elementtype, 2, &mut panelitems, &panelindex) {
-								draw = state;
-								elementreset = index as u8;
+					} else if let Some(index) = panelstate.hit(motion.event_x, motion.event_y) {
+						match panelstate.elements[index].kind {
+							PanelElement::Link(_) => {
+								//Hovering over a link!
+								if let Some(state) = panelstate.setstate(index, ElementState::Hover) {
+									draw = state;
+									elementreset = Some(index);
+								}
 							}
-						} else if elementtype == 40 {
-						} else {
-							resetpanelelement(&mut draw, &mut elementreset, &mut panelitems, &panelindex);
+							PanelElement::Window(_) => {}
+							_ => resetpanelelement(&mut draw, &mut elementreset, &mut panelstate),
 						}
 					} else {
-						resetpanelelement(&mut draw, &mut elementreset, &mut panelitems, &panelindex);
+						resetpanelelement(&mut draw, &mut elementreset, &mut panelstate);
 					}
 				} else {
 					//Dragging windows n stuff
 					if let (Some(win), Some((startx, starty)), Some((winx, winy))) = (moving, drag, origin) {
+						//Only the newest pointer position matters, so skip over any motion already queued behind this one.
+						let mut motion = motion;
+						while let Some(next) = xconnection.poll_for_event()? {
+							if let Event::MotionNotify(newer) = next {
+								motion = newer;
+							} else {
+								pending = Some(next);
+								break;
+							}
+						}
+						
 						let dx = motion.root_x - startx;
 						let dy = motion.root_y - starty;
-						let newx = winx + dx;
-						let newy = winy + dy;
-							if FASTDRAG {
+						let (newx, newy) = snapframe(winx + dx, winy + dy, dragsize.0 as i16, dragsize.1 as i16, panelstate.workarea(width, height, SETTINGS.autohide), &snaptargets, SETTINGS.snapdistance);
+						if SETTINGS.dragmode == DragMode::Outline {
 							if let Some((lx, ly, lw, lh)) = xordrawn {
 								//Draw XOR Outline to overwrite previous one.
-								drawchunkyxoroutline(&xconnection, screen.root, gc_xorcheckers, lx, ly, lw, lh)?;
-							}
-								//Draw outline.
-							if let Ok(geom) = xconnection.get_geometry(win)?.reply() {
-								drawchunkyxoroutline(&xconnection, screen.root, gc_xorcheckers, newx, newy, geom.width, geom.height, )?;
-								xordrawn = Some((newx, newy, geom.width, geom.height));
+								drawchunkyxoroutline(&xconnection, screen.root, gc_xorcheckers, panelstate.workarea(width, height, SETTINGS.autohide), lx, ly, lw, lh)?;
 							}
+							//Draw outline.
+							drawchunkyxoroutline(&xconnection, screen.root, gc_xorcheckers, panelstate.workarea(width, height, SETTINGS.autohide), newx, newy, dragsize.0, dragsize.1)?;
+							xordrawn = Some((newx, newy, dragsize.0, dragsize.1));
 						} else {
 							xconnection.configure_window(
 								win,
@@ -1032,64 +1293,114 @@ fn desktop() -> Result<(), Box<dyn Error>> {
 			
 				//For releasing the window! Redraw the frame!
 			Event::ButtonRelease(release) => {
-				if FASTDRAG {
+				if SETTINGS.dragmode == DragMode::Outline {
 					//Draw XOR Outline to overwrite old one.
-					if let Some((lx, ly, lw, lh)) = xordrawn { drawchunkyxoroutline(&xconnection, screen.root, gc_xorcheckers, lx, ly, lw, lh)?; xordrawn = None; }
+					if let Some((lx, ly, lw, lh)) = xordrawn { drawchunkyxoroutline(&xconnection, screen.root, gc_xorcheckers, panelstate.workarea(width, height, SETTINGS.autohide), lx, ly, lw, lh)?; xordrawn = None; }
 				}
 				
 					
 				//Releasing the mouse click.
-				if release.event == panel {
-					if let Some((index, elementtype)) = checkelement(release.event_x, release.event_y, &panelindex, &panelcoordinates) {
-						if elementtype == 0 {
+				if let Some(((from, _), (to, _))) = paneldrag.take().zip(dropat.take()) {
+					//Dropped a dragged link or window button. It moves instead of being clicked.
+					if let Some(client) = panelstate.window(from) {
+						panelstate.elements[from].state = if wm.getwindow(&client).is_some_and(|state| state.map == 2) { ElementState::Engaged } else { ElementState::Ready };
+						panelstate.moveelement(from, to);
+						wm.reorder(panelstate.windows().filter_map(|i| panelstate.window(i)));
+					} else {
+						panelstate.elements[from].state = ElementState::Ready;
+						panelstate.moveelement(from, to);
+						savelinkorder(&panelstate);
+					}
+					elementreset = None;
+					draw = Redraw::Panel;
+				} else if release.event == panel {
+					if let Some(index) = panelstate.hit(release.event_x, release.event_y) {
+						let element = panelstate.elements[index];
+						if element.kind == PanelElement::Click {
 							if system == 0 {
-								system = system::clicker::startprogram(&xconnection, &screen, panel, &clickmenuitems, &clickmenusize, &width, &height, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, &mut wm, &mut poly_lowlight, &mut poly_index, &mut poly_windoworcolour);
+								system = system::clicker::startprogram(&xconnection, &screen, panel, &panelstate, &clickmenuitems, &clickmenusize, &width, &height, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, gc_attention, gc_attentiontext, wm, &mut poly_lowlight, &mut poly_index, &mut poly_windoworcolour);
 							}
 							println!("Click Button Clicked");
-							//draw = 1;
-						} else if elementtype == 30 {
+							//draw = Redraw::Click(ElementState::Pressed);
+						} else if let PanelElement::Link(link) = element.kind {
 							//Link released! Open link item.
-							if let Some(state) = updateelement(index, elementtype, 0, &mut panelitems, &panelindex) {
+							if let Some(state) = panelstate.setstate(index, ElementState::Ready) {
 								draw = state;
-								if index > 0 {
-									if index == 1 {
-										let test = createwindow(&xconnection, &screen, 100, 100, 200, 100, b"test1", width, height, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, &mut wm)?;
-										insertpanelwindow(&mut panelindex, test, &mut panelitems, &mut panelcoordinates, &mut panelwindows, &mut panelicons);
-										focuswindow(&mut wm, &xconnection, panel, test, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, &mut poly_lowlight, &mut poly_index, &mut poly_windoworcolour);
-									} else if index == 2 {
-										let test = createwindow(&xconnection, &screen, 100, 100, 300, 200, b"test2", width, height, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, &mut wm)?;
-										insertpanelwindow(&mut panelindex, test, &mut panelitems, &mut panelcoordinates, &mut panelwindows, &mut panelicons);
-										focuswindow(&mut wm, &xconnection, panel, test, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, &mut poly_lowlight, &mut poly_index, &mut poly_windoworcolour);
-									} else if index == 3 {
-										let test = createwindow(&xconnection, &screen, 100, 100, 100, 100, b"test3", width, height, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, &mut wm)?;
-										insertpanelwindow(&mut panelindex, test, &mut panelitems, &mut panelcoordinates, &mut panelwindows, &mut panelicons);
-										focuswindow(&mut wm, &xconnection, panel, test, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, &mut poly_lowlight, &mut poly_index, &mut poly_windoworcolour);
-									} else if index == 4 {
-										draw = programs::booker::startprogram(&xconnection, &screen, panel, width, height, &mut panelindex, &mut panelitems, &mut panelcoordinates, &mut panelwindows, &mut panelicons, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, &mut wm, &mut poly_lowlight, &mut poly_index, &mut poly_windoworcolour);
-									} else if index == 5 {
-										game = games::superbun::startprogram(&xconnection, &screen, panel, width, height, &mut panelindex, &mut panelitems, &mut panelcoordinates, &mut panelwindows, &mut panelicons, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, &mut wm, &mut poly_lowlight, &mut poly_index, &mut poly_windoworcolour);
+								let program = panelstate.links[link].program.clone();
+								match system::launch::internal(&program) {
+									Some("showdesktop") => showdesktop(wm, &xconnection, panel, &mut desktophidden, &mut panelstate, &mut windowactive, &mut windowlast)?,
+									Some("booker") => draw = programs::booker::startprogram(&xconnection, &screen, panel, width, height, &mut panelstate, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, gc_attention, gc_attentiontext, wm, &mut poly_lowlight, &mut poly_index, &mut poly_windoworcolour),
+									Some("superbun") => game = games::superbun::startprogram(&xconnection, &screen, panel, width, height, &mut panelstate, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, gc_attention, gc_attentiontext, wm, &mut poly_lowlight, &mut poly_index, &mut poly_windoworcolour),
+									Some(title @ ("test1" | "test2" | "test3")) => {
+										//Windows of our own, for trying out the window manager.
+										let (testwidth, testheight) = match title { "test1" => (200, 100), "test2" => (300, 200), _ => (100, 100) };
+										let test = createwindow(&xconnection, &screen, 100, 100, testwidth, testheight, title.as_bytes(), width, height, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, wm)?;
+										panelstate.insertwindow(test);
+										focuswindow(wm, &xconnection, panel, test, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, gc_attention, gc_attentiontext, &mut poly_lowlight, &mut poly_index, &mut poly_windoworcolour)?;
+									}
+									Some(other) => eprintln!("No such link as {}{}", system::launch::INTERNAL, other),
+									None => {
+										//Run the command for that link!
+										if let Err(e) = system::launch::spawn(&panelstate.links[link], options.display.as_deref()) {
+											eprintln!("Can't run {}: {}", program, e);
+										}
 									}
-									draw = 40; 
+								}
+								draw = Redraw::Windows; 
 
-									
-									
-									redrawframes(&xconnection, &wm, panel, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, &mut poly_lowlight);
+								
+								
+								redrawframes(&xconnection, &wm, panel, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, gc_attention, gc_attentiontext, &mut poly_lowlight);
+							}
+						} else if let Some(client) = panelstate.window(index) {
+							let members = panelstate.members(index);
+							if members.len() > 1 {
+								//A group button lists its windows instead, unless this click just closed that list.
+								panelstate.elements[index].state = if wm.getwindow(&client).is_some_and(|state| state.map == 2) { ElementState::Engaged } else { ElementState::Ready };
+								draw = Redraw::Windows;
+								if popupclosed != Some(index) {
+									let items = members.iter().filter_map(|&member| panelstate.window(member)).filter_map(|member| wm.getwindow(&member)).map(|state| PopupItem { label: state.title.clone(), icon: state.icon.clone(), value: state.window }).collect();
+									popup = Some(Popup::open(&xconnection, screen, &panelstate, index, items)?);
 								}
-								//Run the command for that link!
+							} else if wm.getwindow(&client).is_some_and(|state| state.map == 2) {
+								//Clicking the active window's button minimises it, and the focus moves on.
+								if wm.minimise(&xconnection, client)? {
+									panelstate.elements[index].state = ElementState::Ready;
+									swapwindow(wm, &xconnection, panel, &mut panelstate, &mut windowactive, &mut windowlast, index, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, gc_attention, gc_attentiontext, &mut poly_lowlight, &mut poly_index, &mut poly_windoworcolour);
+									draw = Redraw::Windows;
+								}
+							} else {
+								//Focus the selected window, bringing it back first if it was minimised.
+								windowlast = windowactive;
+								windowactive = Some(index);
+								panelstate.elements[index].state = ElementState::Engaged;
+								focuswindow(wm, &xconnection, panel, client, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, gc_attention, gc_attentiontext, &mut poly_lowlight, &mut poly_index, &mut poly_windoworcolour);
+								draw = Redraw::Windows;
+							}
+						} else if let PanelElement::Arrow(forward) = element.kind {
+							//Page through the taskbar buttons that don't fit.
+							if panelstate.page(forward) {
+								draw = Redraw::Windows;
+							}
+						} else if element.kind == PanelElement::Notification && popupclosed != Some(index) {
+							//The network icon lists the interfaces, unless this click just closed that list. Applets from the settings hear about the click themselves.
+							let program = panelstate.trayiconat(release.event_x, release.event_y).map(|n| panelstate.trayicons[n].program.clone());
+							if let (Some(program), Some(external)) = (program.as_deref(), external.as_mut()) {
+								external.click(program, release.detail);
+							}
+							if program.as_deref() == Some(applets::network::PROGRAM) {
+								let mut items: Vec<PopupItem> = applets::network::sample(&SETTINGS.sysfsroot, &SETTINGS.procroot).iter().map(|interface| PopupItem { label: interface.describe(), icon: None, value: 0 }).collect();
+								if items.is_empty() {
+									items.push(PopupItem { label: "No network interfaces".to_string(), icon: None, value: 0 });
+								}
+								popup = Some(Popup::open(&xconnection, screen, &panelstate, index, items)?);
 							}
-						} else if elementtype == 40 {
-							//Focus the selected window.
-							windowlast = windowactive;
-							windowactive = index as u8;
-							panelitems[index][0] = 43;
-							let client = panelwindows[index][0] as Window;
-							if let Some(state) = wm.getwindow(&client) { focuswindow(&mut wm, &xconnection, panel, client, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, &mut poly_lowlight, &mut poly_index, &mut poly_windoworcolour); draw = elementtype; }
 						}
 					}
 				} else {
 				
 				
-					if let Some(state) = wm.getframe(&release.event) {
+					if let Some(state) = wm.getframe(&release.event).filter(|state| state.decorated) {
 						//Press buttons on Window Frame.
 						let x = release.event_x;
 						let y = release.event_y;
@@ -1099,22 +1410,17 @@ fn desktop() -> Result<(), Box<dyn Error>> {
 							let edge = state.width + (2 * BORDER as i16);
 							if x >= edge - 54 && x < edge - 38 { //Min button!
 								if let Some(client) = wm.frames.get(&release.event) {
-									if let Some(index) = panelwindows.iter().position(|w| w[0] == *client) {
+									if let Some(index) = panelstate.windowindex(*client) {
 									
 									
 									
-										if let Some(target) = wm.windows.get_mut(client) {
-											target.map = 0;
-											println!("Window.Map {} {}", target.map, index);
-											xconnection.unmap_window(release.event)?;
-											xconnection.unmap_window(*client)?;
+										if wm.minimise(&xconnection, *client)? {
+											panelstate.elements[index].state = ElementState::Ready;
+											draw = Redraw::Windows;
 											
-											panelitems[index][0] = 40;
-											draw = 40;
+											println!("windowactive {:?} windowlast {:?}", windowactive, windowlast);
 											
-											println!("windowactive {} windowlast {}", windowactive, windowlast);
-											
-											swapwindow(&mut wm, &xconnection, panel, &panelwindows, &mut panelitems, &mut windowactive, &mut windowlast, index, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, &mut poly_lowlight, &mut poly_index, &mut poly_windoworcolour);
+											swapwindow(wm, &xconnection, panel, &mut panelstate, &mut windowactive, &mut windowlast, index, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, gc_attention, gc_attentiontext, &mut poly_lowlight, &mut poly_index, &mut poly_windoworcolour);
 											
 										}
 										
@@ -1140,18 +1446,11 @@ fn desktop() -> Result<(), Box<dyn Error>> {
 
 									
 									
-									for i in (panelindex[3] as usize..=(panelindex[4] as usize)) {
-										println!("looking in {} for {}, found {}", i, client, panelwindows[i][0]);
-										if panelwindows[i][0] == client {
-											println!("found it {}", client);
-											//index = i;
-											swapwindow(&mut wm, &xconnection, panel, &panelwindows, &mut panelitems, &mut windowactive, &mut windowlast, i, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, &mut poly_lowlight, &mut poly_index, &mut poly_windoworcolour);
-											//Remove window from the panel.
-											//FIX, TODO, FIXME, this part isn't removing the correct index?
-											removepanelwindow(&mut panelindex, client, &mut panelitems, &mut panelcoordinates, &mut panelwindows, &mut panelicons);
-											draw = 40;
-											break;
-										}
+									if let Some(i) = panelstate.windowindex(client) {
+										swapwindow(wm, &xconnection, panel, &mut panelstate, &mut windowactive, &mut windowlast, i, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, gc_attention, gc_attentiontext, &mut poly_lowlight, &mut poly_index, &mut poly_windoworcolour);
+										//Remove window from the panel.
+										panelstate.removewindow(client);
+										draw = Redraw::Windows;
 									}
 								}
 							}
@@ -1165,8 +1464,7 @@ fn desktop() -> Result<(), Box<dyn Error>> {
 						//Move window.
 						if let (Some((finalx, finaly)), Some((targetx, targety))) = (drag, origin) {
 							let pointer = xconnection.query_pointer(screen.root)?.reply()?;
-							let newx = targetx + (pointer.root_x - finalx);
-							let newy = targety + (pointer.root_y - finaly);
+							let (newx, newy) = snapframe(targetx + (pointer.root_x - finalx), targety + (pointer.root_y - finaly), dragsize.0 as i16, dragsize.1 as i16, panelstate.workarea(width, height, SETTINGS.autohide), &snaptargets, SETTINGS.snapdistance);
 							
 							//Update xy on X server.
 							xconnection.configure_window(target, &ConfigureWindowAux::new().x(newx as i32).y(newy as i32))?;
@@ -1181,15 +1479,75 @@ fn desktop() -> Result<(), Box<dyn Error>> {
 					moving = None;
 					drag = None;
 					origin = None;
+					snaptargets.clear();
 						//Redraw window frames.
-					redrawframes(&xconnection, &wm, panel, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, &mut poly_lowlight);
+					redrawframes(&xconnection, &wm, panel, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, gc_attention, gc_attentiontext, &mut poly_lowlight);
 					}
 					xconnection.flush()?;
 			}
 			
 			//We need a lot more comments here!
             Event::ButtonPress(press) => {
+				if system != 0 && press.event == system {
+					//Picked something from the Clicker menu.
+					if let Some(item) = system::clicker::clickmenuitem(&clickmenuitems, &clickmenusize, press.event_y) {
+						match clickmenuitems[item][2].as_str() {
+							"restart" => break Exit::Restart,
+							"logoff" => break Exit::Quit,
+							_ => eprintln!("Clicker item '{}' doesn't do anything yet.", clickmenuitems[item][0]),
+						}
+					}
+					draw = Redraw::Click(ElementState::Ready);
+				}
+				
+				tooltipat = None;
+				timers.set(Timer::Tooltip, None);
+				if let Some(open) = tooltip.take() {
+					open.close(&xconnection);
+				}
+				
+				//Clicking a notification bubble runs its action and takes it down.
+				if let Some(bubbles) = notifications.as_mut() {
+					if let Err(e) = bubbles.click(&xconnection, screen, &panelstate, press.event, press.event_x, press.event_y) {
+						eprintln!("Notifications have stopped: {}", e);
+						bubbles.closeall(&xconnection);
+						notifications = None;
+					}
+				}
+				
+				popupclosed = None;
+				if let Some(open) = popup.take() {
+					//Any click closes the popup. One on an item also brings that window up.
+					if press.event == open.window {
+						//Only a taskbar group's list has windows in it.
+						if let Some(client) = open.item(press.event_y).filter(|_| panelstate.window(open.owner).is_some()).map(|item| item.value as Window) {
+							activatepanelwindow(client, &mut panelstate, &mut windowactive, &mut windowlast);
+							focuswindow(wm, &xconnection, panel, client, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, gc_attention, gc_attentiontext, &mut poly_lowlight, &mut poly_index, &mut poly_windoworcolour)?;
+							draw = Redraw::Windows;
+						}
+					} else {
+						popupclosed = Some(open.owner);
+					}
+					open.close(&xconnection);
+				}
+				
+				//Clicks in the calendar work it. Anywhere else closes it, and a click on the clock doesn't open it again.
+				let mut calendarclosed = false;
+				if let Some(open) = calendar.as_mut().filter(|open| press.event == open.window) {
+					if open.click(press.event_x, press.event_y, press.detail, &mut alarms, &timezone, epochnow()) {
+						//An alarm may have come or gone.
+						timers.set(Timer::Clock, Some(clockwake(&alarms)));
+						open.draw(&xconnection, &alarms, &timezone, &calendarpalette)?;
+					}
+				} else if let Some(open) = calendar.take() {
+					open.close(&xconnection);
+					calendarclosed = true;
+				}
 				
+				//Undecorated windows grab clicks so we can focus them. Let the click carry on to the window.
+				if wm.getwindow(&press.event).is_some_and(|state| !state.decorated) {
+					xconnection.allow_events(Allow::REPLAY_POINTER, press.time)?;
+				}
 				
 				if press.detail == 1 { //Left mouse button pressed.
 				
@@ -1200,7 +1558,7 @@ fn desktop() -> Result<(), Box<dyn Error>> {
 				
 				
 					//For the outline drag only.
-					if FASTDRAG {
+					if SETTINGS.dragmode == DragMode::Outline {
 						if let Event::ButtonPress(ButtonPressEvent { root_x, root_y, .. }) = event {
 							let pointer = xconnection.query_pointer(screen.root)?.reply()?;
 							dragpressoffsetx = pointer.root_x - root_x as i16;
@@ -1210,51 +1568,33 @@ fn desktop() -> Result<(), Box<dyn Error>> {
 				
 				
 					
-					let target = press.event;
-					if let Some((frame, statex, statey)) = wm.getframe(&target).map(|state| (state.frame, state.x, state.y)).or_else(|| wm.windows.values().find(|state| state.window == target).map(|state| (state.frame, state.x, state.y))) {
-						sleep = false;
+					//Alt and the left button is grabbed on the root, so the window it landed on is the child.
+					let altdrag = press.event == screen.root && press.child != NONE && press.state.contains(KeyButMask::MOD1);
+					let target = if altdrag { press.child } else { press.event };
+					if let Some((frame, statex, statey, decorated)) = wm.getframe(&target).map(|state| (state.frame, state.x, state.y, state.decorated)).or_else(|| wm.windows.values().find(|state| state.window == target).map(|state| (state.frame, state.x, state.y, state.decorated))) {
 						wm.focus(&xconnection, frame, panel)?;
 						
 						let mut paneltarget = target;
 						
 						
-						if target == frame && press.event_y < TITLEBAR as i16 {
+						if altdrag || (target == frame && decorated && press.event_y < TITLEBAR as i16) {
 							moving = Some(frame);
 							drag = Some((press.root_x, press.root_y));
 							origin = Some((statex, statey));
 							
+							//Grab the geometry of this frame and everything it could snap to now, rather than on every motion event.
+							if let Ok(geom) = xconnection.get_geometry(frame)?.reply() {
+								dragsize = (geom.width, geom.height);
+								origin = Some((geom.x, geom.y));
+							}
+							snaptargets = framegeometries(&xconnection, wm, frame)?;
+							
 							paneltarget = wm.windows.values().find(|s| s.frame == target).map_or(target, |state| state.window);
 						}
 						
-						for i in (panelindex[3] as usize)..=(panelindex[4] as usize) {
-							//TODO: remove this!
-							//I don't like this loop.
-							if panelwindows[i][0] == paneltarget as u32 {
-								windowactive = i as u8;
-								panelitems[i][0] = 43;
-							} else {
-								if panelwindows[i][0] == 43 {
-									windowlast = i as u8;
-								}
-								panelitems[i][0] = 40;
-							}
-						}	
+						activatepanelwindow(paneltarget, &mut panelstate, &mut windowactive, &mut windowlast);
 						
-						let redraw: Vec<(Window, Window, i16, i16)> = wm.windows.values().filter(|state| state.map == 2 || state.map == 3).map(|state| {
-						let fwidth = state.width + (2 * BORDER as i16);
-						let fheight = state.height + (2 * BORDER as i16) + (TITLEBAR as i16); (state.frame, state.window, fwidth, fheight)}).collect();
-						for (frame, client, width, height) in redraw {
-							
-
-							
-
-							
-							
-							
-							if frame != panel {
-								updateborder(&xconnection, frame, client, width, height, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground,gc_titlebar, gc_titlebartext, &mut poly_lowlight);
-							}
-						}
+						redrawshownframes(&xconnection, wm, panel, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, gc_attention, gc_attentiontext, &mut poly_lowlight);
 						//Draw the taskbar window buttons.
 						//drawpanelwindows(&xconnection, panel, 61, width - panelcoordinates[trayindex as usize][1] - 67, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_highcheckers, &wm)?;
 
@@ -1265,9 +1605,7 @@ fn desktop() -> Result<(), Box<dyn Error>> {
 						
 
 
-						draw = 40;
-					} else {
-						sleep = true;
+						draw = Redraw::Windows;
 					}
 				
 				
@@ -1277,30 +1615,34 @@ fn desktop() -> Result<(), Box<dyn Error>> {
 				if press.event == panel {
 
 					
-					if let Some((index, elementtype)) = checkelement(press.event_x, press.event_y, &panelindex, &panelcoordinates) {
-						//println!("elementtype: {}", elementtype);
-						if elementtype == 0 {
-							//Pressing Click button!
-							draw = 1;
-							//elementreset = 0 will reset the button, but we aren't going to use it here. We will use it on the Clicker function.
-							elementreset = 0;
-						} else if elementtype == 30 {
-							if let Some(state) = updateelement(index, elementtype, 1, &mut panelitems, &panelindex) {
-								draw = state;
-								//println!("Button pressed!");
+					if let Some(index) = panelstate.hit(press.event_x, press.event_y) {
+						match panelstate.elements[index].kind {
+							PanelElement::Click => {
+								//Pressing Click button!
+								draw = Redraw::Click(ElementState::Pressed);
+								//elementreset = Some(0) will reset the button, but we aren't going to use it here. We will use it on the Clicker function.
+								elementreset = Some(index);
 							}
-						} else if elementtype == 40 {
-							//Window button pressed!
-							//TODO: FIX ME FIX THIS!
-							
-							if let Some(state) = updateelement(index, elementtype, 1, &mut panelitems, &panelindex) {
-								println!("Press window button, state: {}", state);
-								draw = 41;
-								elementreset = index as u8;
+							PanelElement::Link(_) => {
+								paneldrag = Some((index, panelstate.along(press.event_x, press.event_y)));
+								if let Some(state) = panelstate.setstate(index, ElementState::Pressed) {
+									draw = state;
+									//println!("Button pressed!");
+								}
+							}
+							PanelElement::Window(_) => {
+								//Window button pressed!
+								paneldrag = Some((index, panelstate.along(press.event_x, press.event_y)));
+								
+								if let Some(state) = panelstate.setstate(index, ElementState::Pressed) {
+									draw = state;
+									elementreset = Some(index);
+								}
+							}
+							PanelElement::Arrow(_) | PanelElement::Notification => {
+								resetpanelelement(&mut draw, &mut elementreset, &mut panelstate);
+								
 							}
-						} else {
-							resetpanelelement(&mut draw, &mut elementreset, &mut panelitems, &panelindex);
-							
 						}
 					}
 					
@@ -1308,41 +1650,89 @@ fn desktop() -> Result<(), Box<dyn Error>> {
 				
 				
 				
-					if press.event_y > height - 20 && press.event_y < height - 5 {
-						if press.event_x > panelcoordinates[panelindex[5] as usize][0] {
-							if press.event_x < width - 60 {
-								let clickedicon = icons as i16 - 1 - (press.event_x - (width - panelcoordinates[panelindex[5] as usize][1])) / 20;
-								if clickedicon >= 0 { 
-									println!("Icon index: {}", clickedicon + 1);
-								}
-							} else {
-								println!("Clock clicked!");
-							}
-						} else {
-							println!("Middle of panel clicked!");
-							let linkx = panelcoordinates[panelindex[1] as usize][0];
-							if press.event_x > linkx {
-							
-								let windowx = panelcoordinates[panelindex[3] as usize][0];
-								if press.event_x > windowx {
-									let link = clickelement(press.event_x - windowx, panelcoordinates[panelindex[3] as usize][1]);
-									println!("Window Button Clicked: {}", link);
-								} else if press.event_x < panelcoordinates[panelindex[3] as usize][0] {
-									let link = clickelement(press.event_x - linkx, panelcoordinates[panelindex[1] as usize][1]);
-									println!("Link Clicked: {}", link);
-								}
-								
-								
-								
-								
-							
-							}
+					if press.event == panel && panelstate.hit(press.event_x, press.event_y) == Some(panelstate.notification()) {
+						//Tray icons answer when the button comes up. The rest of the notification area is the clock.
+						if panelstate.trayiconat(press.event_x, press.event_y).is_none() && !calendarclosed {
+							calendar = Some(Calendar::open(&xconnection, screen, &panelstate, timezone.localtime(epochnow()))?);
 						}
 					} else {
 						println!("Mouse button pressed at ({}, {}) with button: {}", press.event_x, press.event_y, press.detail);
 					}
 				}
 			}
+			Event::PropertyNotify(notify) => {
+				if let Some(tray) = tray.as_mut().filter(|tray| notify.atom == tray.atoms._XEMBED_INFO && tray.docked(&panelstate, notify.window)) {
+					if tray.update(&xconnection, &mut panelstate, notify.window)? {
+						draw = Redraw::Panel;
+					}
+				}
+				if (notify.atom == u32::from(AtomEnum::WM_HINTS) || notify.atom == wm.atoms._NET_WM_ICON) && wm.getwindow(&notify.window).is_some() {
+					//New icon, or an icon pixmap in the new WM_HINTS.
+					let icon = windowicon(&xconnection, &wm.atoms, notify.window);
+					if let Some(state) = wm.windows.get_mut(&notify.window).filter(|state| state.icon != icon) {
+						state.icon = icon;
+						redrawframes(&xconnection, wm, panel, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, gc_attention, gc_attentiontext, &mut poly_lowlight);
+						draw = Redraw::Windows;
+					}
+				}
+				if (notify.atom == u32::from(AtomEnum::WM_HINTS) || notify.atom == wm.atoms._NET_WM_STATE) && wm.getwindow(&notify.window).is_some() {
+					let urgent = windowurgent(&xconnection, &wm.atoms, notify.window);
+					if wm.setattention(notify.window, urgent) {
+						if urgent && !timers.pending(Timer::Flash) {
+							//Start lit, then flash.
+							flash = true;
+							timers.after(Timer::Flash, FLASH);
+						}
+						redrawframes(&xconnection, wm, panel, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, gc_attention, gc_attentiontext, &mut poly_lowlight);
+						draw = Redraw::Windows;
+					}
+				}
+			}
+			Event::ClientMessage(message) => {
+				if let Some(tray) = tray.as_mut() {
+					if let Some(icon) = tray.dockrequest(&message) {
+						tray.dock(&xconnection, panel, &mut panelstate, icon)?;
+						draw = Redraw::Panel;
+					}
+				}
+				if message.type_ == wm.atoms._NET_WM_STATE {
+					if let Some(state) = wm.getwindow(&message.window) {
+						//data[0] is remove (0), add (1) or toggle (2). data[1] and data[2] are the states to change.
+						let data = message.data.as_data32();
+						let demands = wm.atoms._NET_WM_STATE_DEMANDS_ATTENTION;
+						if data[1] == demands || data[2] == demands {
+							let on = match data[0] {
+								0 => false,
+								1 => true,
+								_ => !window::netwmstate(&xconnection, &wm.atoms, message.window).contains(&demands),
+							};
+							//The focused window already has the user's attention. The PropertyNotify from this takes care of the rest.
+							setnetwmstate(&xconnection, &wm.atoms, message.window, demands, on && state.map != 2);
+						}
+					}
+				}
+			}
+			Event::SelectionClear(clear) => {
+				if clear.selection == managerselection && clear.owner == manager {
+					//Another window manager was started with --replace. Bow out.
+					println!("Another window manager has taken over, exiting.");
+					break Exit::Quit;
+				}
+			}
+			Event::Expose(expose) => {
+				if let Some(open) = popup.as_ref().filter(|open| open.window == expose.window && expose.count == 0) {
+					open.draw(&xconnection, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground);
+				} else if let Some(open) = calendar.as_ref().filter(|open| open.window == expose.window && expose.count == 0) {
+					open.draw(&xconnection, &alarms, &timezone, &calendarpalette)?;
+				} else if let Some(open) = tooltip.as_ref().filter(|open| open.window == expose.window && expose.count == 0) {
+					open.draw(&xconnection, gc_lowlight);
+				} else if let Some(bubbles) = notifications.as_ref().filter(|bubbles| expose.count == 0 && bubbles.bubbles.iter().any(|bubble| bubble.window == expose.window)) {
+					bubbles.draw(&xconnection, expose.window, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground)?;
+				} else if expose.window == panel && expose.count == 0 {
+					//Coming back out after autohide, for one.
+					draw = Redraw::Panel;
+				}
+			}
             Event::Error(_) => println!("bug bug"), _ => (),
         }
 		
@@ -1350,21 +1740,16 @@ fn desktop() -> Result<(), Box<dyn Error>> {
 
 		
 		
-		if draw < 255 {
+		if draw != Redraw::Nothing {
 			//What do we need to redraw?
-			if draw < 5 {
-				// 0 = Start Button Ready
-				// 1 = Start Button Pressed
-				// 2 = Start Button Hover
-				// 3 = Start Button Engaged
-				// 4 = Start Button Reset
-				if draw == 0 {
+			if let Redraw::Click(state) = draw {
+				if state == ElementState::Ready {
 					//Change bottom left Click button to ready.
 					//Endprogram function deleted the Clicker menu and redraws the Click button to unpressed.
-					system::clicker::endprogram(&mut wm, &xconnection, panel, system, panelcoordinates, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, &mut poly_lowlight);
+					system::clicker::endprogram(wm, &xconnection, panel, system, &panelstate, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, &mut poly_lowlight);
 					system = 0;
 				}
-				if draw == 1 {
+				if state == ElementState::Pressed {
 					//workinghere
 					//drawclickbutton(&xconnection, panel, panelcoordinates[i][0], 4, panelcoordinates[i][1], 21, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground)?;
 					//Click button is changed to pressed.
@@ -1378,213 +1763,148 @@ fn desktop() -> Result<(), Box<dyn Error>> {
 
 
 				}
-			} else if draw == 30 {
-				let tempstart = panelcoordinates[panelindex[1] as usize][0] - 1;
-				let tempwidth = panelcoordinates[panelindex[3] as usize][0] - tempstart;
-				xconnection.poly_fill_rectangle(panel, gc_highbackground, &[Rectangle { x: tempstart, y: 3, width: tempwidth as u16, height: panelheight }])?;
-				for i in panelindex[1] as usize..panelindex[3] as usize {
-					match panelitems[i][0] {
-						30 => {
-							drawpng(&xconnection, panel, &panelicons[panelwindows[i][0] as usize][3], panelcoordinates[i][0] + 3, 7, 16, 16, COLOURS[HIGHBACKGROUND_COLOUR])?;
+			} else if draw == Redraw::Links {
+				let links = panelstate.links();
+				if !links.is_empty() {
+					let last = panelstate.elements[links.end - 1];
+					let (x, y, w, h) = panelstate.band(panelstate.elements[links.start].x - 1, last.x + last.width);
+					xconnection.poly_fill_rectangle(panel, gc_highbackground, &[Rectangle { x, y, width: w as u16, height: h as u16 }])?;
+				}
+				for i in links {
+					let (x, y, w, h) = panelstate.rect(i);
+					let icon = panelstate.link(i).map_or("", |link| link.icon.as_str());
+					match panelstate.elements[i].state {
+						ElementState::Ready => {
+							drawpng(&xconnection, panel, icon, x + 3, y + 3, 16, 16, COLOURS[HIGHBACKGROUND_COLOUR])?;
 						}
-						32 => {
-							drawpng(&xconnection, panel, &panelicons[panelwindows[i][0] as usize][3], panelcoordinates[i][0] + 3, 7, 16, 16, COLOURS[HIGHBACKGROUND_COLOUR])?;
-							drawdepressedframe(&xconnection, panel, panelcoordinates[i][0] + panelcoordinates[i][1] - 2, 4, panelcoordinates[i][1] - 1, 21, gc_lowbackground, gc_highlight)?;
+						ElementState::Hover => {
+							drawpng(&xconnection, panel, icon, x + 3, y + 3, 16, 16, COLOURS[HIGHBACKGROUND_COLOUR])?;
+							drawdepressedframe(&xconnection, panel, x + w - 2, y, w - 1, h, gc_lowbackground, gc_highlight)?;
 						}
 						_ => {
 							break;
 						}
 					}
 				}
-			} else if draw > 30 && draw < 35 {
+			} else if draw == Redraw::LinkStates {
 				//println!("draw function > 30 < 35 entered {}", draw);
-				for i in (panelindex[1] as usize..=(panelindex[2] as usize)) {
-					//println!("i {} in panelindex[1] {} and panelindex[2] {} panelitems[i][0] {}", i, panelindex[1], panelindex[2], panelitems[i][0]);
-					match panelitems[i][0] {
-						34 => {
-							drawdepressedframe(&xconnection, panel, panelcoordinates[i][0] + panelcoordinates[i][1] - 2, 4, panelcoordinates[i][1] - 1, 21, gc_highbackground, gc_highbackground)?;
-							panelitems[i][0] = 30;
+				for i in panelstate.links() {
+					let (x, y, w, h) = panelstate.rect(i);
+					match panelstate.elements[i].state {
+						ElementState::Reset => {
+							drawdepressedframe(&xconnection, panel, x + w - 2, y, w - 1, h, gc_highbackground, gc_highbackground)?;
+							panelstate.elements[i].state = ElementState::Ready;
 						}
-						31 => {
+						ElementState::Pressed => {
 							//Press link!
-							drawdepressedframe(&xconnection, panel, panelcoordinates[i][0] + panelcoordinates[i][1] - 2, 4, panelcoordinates[i][1] - 1, 21, gc_highlight, gc_lowbackground)?;
+							drawdepressedframe(&xconnection, panel, x + w - 2, y, w - 1, h, gc_highlight, gc_lowbackground)?;
 						}
-						32 => {
-							//println!("Hover over link! {}", draw);
+						ElementState::Hover => {
 							//Hover over link!
-							drawdepressedframe(&xconnection, panel, panelcoordinates[i][0] + panelcoordinates[i][1] - 2, 4, panelcoordinates[i][1] - 1, 21, gc_lowbackground, gc_highlight)?;
+							drawdepressedframe(&xconnection, panel, x + w - 2, y, w - 1, h, gc_lowbackground, gc_highlight)?;
 						}
 						_ => {
 
 						}
 					}
 				}
-			} else if draw > 40 && draw < 45 {
+			} else if draw == Redraw::WindowStates {
 				//Redraw the tray windows only!
-				for i in (panelindex[3] as usize..=(panelindex[4] as usize)) {
-					println!("i {} panelitems[i][0] {}", i, panelitems[i][0]);
-					match panelitems[i][0] {
-						40 => {
-							//startx: i16, starty: i16, framewidth: i16, frameheight: i1
-							//drawwindowbuttons(&xconnection, panel, panelwindows[i][0], panelcoordinates[i][0], panelcoordinates[i][1], &wm, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_highcheckers)?;
-						}
-						41 => {
-							//startx: i16, starty: i16, framewidth: i16, frameheight: i1
-							drawdepressedbumpyframe(&xconnection, panel, panelcoordinates[i][0], 4, panelcoordinates[i][1], 21, gc_highlight, gc_highbackground, gc_lowbackground, 0, &mut poly_lowlight);
+				for i in panelstate.windows() {
+					let (x, y, w, h) = panelstate.rect(i);
+					match panelstate.elements[i].state {
+						ElementState::Pressed => {
+							drawdepressedbumpyframe(&xconnection, panel, x, y, w, h, gc_highlight, gc_highbackground, gc_lowbackground, 0, &mut poly_lowlight);
 						}
-						43 => {
-							//Engaged
-							//startx: i16, starty: i16, framewidth: i16, frameheight: i1
-							//drawwindowbuttons(&xconnection, panel, panelwindows[i][0], panelcoordinates[i][0], panelcoordinates[i][1], &wm, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_highcheckers)?;
-						}
-						44 => {
-							println!("so far so good");
-							panelitems[i][0] = 40;
-							
-							if let Some(state) = wm.getwindow(&(panelwindows[i][0] as Window)) {
-								drawwindowbuttons(&xconnection, panel, panelitems[i][0], &state.title, panelcoordinates[i][0], panelcoordinates[i][1], gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_highcheckers, &mut poly_lowlight);
-							} else {
-								println!("ERROR 44: No window state for panel window {} at index {}.",	panelwindows[i][0], i);
-							}
-							
-							
-							
-
+						ElementState::Reset => {
+							panelstate.elements[i].state = ElementState::Ready;
+							drawtaskbutton(&xconnection, panel, wm, i, &panelstate, flash, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_highcheckers, gc_attention, gc_attentiontext, &mut poly_lowlight);
 						}
 						_ => {
 							
 						}
 					}
 				}
-			} else if draw == 40 {
-				
-				//panelcoordinates[i] = [panelcoordinates[i - 1][0] + panelcoordinates[i - 1][1] + 3, WINDOWBUTTONLENGTH];
+			} else if draw == Redraw::Windows {
 				
-				let linkindexend = panelindex[2] as usize;
-				let windowindexstart = panelindex[3] as usize;
-				let trayindexstart = panelindex[5];
-				let trayindexstartx = panelcoordinates[trayindexstart as usize][0];
-				
-				if linkindexend == windowindexstart {
-					//No window buttons!
-					let linkindexendx = panelcoordinates[linkindexend][0] as usize;
-					let linkindexendlength = panelcoordinates[linkindexend][1] as usize;
-					let startx = (linkindexendx + linkindexendlength) as i16;
-					
-					xconnection.poly_fill_rectangle(panel, gc_highbackground, &[Rectangle { x: startx as i16, y: 3, width: (trayindexstartx - startx) as u16, height: panelheight }])?; //Draw panel background.
-				} else {
+				panelstate.groupwindows(SETTINGS.grouping, |client| wm.getwindow(&client).map_or(String::new(), |state| state.class.clone()));
 				
-					let windowindexend = panelindex[4] as usize;
-					
-					
-					
-					//Redraw the tray windows only!
-					let tempstart = panelcoordinates[windowindexstart][0];
-					
-					let tempwidth = trayindexstartx - tempstart;
-					xconnection.poly_fill_rectangle(panel, gc_highbackground, &[Rectangle { x: tempstart, y: 3, width: tempwidth as u16, height: panelheight }])?; //Draw panel background.
-					for i in (windowindexstart..=windowindexend) {
-						println!("i {} panelitems[i][0] {}", i, panelitems[i][0]);
-						if panelitems[i][0] >= 40 && panelitems[i][0] < 45 {
-							//startx: i16, starty: i16, framewidth: i16, frameheight: i1
-							if let Some(state) = wm.getwindow(&(panelwindows[i][0] as Window)) {
-								drawwindowbuttons(&xconnection, panel, panelitems[i][0], &state.title, panelcoordinates[i][0], panelcoordinates[i][1], gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_highcheckers, &mut poly_lowlight);
-							} else {
-								println!("ERROR 40: No window state for panel window {} at index {}.",	panelwindows[i][0], i);
-							}
-							if panelitems[i][0] == 44 {
-								panelitems[i][0] = 40;
-							}
-						}
+				//Clear everything between the links and the notification area, then draw the window buttons back.
+				let start = panelstate.links().next_back().unwrap_or(0);
+				let startx = panelstate.elements[start].x + panelstate.elements[start].width;
+				let trayx = panelstate.elements[panelstate.notification()].x;
+				let (x, y, w, h) = panelstate.band(startx, trayx.max(startx));
+				xconnection.poly_fill_rectangle(panel, gc_highbackground, &[Rectangle { x, y, width: w as u16, height: h as u16 }])?; //Draw panel background.
+				for i in panelstate.windows() {
+					drawtaskbutton(&xconnection, panel, wm, i, &panelstate, flash, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_highcheckers, gc_attention, gc_attentiontext, &mut poly_lowlight);
+					if panelstate.elements[i].state == ElementState::Reset {
+						panelstate.elements[i].state = ElementState::Ready;
 					}
-				
-				
-				
+				}
+				for i in panelstate.arrows() {
+					drawarrow(&xconnection, panel, &panelstate, i, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, &mut poly_lowlight)?;
 				}
 			} else {
 
 				//Let's draw the panel.
-				xconnection.poly_fill_rectangle(panel, gc_highbackground, &[Rectangle { x: 0, y: 0, width: width as u16, height: panelheight }])?; //Draw panel background.
-				xconnection.poly_line(CoordMode::PREVIOUS, panel, gc_highlight, &[Point { x: 0, y: 1 }, Point { x: width as i16, y: 0 }])?; //Draw panel highlight.
+				panelstate.groupwindows(SETTINGS.grouping, |client| wm.getwindow(&client).map_or(String::new(), |state| state.class.clone()));
+				let (_, _, panelwidth, panelheight) = panelstate.geometry(width, height, true);
+				xconnection.poly_fill_rectangle(panel, gc_highbackground, &[Rectangle { x: 0, y: 0, width: panelwidth as u16, height: panelheight as u16 }])?; //Draw panel background.
+				//Raised edge on the side facing the desktop. Highlight above or left, lowlight below or right.
+				let (gc_edge, from, to) = match SETTINGS.paneledge {
+					PanelEdge::Bottom => (gc_highlight, Point { x: 0, y: 1 }, Point { x: panelwidth, y: 1 }),
+					PanelEdge::Top => (gc_lowlight, Point { x: 0, y: panelheight - 1 }, Point { x: panelwidth, y: panelheight - 1 }),
+					PanelEdge::Left => (gc_lowlight, Point { x: panelwidth - 1, y: 0 }, Point { x: panelwidth - 1, y: panelheight }),
+					PanelEdge::Right => (gc_highlight, Point { x: 1, y: 0 }, Point { x: 1, y: panelheight }),
+				};
+				xconnection.poly_line(CoordMode::ORIGIN, panel, gc_edge, &[from, to])?; //Draw panel highlight.
 
 
-				//We will loop through all items in panel[?] and display them.
-				for i in 0..(panelindex[5] as usize + 1) {
-					//println!("Index {} - Type {} - X {}", i, panelitems[i][0], panelcoordinates[i][0]);
-					match panelitems[i][0] {
-						0 => {
-							//Invalid panel item, assume the rest are too!
-							break;
-						}
-						1 => {
+				//We will loop through all items in the panel and display them.
+				for i in 0..panelstate.elements.len() {
+					let element = panelstate.elements[i];
+					match element.kind {
+						PanelElement::Click => {
 							//Start Button
-							drawclickbutton(&xconnection, panel, panelcoordinates[i][0], 4, panelcoordinates[i][1], 21, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, &mut poly_lowlight);
-							//println!("Start Button X {}", panelcoordinates[i][0]);
+							let (x, y, w, h) = panelstate.rect(i);
+							drawclickbutton(&xconnection, panel, x, y, w, h, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, &mut poly_lowlight);
 						}
-						30 => {
+						PanelElement::Link(link) => {
 							//Three pixels to the left of the icon, four to the right.
-							drawpng(&xconnection, panel, &panelicons[panelwindows[i][0] as usize][3], panelcoordinates[i][0] + 3, 7, 16, 16, COLOURS[HIGHBACKGROUND_COLOUR])?;
-							
+							let (x, y, _, _) = panelstate.rect(i);
+							drawpng(&xconnection, panel, &panelstate.links[link].icon, x + 3, y + 3, 16, 16, COLOURS[HIGHBACKGROUND_COLOUR])?;
 						}
-						40 => {
-							//startx: i16, starty: i16, framewidth: i16, frameheight: i1
-							
-							drawwindowbuttons(&xconnection, panel, panelitems[i][0], &wm.getwindow(&(panelwindows[i][0] as Window)).unwrap().title, panelcoordinates[i][0], panelcoordinates[i][1], gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_highcheckers, &mut poly_lowlight);
-							
-
-							
+						PanelElement::Window(_) => {
+							drawtaskbutton(&xconnection, panel, wm, i, &panelstate, flash, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_highcheckers, gc_attention, gc_attentiontext, &mut poly_lowlight);
+						}
+						PanelElement::Arrow(_) => {
+							drawarrow(&xconnection, panel, &panelstate, i, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, &mut poly_lowlight)?;
 						}
-						60 => {
+						PanelElement::Notification => {
 							//Notification Area
 							
 							//Notification - Depression
-							drawdepressedframe(&xconnection, panel, panelcoordinates[i][0] + panelcoordinates[i][1], 4, panelcoordinates[i][1], 21, gc_highlight, gc_lowbackground)?;
-							
+							let (x, y, w, h) = panelstate.rect(i);
+							drawdepressedframe(&xconnection, panel, x + w, y, w, h, gc_highlight, gc_lowbackground)?;
 							
-							//Load notification graphic. 
-							
-							//println!("Here Here {} {}", panelcoordinates[i][0], panelcoordinates[i][1]);
-							
-							
-											
-							let mut start = 16;
-							//This is dumb. Surely there was a better way to print icons right to left.
-							//Anyway, this bit of code will hopefully save something.
-							//Reorder this when the default number of expected icons increases.
-							if panelicons[27][3].is_empty() {
-								start = 28;
-							} else if panelicons[23][3].is_empty() {
-								start = 24;
-							} else if panelicons[19][3].is_empty() {
-								start = 20;
-							}
-							//Check panelicons from Start to 31 and print them right to left in the notificaiton tray.
-							let mut counter = 0;
-							for b in start..=31 {
-								if !panelicons[b][3].is_empty() {
-									//println!("Tray Icons {} {}", counter, b);
-									drawpng(&xconnection, panel, &panelicons[b][3], panelcoordinates[i][0] + 3 + (counter * 20), 7, 16, 16, COLOURS[HIGHBACKGROUND_COLOUR])?;
-									counter += 1;
+							//Tray icons, left to right. Docked ones draw themselves.
+							for (counter, icon) in panelstate.trayicons.iter().enumerate().filter(|(_, icon)| icon.window.is_none()) {
+								let (iconx, icony) = panelstate.trayicon(counter);
+								match monitor.reading.filter(|_| icon.program == applets::monitor::PROGRAM) {
+									Some(reading) => monitor.draw(&xconnection, panel, iconx, icony, reading.hot(SETTINGS.cpuwarning, SETTINGS.memorywarning, SETTINGS.temperaturewarning), gc_lowlight, gc_highlight, gc_warning)?,
+									None => drawpng(&xconnection, panel, &icon.icon, iconx, icony, 16, 16, COLOURS[HIGHBACKGROUND_COLOUR])?,
 								}
 							}
-							(_, pminute, phour) = drawclock(&xconnection, panel, gc_lowlight, width, clockheight)?;
+							let (clockx, clocky) = panelstate.clock();
+							drawclock(&xconnection, panel, gc_lowlight, clockx, clocky, &clocktext, clockcharacters)?;
 
 						}
-						_ => {
-							println!("{} {} {} {}", panelitems[i][0], panelcoordinates[i][0], panelcoordinates[i][1], panelwindows[i][0]);
-						}
 					}
 				}
 			}
-			draw = 255;
+			draw = Redraw::Nothing;
 		}
 			
-		if sleep == true {
-			thread::sleep(Duration::from_millis(1));
-			//println!("sleeping");
-		} else {
-			//println!("waking");
-		}
 			
 			
 		
@@ -1598,281 +1918,208 @@ fn desktop() -> Result<(), Box<dyn Error>> {
 		//width = screen.width_in_pixels as i16;
 		//height = screen.height_in_pixels as i16;
 		
-		//draw = 1;
+		//draw = Redraw::Click(ElementState::Pressed);
 		
-    }
-    println!("wat?");
+    };
 	
+	xconnection.destroy_window(manager)?;
+	xconnection.flush()?;
+	Ok(exit)
 }
 
-fn clickelement(offsetx: i16, elementwidth: i16) -> u8 {
-    if offsetx < 0 {
-        return 255;
-    }
-    let element = offsetx / (elementwidth + 1);
-    if element >= 0 {
-        element as u8
-    } else {
-        255
-    }
+fn drawtaskbutton<C: Connection>(xconnection: &C, panel: Window, wm: &WindowManager, i: usize, panelstate: &Panel, flash: bool, gc_highlight: Gcontext, gc_lowlight: Gcontext, gc_highbackground: Gcontext, gc_lowbackground: Gcontext, gc_highcheckers: Gcontext, gc_attention: Gcontext, gc_attentiontext: Gcontext, poly_lowlight: &mut Vec<Segment>) {
+	//Draw the taskbar button at i. A group shows its class and how many windows it has, engaged or flashing if any of them is.
+	let element = panelstate.elements[i];
+	if element.width == 0 {
+		//Folded into a group.
+		return;
+	}
+	let members = panelstate.members(i);
+	let states: Vec<&WindowState> = members.iter().filter_map(|&member| panelstate.window(member)).filter_map(|client| wm.getwindow(&client)).collect();
+	let Some(first) = states.first() else {
+		println!("ERROR: No window state for panel element {:?} at index {}.", element.kind, i);
+		return;
+	};
+	let state = if members.iter().any(|&member| panelstate.elements[member].state == ElementState::Engaged) { ElementState::Engaged } else { element.state };
+	let title = if members.len() > 1 { format!("{} ({})", first.class, members.len()) } else { first.title.clone() };
+	let urgent = flash && states.iter().any(|state| state.urgent);
+	let (x, y, width, _) = panelstate.rect(i);
+	drawwindowbuttons(xconnection, panel, state, &title, first.icon.as_deref(), x, y, width, urgent, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_highcheckers, gc_attention, gc_attentiontext, poly_lowlight);
 }
 
-fn checkelement(eventx: i16, eventy: i16, panelindex: &[u8; 6], panelcoordinates: &[[i16; 2]; 128]) -> Option<(usize, u8)> {
-    if eventy <= 2 {
-        return None;
-    }
-
-    let index = panelindex[1] as usize;
-    let linkstart = panelcoordinates[index][0];
-	let linkend = panelcoordinates[panelindex[2] as usize][0] + panelcoordinates[panelindex[2] as usize][1];
-
-    // Check for link buttons (between linkx and windowx)
-	
-	//println!("eventx {} > linkstart {} && eventx {} <= linkend {}", eventx, linkstart, eventx, linkend);
-	
-	
-	if eventx < linkstart {
-		return Some((0, 0)); //0 for main menu!
-	} else if eventx > linkstart && eventx <= linkend {
-        let link = clickelement(eventx - linkstart, panelcoordinates[index][1]);
-        return Some((index + link as usize, 30));  //30 for links
-    }
-
-    let windowx = panelcoordinates[panelindex[3] as usize][0];
-    let notificationx = panelcoordinates[panelindex[5] as usize][0];
-
-    // Check for window buttons (between windowx and notificationx)
-    if eventx > windowx && eventx < notificationx {
-        let index = panelindex[3] as usize;
-        let window = clickelement(eventx - windowx, panelcoordinates[index][1]);
-        return Some((index + window as usize, 40));  //40 for window buttons
-    }
-
-    None
+fn drawarrow<C: Connection>(xconnection: &C, panel: Window, panelstate: &Panel, i: usize, gc_highlight: Gcontext, gc_lowlight: Gcontext, gc_highbackground: Gcontext, gc_lowbackground: Gcontext, poly_lowlight: &mut Vec<Segment>) -> Result<(), Box<dyn Error>> {
+	//Draw the taskbar arrow at i, greyed out if there are no more buttons that way.
+	let PanelElement::Arrow(forward) = panelstate.elements[i].kind else {
+		return Ok(());
+	};
+	let (x, y, width, height) = panelstate.rect(i);
+	drawpanelarrow(xconnection, panel, x, y, width, height, forward, panelstate.vertical(), panelstate.canpage(forward), gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, poly_lowlight)
 }
 
-fn updateelement(target: usize, elementtype: u8, new: u8, panelitems: &mut [[u8; 1]; 128], panelindex: &[u8; 6]) -> Option<u8> {
-    //let target = index + link as usize;
-	
-    let state = panelitems[target][0] % 10;
-
-	//println!("updateelement state {} new {}", state, new);
-
-    if state == new {
-        return None;
-    }
-
-	let (start, end) = if elementtype == 30 { (panelindex[1] as usize, panelindex[2] as usize) } else if elementtype == 40 { (panelindex[3] as usize, panelindex[4] as usize) } else { (0, 0) };
-
-	//println!("start {} end {}", start, end);
-
+fn epochnow() -> i64 {
+	SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map(|since| since.as_secs() as i64).unwrap_or(0)
+}
 
-    if new == 0 {
-		//Is this still used???
-        if state > 0 {
-            for i in start..=end {
-                let s = panelitems[i][0];
-				//println!("Loop 1 s {}", s);
-                if s > elementtype {
-                    panelitems[i][0] = elementtype;
-                }
-            }
-        }
-        panelitems[target][0] = elementtype;
-		
-		//println!("new == 0 state > 0 panelitems[{}][0] {}", target, elementtype);
-		
-        return Some(elementtype);
-    }
+fn clockwake(alarms: &Alarms) -> Instant {
+	//When the clock next has to look: the next second or minute depending on what it shows, or an alarm if that comes first.
+	let since = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+	let period: u128 = if SETTINGS.clockseconds { 1000 } else { 60000 };
+	let tick = Duration::from_millis((period - since.as_millis() % period) as u64);
+	let alarm = alarms.next().map(|at| Duration::from_secs(at.saturating_sub(epochnow()).max(0) as u64));
+	Instant::now() + alarm.map_or(tick, |alarm| alarm.min(tick))
+}
 
-    if (new == 1 && state >= 0) || (new == 2 && state == 0) {
-        for i in start..=end {
-            let s = panelitems[i][0];
-			
-            if s > elementtype {
-                panelitems[i][0] = elementtype + 4;
-            }
-			//println!("Loop 2's {} s {} to {}", i, s, panelitems[i][0]);
-        }
-		let exit = elementtype + new;
-        panelitems[target][0] = exit;
-		
-		//println!("(new == 1 && state >= 0) || (new == 2 && state == 0) panelitems[{}][0] {}", target, exit);
-		
-		//println!("Loop 2's Final: {} s {}", target, exit);
-        return Some(exit);
-    }
+fn clocknow(timezone: &system::clock::TimeZone) -> String {
+	//What the clock should say right now, in local time and the format from the settings.
+	system::clock::text(timezone.localtime(epochnow()), SETTINGS.clock, SETTINGS.clockseconds, SETTINGS.clockdate)
+}
 
-    None
+fn tooltiptext(wm: &WindowManager, panelstate: &Panel, timezone: &system::clock::TimeZone, (index, trayicon): (usize, Option<usize>)) -> Option<String> {
+	//What the tooltip for a panel element says. Taskbar buttons get the whole title, and the clock the whole date.
+	let text = match panelstate.elements.get(index)?.kind {
+		PanelElement::Click => "Click here to begin.".to_string(),
+		PanelElement::Link(_) => panelstate.link(index)?.tooltip.clone(),
+		PanelElement::Window(client) => {
+			let members = panelstate.members(index);
+			let state = wm.getwindow(&client)?;
+			if members.len() > 1 { format!("{} ({})", state.class, members.len()) } else { state.title.clone() }
+		}
+		PanelElement::Arrow(forward) => format!("{} page ({} more)", if forward { "Next" } else { "Previous" }, panelstate.hidden(forward)),
+		PanelElement::Notification => match trayicon {
+			Some(n) => panelstate.trayicons.get(n)?.tooltip.clone(),
+			None => system::clock::fulldate(timezone.localtime(epochnow())),
+		},
+	};
+	Some(text).filter(|text| !text.is_empty())
 }
 
-fn focuswindow<C: Connection>(wm: &mut WindowManager, xconnection: &C, panel: Window, client: Window, gc_highlight: Gcontext, gc_lowlight: Gcontext, gc_highbackground: Gcontext, gc_lowbackground: Gcontext, gc_titlebar: Gcontext, gc_titlebartext: Gcontext, poly_lowlight: &mut Vec<Segment>, poly_index: &mut Vec<u8>, poly_windoworcolour: &mut Vec<u32>) -> Result<(), Box<dyn Error>> {
+fn focuswindow<C: Connection>(wm: &mut WindowManager, xconnection: &C, panel: Window, client: Window, gc_highlight: Gcontext, gc_lowlight: Gcontext, gc_highbackground: Gcontext, gc_lowbackground: Gcontext, gc_titlebar: Gcontext, gc_titlebartext: Gcontext, gc_attention: Gcontext, gc_attentiontext: Gcontext, poly_lowlight: &mut Vec<Segment>, poly_index: &mut Vec<u8>, poly_windoworcolour: &mut Vec<u32>) -> Result<(), Box<dyn Error>> {
     if let Some(state) = wm.getwindow(&client) {
         let frame = state.frame;
-        if state.map == 0 { xconnection.map_window(client)?; xconnection.map_window(frame)?; }
+        wm.restore(xconnection, client)?;
         wm.focus(&xconnection, frame, panel)?;
-        let redraw: Vec<(Window, Window, i16, i16)> = wm.windows.values().filter(|state| state.map == 2 || state.map == 3).map(|state| {
-			let fwidth = state.width + (2 * BORDER as i16);
-			let fheight = state.height + (2 * BORDER as i16) + (TITLEBAR as i16);
-			(state.frame, state.window, fwidth, fheight)
-		}).collect();
-        for (frame, client, width, height) in redraw {
-			if frame != panel {
-				let somethingtodraw = poly_lowlight.len();
-				updateborder(&xconnection, frame, client, width, height, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, poly_lowlight);
-				if poly_lowlight.len() != somethingtodraw {
-					poly_index.push(somethingtodraw as u8);
-					poly_windoworcolour.push(frame);
-				}
-			}
+        for (start, frame) in redrawshownframes(xconnection, wm, panel, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, gc_attention, gc_attentiontext, poly_lowlight) {
+			poly_index.push(start);
+			poly_windoworcolour.push(frame);
 		}
     }
     Ok(())
 }
 
+fn redrawshownframes<C: Connection>(xconnection: &C, wm: &WindowManager, panel: Window, gc_highlight: Gcontext, gc_lowlight: Gcontext, gc_highbackground: Gcontext, gc_lowbackground: Gcontext, gc_titlebar: Gcontext, gc_titlebartext: Gcontext, gc_attention: Gcontext, gc_attentiontext: Gcontext, poly_lowlight: &mut Vec<Segment>) -> Vec<(u8, Window)> {
+	//Redraw the frames of the windows on screen, after the focus moves. Returns where each frame that drew anything started in poly_lowlight.
+	let mut drawn = Vec::new();
+	for state in wm.windows.values().filter(|state| (state.map == 2 || state.map == 3) && state.decorated && state.frame != panel) {
+		let start = poly_lowlight.len();
+		let width = state.width + (2 * BORDER as i16);
+		let height = state.height + (2 * BORDER as i16) + (TITLEBAR as i16);
+		updateborder(xconnection, state.frame, state.window, width, height, state.map == 2, state.urgent, state.icon.as_deref(), gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, gc_attention, gc_attentiontext, poly_lowlight);
+		if poly_lowlight.len() != start {
+			drawn.push((start as u8, state.frame));
+		}
+	}
+	drawn
+}
+
+
+fn activatepanelwindow(client: Window, panelstate: &mut Panel, windowactive: &mut Option<usize>, windowlast: &mut Option<usize>) {
+	//Press in the taskbar button for client and pop the rest back out. A client without a button (or 0) leaves them all out.
+	for i in panelstate.windows() {
+		let element = &mut panelstate.elements[i];
+		if client != 0 && element.kind == PanelElement::Window(client) {
+			*windowactive = Some(i);
+			element.state = ElementState::Engaged;
+		} else {
+			if element.state == ElementState::Engaged {
+				*windowlast = Some(i);
+			}
+			element.state = ElementState::Ready;
+		}
+	}
+}
 
-fn resetpanelelement(draw: &mut u8, elementreset: &mut u8, panelitems: &mut [[u8; 1]; 128], panelindex: &[u8; 6]) {
+fn resetpanelelement(draw: &mut Redraw, elementreset: &mut Option<usize>, panelstate: &mut Panel) {
 	//This function reset the link or window button if you move the mouse away from it.
-    if *elementreset < 255 {
-		let mut target = *elementreset as usize;
-		let mut output = 255;
-		if panelindex[0] == *elementreset {
-			output = 0;
-        } else if panelindex[1] <= *elementreset && *elementreset <= panelindex[2] {
-			output = 34;
-		} else if panelindex[3] <= *elementreset && *elementreset <= panelindex[4] {
-			if panelitems[target][0] == 43 {
-				//If the window button on the panel is active, we don't want it to be reset!
-				//However, we can reset the buttons either side if they are indented.
-				println!("useful");
-				if panelitems[target - 1][0] == 41 {
-					//Reset the button on the left.
-					target = target - 1;
-					println!("useful - 1");
-				} else if panelitems[target + 1][0] == 41 {
-					//Reset the button on the right.
-					target = target + 1;
-					println!("useful + 1");
-				} else {
-					//Nope, nothing to reset here.
-					return
-				}
+	if let Some(mut target) = elementreset.take() {
+		let output = match panelstate.elements[target].kind {
+			PanelElement::Click => ElementState::Ready,
+			PanelElement::Link(_) | PanelElement::Window(_) => ElementState::Reset,
+			PanelElement::Arrow(_) | PanelElement::Notification => return,
+		};
+		if panelstate.elements[target].state == ElementState::Engaged {
+			//If the window button on the panel is active, we don't want it to be reset!
+			//However, we can reset the buttons either side if they are indented.
+			let pressed = |i: usize| panelstate.elements.get(i).is_some_and(|element| element.state == ElementState::Pressed);
+			if target > 0 && pressed(target - 1) {
+				//Reset the button on the left.
+				target -= 1;
+			} else if pressed(target + 1) {
+				//Reset the button on the right.
+				target += 1;
+			} else {
+				//Nope, nothing to reset here.
+				return;
 			}
-			output = 44;
-		} else {
-			return
 		}
-        panelitems[target][0] = output;
-        *draw = output;
-        *elementreset = 255;
-    }
+		panelstate.elements[target].state = output;
+		*draw = panelstate.elements[target].redraw();
+	}
 }
 
-fn swapwindow<C: Connection>(wm: &mut WindowManager, xconnection: &C, panel: Window, panelwindows: &[[u32; 1]; 128], panelitems: &mut [[u8; 1]; 128], windowactive: &mut u8, windowlast: &mut u8, index: usize, gc_highlight: Gcontext, gc_lowlight: Gcontext, gc_highbackground: Gcontext, gc_lowbackground: Gcontext, gc_titlebar: Gcontext, gc_titlebartext: Gcontext, poly_lowlight: &mut Vec<Segment>, poly_index: &mut Vec<u8>, poly_windoworcolour: &mut Vec<u32>) {
-    if *windowlast == 255 || *windowlast == *windowactive {
-        let loopindex = index as u8;
+fn swapwindow<C: Connection>(wm: &mut WindowManager, xconnection: &C, panel: Window, panelstate: &mut Panel, windowactive: &mut Option<usize>, windowlast: &mut Option<usize>, index: usize, gc_highlight: Gcontext, gc_lowlight: Gcontext, gc_highbackground: Gcontext, gc_lowbackground: Gcontext, gc_titlebar: Gcontext, gc_titlebartext: Gcontext, gc_attention: Gcontext, gc_attentiontext: Gcontext, poly_lowlight: &mut Vec<Segment>, poly_index: &mut Vec<u8>, poly_windoworcolour: &mut Vec<u32>) {
+    //Only go back to the last window if it is still on screen, rather than pulling a minimised one back up.
+    let lastvisible = windowlast.and_then(|last| panelstate.window(last)).and_then(|last| wm.getwindow(&last)).is_some_and(|state| state.map == 3);
+    if !lastvisible || *windowlast == *windowactive {
+        //Look for the nearest visible window either side, up to five buttons away.
         for i in 1..=5 {
-            if let Some(checkindex) = loopindex.checked_sub(i) {
-                if (checkindex as usize) < panelwindows.len() {
-                    if let Some(window) = wm.windows.get_mut(&(panelwindows[checkindex as usize][0] as Window)) {
-                        println!("Window.Map {} {}", window.map, checkindex);
-                        if window.map == 3 {
-                            window.map = 2;
-                            panelitems[checkindex as usize][0] = 43;
-                            focuswindow(wm, xconnection, panel, panelwindows[checkindex as usize][0] as Window, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, poly_lowlight, poly_index, poly_windoworcolour);
-                            break;
-                        }
-                    }
-                }
-            }
-            let checkindex = loopindex + i;
-            if (checkindex as usize) < panelwindows.len() {
-                if let Some(window) = wm.windows.get_mut(&(panelwindows[checkindex as usize][0] as Window)) {
+            let neighbours = [index.checked_sub(i), Some(index + i)];
+            for checkindex in neighbours.into_iter().flatten() {
+                let Some(client) = panelstate.window(checkindex) else {
+                    continue;
+                };
+                if let Some(window) = wm.windows.get_mut(&client) {
                     println!("Window.Map {} {}", window.map, checkindex);
                     if window.map == 3 {
                         window.map = 2;
-                        panelitems[checkindex as usize][0] = 43;
-                        focuswindow(wm, xconnection, panel, panelwindows[checkindex as usize][0] as Window, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, poly_lowlight, poly_index, poly_windoworcolour);
-                        break;
+                        panelstate.elements[checkindex].state = ElementState::Engaged;
+                        focuswindow(wm, xconnection, panel, client, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, gc_attention, gc_attentiontext, poly_lowlight, poly_index, poly_windoworcolour);
+                        return;
                     }
                 }
             }
         }
-    } else {
-        *windowactive = *windowlast;
-        panelitems[*windowactive as usize][0] = 43;
-        *windowlast = 255;
-        focuswindow(wm, xconnection, panel, panelwindows[*windowactive as usize][0] as Window, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, poly_lowlight, poly_index, poly_windoworcolour);
+    } else if let Some(last) = windowlast.take() {
+        *windowactive = Some(last);
+        panelstate.elements[last].state = ElementState::Engaged;
+        if let Some(client) = panelstate.window(last) {
+            focuswindow(wm, xconnection, panel, client, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, gc_attention, gc_attentiontext, poly_lowlight, poly_index, poly_windoworcolour);
+        }
     }
 }
 
-fn removepanelwindow(panelindex: &mut [u8; 6], window: u32, panelitems: &mut [[u8; 1]; 128], panelcoordinates: &mut [[i16; 2]; 128], panelwindows: &mut [[u32; 1]; 128], panelicons: &mut [[String; 4]; 32]) {
-    let windowstart = panelindex[3];
-    let windowend = panelindex[5];
-    let mut remove: Option<usize> = None;
-    
-    //Find the window to remove.
-    for index in windowstart as usize..windowend as usize {
-        if panelwindows[index][0] == window {
-			//Move windows left.
-			for i in index..=windowend as usize - 1 {
-				panelitems[i] = panelitems[i + 1];
-				panelcoordinates[i] = panelcoordinates[i + 1];
-				panelwindows[i] = panelwindows[i + 1];
-			}
-
-			if panelindex[3] != panelindex[4] {
-				panelindex[4] = panelindex[4] - 1;
-				panelindex[5] = panelindex[5] - 1;
-			} else {
-				//Reset to factory settings!
-				panelindex[3] = panelindex[2];
-				panelindex[4] = panelindex[2];
-				panelindex[5] = panelindex[2] + 1;
+fn showdesktop<C: Connection>(wm: &mut WindowManager, xconnection: &C, panel: Window, hidden: &mut Vec<Window>, panelstate: &mut Panel, windowactive: &mut Option<usize>, windowlast: &mut Option<usize>) -> Result<(), Box<dyn Error>> {
+	//Minimise everything, or bring back what we minimised last time. The focused window goes first in hidden, so it gets the focus back.
+	hidden.retain(|client| wm.getwindow(client).is_some_and(|state| state.map == 0));
+	if hidden.is_empty() {
+		let mut visible: Vec<(Window, bool)> = wm.windows.values().filter(|state| (state.map == 2 || state.map == 3) && state.frame != 0).map(|state| (state.window, state.map == 2)).collect();
+		visible.sort_by_key(|&(_, focused)| !focused);
+		for (client, _) in visible {
+			if wm.minimise(xconnection, client)? {
+				hidden.push(client);
 			}
+		}
+		wm.dropfocus(xconnection)?;
+		activatepanelwindow(0, panelstate, windowactive, windowlast);
+	} else {
+		for &client in hidden.iter() {
+			wm.restore(xconnection, client)?;
+		}
+		if let Some(frame) = wm.getwindow(&hidden[0]).map(|state| state.frame) {
+			wm.focus(xconnection, frame, panel)?;
+			activatepanelwindow(hidden[0], panelstate, windowactive, windowlast);
+		}
+		hidden.clear();
+	}
+	Ok(())
+}
 
-			//Clear last slot that is now out of range.
-			panelitems[windowend as usize] = [0];
-			panelcoordinates[windowend as usize] = [0, 0];
-			panelwindows[windowend as usize] = [0];
-
-			windowbuttonlength(index, panelindex, panelitems, panelcoordinates); //Was index - 1, changed it for no reason.
-
-			break;
-        }
-    }
-	
-	
-    println!("\n=== Panel Indices ===");
-    println!("┌───────┬─────────────┬───────────┬──────────────┬─────────────┬──────────────┐");
-    println!("│ Start │ Links Start │ Links End │ Window Start │ Window End  │ Notify Start │");
-    println!("├───────┼─────────────┼───────────┼──────────────┼─────────────┼──────────────┤");
-    println!("│   {}   │     {}       │     {}     │      {}       │      {}      │      {}       │", 
-        panelindex[0], panelindex[1], panelindex[2], panelindex[3], panelindex[4], panelindex[5]);
-    println!("└───────┴─────────────┴───────────┴──────────────┴─────────────┴──────────────┘");
-
-    println!("\n=== Panel Items & Windows ===");
-    println!("┌───────┬────────────┬────────────────┬──────────────┐");
-    println!("│ Index │ Item Type  │ Window Handle  │ Coordinates  │");
-    println!("├───────┼────────────┼────────────────┼──────────────┤");
-
-    for i in 0..=panelindex[5] as usize {
-        println!("│  {:3}  │    {:3}     │   {:8}     │ [{:4}, {:4}] │",
-            i,
-            panelitems[i][0],
-            panelwindows[i][0],
-            panelcoordinates[i][0],
-            panelcoordinates[i][1]
-        );
-    }
-    println!("└───────┴────────────┴────────────────┴──────────────┘");
-
-    println!("\nItem Types Legend:");
-    println!(" 0  = Start Button");
-    println!("30  = Icon Link");
-    println!("40  = Window Button (Normal)");
-    println!("43  = Window Button (Focused)");
-    println!("60  = Notification Area");
-	
-	
-}
\ No newline at end of file
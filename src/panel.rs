@@ -0,0 +1,909 @@
+use std::ops::Range;
+use x11rb::protocol::xproto::Window;
+
+use crate::system::settings::PanelEdge;
+
+//Where things go on the panel, left to right (or top to bottom on the sides of the screen).
+pub const CLICKX: i16 = 2;
+pub const CLICKWIDTH: i16 = 54;
+pub const LINKX: i16 = 60;
+pub const LINKWIDTH: i16 = 23;
+pub const WINDOWBUTTONLENGTH: i16 = 160;
+const MINWINDOWBUTTONLENGTH: i16 = 48; //Any shorter and the taskbar pages instead, with arrows.
+const ARROWLENGTH: i16 = 16;
+pub const TRAYICONWIDTH: i16 = 20;
+pub const BUTTONHEIGHT: i16 = 21;
+const CLOCKWIDTH: i16 = 60;
+const CLOCKHEIGHT: i16 = 24;
+const GAP: i16 = 3;
+const MINTHICKNESS: i16 = 28;
+const MINSIDETHICKNESS: i16 = CLOCKWIDTH + 6; //Down the side, the clock still has to fit across. More if it has more to say, see setclock.
+const HIDDENTHICKNESS: i16 = 2; //How much of an autohidden panel stays on screen, for the pointer to touch.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PanelElement {
+	Click, //Opens the Clicker menu.
+	Link(usize), //Quick-launch link, by its place in Panel::links. Stays the same when the links are dragged around.
+	Window(Window), //Taskbar button for a client window.
+	Arrow(bool), //Pages through the taskbar buttons when they don't all fit. true goes forward, false back.
+	Notification, //Tray icons and the clock.
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ElementState {
+	Ready,
+	Pressed,
+	Hover,
+	Engaged, //Taskbar button of the focused window.
+	Reset, //Needs drawing popped back out, then it is ready.
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Redraw {
+	//What the event loop draws again on the panel once it has dealt with an event.
+	Nothing,
+	Click(ElementState), //Back to ready closes the Clicker menu.
+	Links, //Every link from scratch.
+	LinkStates, //Just the frames of links being pressed, hovered or popped back out.
+	Windows, //Lay the taskbar out again and draw all its buttons.
+	WindowStates, //Just the taskbar buttons being pressed or popped back out.
+	Panel, //All of it.
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Element {
+	pub kind: PanelElement,
+	pub state: ElementState,
+	pub x: i16,
+	pub width: i16, //0 for taskbar buttons folded into a group.
+}
+
+impl Element {
+	pub fn redraw(&self) -> Redraw {
+		//What to draw again now it is in this state. Back to ready redraws the lot, otherwise just what changed.
+		match (self.kind, self.state) {
+			(PanelElement::Click, state) => Redraw::Click(state),
+			(PanelElement::Link(_), ElementState::Ready) => Redraw::Links,
+			(PanelElement::Link(_), _) => Redraw::LinkStates,
+			(PanelElement::Window(_), ElementState::Ready) => Redraw::Windows,
+			(PanelElement::Window(_), _) => Redraw::WindowStates,
+			(PanelElement::Arrow(_) | PanelElement::Notification, _) => Redraw::Panel,
+		}
+	}
+}
+
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct PanelIcon {
+	pub program: String, //Command line to run, or something built in like tullamore:showdesktop for the Show Desktop button.
+	pub label: String,
+	pub tooltip: String,
+	pub icon: String,
+	pub directory: String, //Where to run it. Empty means wherever Tullamore is running.
+	pub environment: Vec<(String, String)>, //Extra environment variables, as (name, value).
+	pub window: Option<Window>, //Tray icon docked by another program, which draws it itself.
+}
+
+pub struct Panel {
+	//Always the Click button, then the links, then the taskbar buttons, then the arrows if they are paging, then the notification area.
+	pub elements: Vec<Element>,
+	pub links: Vec<PanelIcon>,
+	pub trayicons: Vec<PanelIcon>, //Left to right.
+	pub groups: Vec<Vec<usize>>, //Taskbar buttons sharing one button, by element index. The first one gets the button.
+	pub edge: PanelEdge,
+	pub thickness: i16, //Height along the top or bottom, width down the sides.
+	clockwidth: i16,
+	length: i16, //Screen width or height, whichever the panel runs along.
+	page: usize, //First taskbar button shown while paging, counting a group as one.
+	perpage: usize,
+	buttons: usize, //Taskbar buttons at the last layout, again counting a group as one.
+}
+
+pub fn thickness(edge: PanelEdge, size: i16) -> i16 {
+	//The panel size from the settings, made big enough for everything on it.
+	match edge {
+		PanelEdge::Top | PanelEdge::Bottom => size.max(MINTHICKNESS),
+		PanelEdge::Left | PanelEdge::Right => size.max(MINSIDETHICKNESS),
+	}
+}
+
+impl Panel {
+	pub fn new(edge: PanelEdge, thickness: i16, screenwidth: i16, screenheight: i16) -> Panel {
+		let mut panel = Panel {
+			elements: vec![
+				Element { kind: PanelElement::Click, state: ElementState::Ready, x: 0, width: 0 },
+				Element { kind: PanelElement::Notification, state: ElementState::Ready, x: 0, width: 0 },
+			],
+			links: Vec::new(),
+			trayicons: Vec::new(),
+			groups: Vec::new(),
+			edge,
+			thickness,
+			clockwidth: CLOCKWIDTH,
+			length: if matches!(edge, PanelEdge::Left | PanelEdge::Right) { screenheight } else { screenwidth },
+			page: 0,
+			perpage: 0,
+			buttons: 0,
+		};
+		panel.reflow();
+		panel
+	}
+
+	pub fn vertical(&self) -> bool {
+		matches!(self.edge, PanelEdge::Left | PanelEdge::Right)
+	}
+
+	pub fn geometry(&self, screenwidth: i16, screenheight: i16, shown: bool) -> (i16, i16, i16, i16) {
+		//Where the panel window goes as (x, y, width, height). Hidden, only a sliver is left on screen.
+		let offscreen = if shown { 0 } else { self.thickness - HIDDENTHICKNESS };
+		match self.edge {
+			PanelEdge::Bottom => (0, screenheight - self.thickness + offscreen, screenwidth, self.thickness),
+			PanelEdge::Top => (0, -offscreen, screenwidth, self.thickness),
+			PanelEdge::Left => (-offscreen, 0, self.thickness, screenheight),
+			PanelEdge::Right => (screenwidth - self.thickness + offscreen, 0, self.thickness, screenheight),
+		}
+	}
+
+	pub fn workarea(&self, screenwidth: i16, screenheight: i16, autohide: bool) -> (i16, i16, i16, i16) {
+		//The part of the screen windows get as (x, y, width, height). An autohidden panel doesn't take any.
+		if autohide {
+			return (0, 0, screenwidth, screenheight);
+		}
+		match self.edge {
+			PanelEdge::Bottom => (0, 0, screenwidth, screenheight - self.thickness),
+			PanelEdge::Top => (0, self.thickness, screenwidth, screenheight - self.thickness),
+			PanelEdge::Left => (self.thickness, 0, screenwidth - self.thickness, screenheight),
+			PanelEdge::Right => (0, 0, screenwidth - self.thickness, screenheight),
+		}
+	}
+
+	pub fn beside(&self, index: usize, width: i16, height: i16, screenwidth: i16, screenheight: i16) -> (i16, i16) {
+		//Where a width x height window opening from the element at index goes: just off the panel, lined up with the element, and on screen.
+		//The Click button's slot runs from the very start of the panel.
+		self.besidepoint(if index == 0 { 0 } else { self.elements[index].x }, width, height, screenwidth, screenheight)
+	}
+
+	pub fn besidepoint(&self, start: i16, width: i16, height: i16, screenwidth: i16, screenheight: i16) -> (i16, i16) {
+		//Same as beside, but from start along the panel rather than from an element. Tooltips open from the pointer.
+		let (panelx, panely, _, _) = self.geometry(screenwidth, screenheight, true);
+		let (x, y) = match self.edge {
+			PanelEdge::Bottom => (panelx + start, panely - height),
+			PanelEdge::Top => (panelx + start, panely + self.thickness),
+			PanelEdge::Left => (panelx + self.thickness, panely + start),
+			PanelEdge::Right => (panelx - width, panely + start),
+		};
+		(x.min(screenwidth - width).max(0), y.min(screenheight - height).max(0))
+	}
+
+	pub fn along(&self, x: i16, y: i16) -> i16 {
+		//How far along the panel a point in it is. Element x and width are measured this way.
+		if self.vertical() { y } else { x }
+	}
+
+	pub fn rect(&self, index: usize) -> (i16, i16, i16, i16) {
+		//Where the element at index is drawn in the panel window, as (x, y, width, height).
+		let element = self.elements[index];
+		if self.vertical() {
+			let height = if element.kind == PanelElement::Notification { element.width } else { element.width.min(BUTTONHEIGHT) };
+			(GAP, element.x, self.thickness - GAP - GAP, height)
+		} else {
+			(element.x, (self.thickness - BUTTONHEIGHT + 1) / 2, element.width, BUTTONHEIGHT)
+		}
+	}
+
+	pub fn band(&self, from: i16, to: i16) -> (i16, i16, i16, i16) {
+		//The strip of panel between from and to, inside the panel's own edges. For clearing bits of it.
+		if self.vertical() {
+			(2, from, self.thickness - 4, to - from)
+		} else {
+			(from, 2, to - from, self.thickness - 4)
+		}
+	}
+
+	pub fn marker(&self, position: i16) -> (i16, i16, i16, i16) {
+		//The drop marker for a dragged link or taskbar button, across the panel at position.
+		let (x, y, width, height) = self.rect(0);
+		if self.vertical() {
+			(x, position - 1, width, 2)
+		} else {
+			(position - 1, y, 2, height)
+		}
+	}
+
+	fn trayiconsperrow(&self) -> usize {
+		if self.vertical() {
+			((self.thickness - GAP - GAP - GAP) / TRAYICONWIDTH).max(1) as usize
+		} else {
+			self.trayicons.len().max(1)
+		}
+	}
+
+	pub fn trayicon(&self, n: usize) -> (i16, i16) {
+		//Top left of the nth tray icon. Down the side of the screen they wrap onto more rows.
+		let (x, y, _, _) = self.rect(self.notification());
+		let perrow = self.trayiconsperrow();
+		(x + 3 + (n % perrow) as i16 * TRAYICONWIDTH, y + 3 + (n / perrow) as i16 * TRAYICONWIDTH)
+	}
+
+	pub fn trayiconat(&self, x: i16, y: i16) -> Option<usize> {
+		(0..self.trayicons.len()).find(|&n| {
+			let (iconx, icony) = self.trayicon(n);
+			x >= iconx && x < iconx + TRAYICONWIDTH && y >= icony - 3 && y < icony - 3 + TRAYICONWIDTH
+		})
+	}
+
+	pub fn setclock(&mut self, characters: usize) {
+		//Make room for the clock's longest text. Down the side that can make the panel wider.
+		self.clockwidth = CLOCKWIDTH.max(characters as i16 * 6 + 12);
+		if self.vertical() {
+			self.thickness = self.thickness.max(self.clockwidth + 6);
+		}
+		self.reflow();
+	}
+
+	pub fn clock(&self) -> (i16, i16) {
+		//Right hand end and baseline of the clock, the way drawclock wants them.
+		let (x, y, width, height) = self.rect(self.notification());
+		if self.vertical() {
+			(self.thickness, y + height - 5)
+		} else {
+			(x + width + GAP, y + height - 5)
+		}
+	}
+
+	fn buttonlength(&self) -> i16 {
+		//Taskbar button length when they all fit.
+		if self.vertical() { BUTTONHEIGHT } else { WINDOWBUTTONLENGTH }
+	}
+
+	pub fn links(&self) -> Range<usize> {
+		1..1 + self.links.len()
+	}
+
+	pub fn windows(&self) -> Range<usize> {
+		self.links().end..self.arrows().start
+	}
+
+	pub fn arrows(&self) -> Range<usize> {
+		//Back then forward, when the taskbar is paging. Empty when everything fits.
+		let end = self.notification();
+		match self.elements[end - 1].kind {
+			PanelElement::Arrow(_) => end - 2..end,
+			_ => end..end,
+		}
+	}
+
+	pub fn notification(&self) -> usize {
+		self.elements.len() - 1
+	}
+
+	fn area(&self, index: usize) -> Range<usize> {
+		//The run of elements index belongs to.
+		match self.elements[index].kind {
+			PanelElement::Link(_) => self.links(),
+			PanelElement::Window(_) => self.windows(),
+			_ => index..index + 1,
+		}
+	}
+
+	pub fn addlink(&mut self, link: PanelIcon) -> usize {
+		//New links go on the end of the links.
+		let index = self.links().end;
+		self.elements.insert(index, Element { kind: PanelElement::Link(self.links.len()), state: ElementState::Ready, x: 0, width: 0 });
+		self.links.push(link);
+		self.reflow();
+		index
+	}
+
+	pub fn addtrayicon(&mut self, icon: PanelIcon) {
+		//Tray icons fill in from the right, so the newest is on the left.
+		self.trayicons.insert(0, icon);
+		self.reflow();
+	}
+
+	pub fn removetrayicon(&mut self, window: Window) -> bool {
+		//Take out a docked tray icon. Returns whether it was there.
+		let count = self.trayicons.len();
+		self.trayicons.retain(|icon| icon.window != Some(window));
+		self.reflow();
+		self.trayicons.len() != count
+	}
+
+	pub fn applet(&self, program: &str) -> Option<usize> {
+		//Which tray icon belongs to a built-in applet, like tullamore:battery.
+		self.trayicons.iter().position(|icon| icon.program == program)
+	}
+
+	pub fn removetrayiconat(&mut self, n: usize) {
+		self.trayicons.remove(n);
+		self.reflow();
+	}
+
+	pub fn link(&self, index: usize) -> Option<&PanelIcon> {
+		match self.elements.get(index)?.kind {
+			PanelElement::Link(link) => self.links.get(link),
+			_ => None,
+		}
+	}
+
+	pub fn window(&self, index: usize) -> Option<Window> {
+		match self.elements.get(index)?.kind {
+			PanelElement::Window(window) => Some(window),
+			_ => None,
+		}
+	}
+
+	pub fn windowindex(&self, window: Window) -> Option<usize> {
+		self.elements.iter().position(|element| element.kind == PanelElement::Window(window))
+	}
+
+	pub fn insertwindow(&mut self, window: Window) -> usize {
+		//New windows go on the end of the taskbar, engaged, and every other button pops back out.
+		let windows = self.windows();
+		for element in &mut self.elements[windows.clone()] {
+			if element.state == ElementState::Engaged {
+				element.state = ElementState::Ready;
+			}
+		}
+		self.elements.insert(windows.end, Element { kind: PanelElement::Window(window), state: ElementState::Engaged, x: 0, width: 0 });
+		self.reflow();
+		windows.end
+	}
+
+	pub fn removewindow(&mut self, window: Window) -> Option<usize> {
+		//Returns where its button was.
+		let index = self.windowindex(window)?;
+		self.elements.remove(index);
+		self.reflow();
+		Some(index)
+	}
+
+	pub fn moveelement(&mut self, from: usize, to: usize) {
+		//Move the link or taskbar button at from so it sits in front of whatever is at to.
+		//to has to be in the same area, or one past the end of it.
+		if from == to || from + 1 == to {
+			return;
+		}
+		let element = self.elements.remove(from);
+		self.elements.insert(if to > from { to - 1 } else { to }, element);
+		self.reflow();
+	}
+
+	pub fn reflow(&mut self) {
+		//Lay everything out again. Taskbar buttons get the full length if they all fit, otherwise they share the space evenly.
+		//Past a point (straight away down the side of the screen, where they can't get any shorter) they page instead, see placebuttons.
+		let vertical = self.vertical();
+		let notificationlength = if vertical {
+			let rows = self.trayicons.len().div_ceil(self.trayiconsperrow()) as i16;
+			rows * TRAYICONWIDTH + CLOCKHEIGHT
+		} else {
+			self.trayicons.len() as i16 * TRAYICONWIDTH + self.clockwidth
+		};
+		let clicklength = if vertical { BUTTONHEIGHT } else { CLICKWIDTH };
+		let tray = self.length - notificationlength - GAP;
+		let mut linkx = if vertical { CLICKX + clicklength + GAP + 1 } else { LINKX };
+		let mut startx = CLICKX + clicklength + GAP;
+		for element in &mut self.elements {
+			match element.kind {
+				PanelElement::Click => {
+					element.x = CLICKX;
+					element.width = clicklength;
+				}
+				PanelElement::Link(_) => {
+					element.x = linkx;
+					element.width = LINKWIDTH;
+					linkx += LINKWIDTH;
+					startx = linkx + GAP;
+				}
+				PanelElement::Notification => {
+					element.x = tray;
+					element.width = notificationlength;
+				}
+				PanelElement::Window(_) | PanelElement::Arrow(_) => {}
+			}
+		}
+		self.groups.clear();
+		let buttons: Vec<Vec<usize>> = self.windows().map(|i| vec![i]).collect();
+		self.placebuttons(startx, tray, &buttons);
+	}
+
+	fn placebuttons(&mut self, startx: i16, tray: i16, buttons: &[Vec<usize>]) {
+		//Lay the taskbar buttons out between startx and tray, each group on its first window's button and the rest of it with no width.
+		//When they would get too short (or run off the end, down the side) arrows go in front of the tray to page through them,
+		//and the buttons on other pages get no width either.
+		let vertical = self.vertical();
+		let full = self.buttonlength();
+		let count = buttons.len() as i16;
+		let fits = count == 0 || if vertical { count * (full + GAP) <= tray - startx } else { (tray - startx) / count - GAP >= MINWINDOWBUTTONLENGTH };
+		self.setarrows(!fits);
+		self.buttons = buttons.len();
+		let (width, shown, arrowsx) = if fits {
+			self.page = 0;
+			self.perpage = buttons.len();
+			let width = if vertical || count * (full + GAP) - GAP <= tray - startx { full } else { (tray - startx) / count - GAP };
+			(width, 0..buttons.len(), tray)
+		} else {
+			let arrowlength = if vertical { BUTTONHEIGHT } else { ARROWLENGTH };
+			let arrowsx = tray - arrowlength - arrowlength - GAP;
+			let minimum = if vertical { full } else { MINWINDOWBUTTONLENGTH };
+			self.perpage = ((arrowsx - startx) / (minimum + GAP)).max(1) as usize;
+			self.page = self.page.min(buttons.len() - self.perpage);
+			let width = if vertical { full } else { ((arrowsx - startx) / self.perpage as i16 - GAP).clamp(0, full) };
+			(width, self.page..self.page + self.perpage, arrowsx)
+		};
+		let mut x = startx;
+		for (n, button) in buttons.iter().enumerate() {
+			for (m, &i) in button.iter().enumerate() {
+				self.elements[i].x = x;
+				self.elements[i].width = if m == 0 && shown.contains(&n) { width } else { 0 };
+			}
+			if shown.contains(&n) {
+				x += width + GAP;
+			}
+		}
+		let arrowlength = if vertical { BUTTONHEIGHT } else { ARROWLENGTH };
+		for (n, i) in self.arrows().enumerate() {
+			self.elements[i].x = arrowsx + n as i16 * arrowlength;
+			self.elements[i].width = arrowlength;
+		}
+	}
+
+	fn setarrows(&mut self, paging: bool) {
+		//Put the arrows in, or take them out. Only the notification area moves, as they go after the taskbar buttons.
+		let arrows = self.arrows();
+		if paging && arrows.is_empty() {
+			for forward in [true, false] {
+				self.elements.insert(arrows.start, Element { kind: PanelElement::Arrow(forward), state: ElementState::Ready, x: 0, width: 0 });
+			}
+		} else if !paging {
+			self.elements.drain(arrows);
+		}
+	}
+
+	pub fn page(&mut self, forward: bool) -> bool {
+		//Go a page on or back through the taskbar buttons. False if already at that end.
+		//The caller lays the panel out again, with groupwindows as usual, to show the new page.
+		if !self.canpage(forward) {
+			return false;
+		}
+		self.page = if forward { (self.page + self.perpage).min(self.buttons - self.perpage) } else { self.page.saturating_sub(self.perpage) };
+		true
+	}
+
+	pub fn canpage(&self, forward: bool) -> bool {
+		self.hidden(forward) > 0
+	}
+
+	pub fn hidden(&self, forward: bool) -> usize {
+		//How many taskbar buttons are off the page that way.
+		if forward { self.buttons - self.page - self.perpage } else { self.page }
+	}
+
+	pub fn groupwindows(&mut self, grouping: bool, classof: impl Fn(Window) -> String) {
+		//Lay out, then if the taskbar buttons don't fit at full length, windows of the same class share a button.
+		//The first of each group gets the button and the rest get no width. Windows without a class stay on their own.
+		self.reflow();
+		let windows = self.windows();
+		if !grouping || windows.is_empty() {
+			return;
+		}
+		let full = self.buttonlength();
+		let startx = self.elements[windows.start].x;
+		let tray = self.elements[self.notification()].x;
+		if windows.len() as i16 * (full + GAP) - GAP <= tray - startx {
+			return;
+		}
+		let mut groups: Vec<Vec<usize>> = Vec::new();
+		let mut classes: Vec<String> = Vec::new();
+		for i in windows {
+			let class = self.window(i).map(&classof).unwrap_or_default();
+			match classes.iter().position(|other| !class.is_empty() && *other == class) {
+				Some(group) => groups[group].push(i),
+				None => {
+					groups.push(vec![i]);
+					classes.push(class);
+				}
+			}
+		}
+		self.placebuttons(startx, tray, &groups);
+		groups.retain(|group| group.len() > 1);
+		self.groups = groups;
+	}
+
+	pub fn members(&self, index: usize) -> Vec<usize> {
+		//The taskbar buttons behind the button at index. Just index itself unless it leads a group.
+		self.groups.iter().find(|group| group[0] == index).cloned().unwrap_or_else(|| vec![index])
+	}
+
+	pub fn hit(&self, x: i16, y: i16) -> Option<usize> {
+		//Which element is under (x, y). Everything before the links counts as the Click button.
+		//The couple of pixels on the side facing the desktop don't count, but the screen edge does.
+		let across = if self.vertical() { x } else { y };
+		let dead = match self.edge {
+			PanelEdge::Bottom | PanelEdge::Right => across <= 2,
+			PanelEdge::Top | PanelEdge::Left => across >= self.thickness - 3,
+		};
+		if dead {
+			return None;
+		}
+		let position = self.along(x, y);
+		if position < self.elements[0].x + self.elements[0].width + GAP {
+			return Some(0);
+		}
+		self.elements.iter().position(|element| position >= element.x && position < element.x + element.width)
+	}
+
+	pub fn dropslot(&self, x: i16, index: usize) -> (usize, i16) {
+		//Where the link or taskbar button at index would go if dropped at x (along the panel): in front of the first one whose middle is past x.
+		//Returns the index to move it to and where to draw the insertion marker.
+		let area = self.area(index);
+		match area.clone().find(|&i| self.elements[i].width > 0 && x < self.elements[i].x + self.elements[i].width / 2) {
+			Some(i) => (i, self.elements[i].x - 1),
+			None => {
+				let last = &self.elements[area.clone().rev().find(|&i| self.elements[i].width > 0).unwrap_or(area.end - 1)];
+				(area.end, last.x + last.width)
+			}
+		}
+	}
+
+	pub fn setstate(&mut self, index: usize, new: ElementState) -> Option<Redraw> {
+		//Press, hover or release an element. Returns what to redraw, or None if nothing changed.
+		//Pressing or hovering pops the rest of its area out. Releasing sets the whole area back to ready.
+		let element = self.elements[index];
+		if element.state == new {
+			return None;
+		}
+		let area = self.area(index);
+		match new {
+			ElementState::Ready => {
+				for other in &mut self.elements[area] {
+					other.state = ElementState::Ready;
+				}
+				Some(self.elements[index].redraw())
+			}
+			ElementState::Pressed | ElementState::Hover if new == ElementState::Pressed || element.state == ElementState::Ready => {
+				for other in &mut self.elements[area] {
+					if other.state != ElementState::Ready {
+						other.state = ElementState::Reset;
+					}
+				}
+				self.elements[index].state = new;
+				Some(self.elements[index].redraw())
+			}
+			_ => None,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn link(label: &str) -> PanelIcon {
+		PanelIcon { label: label.to_string(), ..Default::default() }
+	}
+
+	fn bottom(width: i16) -> Panel {
+		Panel::new(PanelEdge::Bottom, 28, width, 600)
+	}
+
+	#[test]
+	fn emptypanel() {
+		let panel = bottom(800);
+		assert_eq!(panel.elements.len(), 2);
+		assert_eq!(panel.links(), 1..1);
+		assert_eq!(panel.windows(), 1..1);
+		assert_eq!((panel.elements[0].x, panel.elements[0].width), (CLICKX, CLICKWIDTH));
+		assert_eq!((panel.elements[1].x, panel.elements[1].width), (800 - 60 - 3, 60));
+	}
+
+	#[test]
+	fn linkssidebyside() {
+		let mut panel = bottom(800);
+		assert_eq!(panel.addlink(link("a")), 1);
+		assert_eq!(panel.addlink(link("b")), 2);
+		assert_eq!(panel.elements[1].x, LINKX);
+		assert_eq!(panel.elements[2].x, LINKX + LINKWIDTH);
+		assert_eq!(panel.link(2).map(|link| link.label.as_str()), Some("b"));
+	}
+
+	#[test]
+	fn trayiconswidenthenotificationarea() {
+		let mut panel = bottom(800);
+		panel.addtrayicon(link("first"));
+		panel.addtrayicon(link("second"));
+		let notification = panel.elements[panel.notification()];
+		assert_eq!(notification.width, 2 * TRAYICONWIDTH + 60);
+		assert_eq!(notification.x, 800 - notification.width - 3);
+		assert_eq!(panel.trayicons[0].label, "second");
+	}
+
+	#[test]
+	fn insertengagesthenewwindow() {
+		let mut panel = bottom(1024);
+		panel.addlink(link("a"));
+		assert_eq!(panel.insertwindow(100), 2);
+		assert_eq!(panel.insertwindow(200), 3);
+		assert_eq!(panel.windows(), 2..4);
+		assert_eq!(panel.elements[2].state, ElementState::Ready);
+		assert_eq!(panel.elements[3].state, ElementState::Engaged);
+		assert_eq!(panel.elements[2].x, LINKX + LINKWIDTH + 3);
+		assert_eq!(panel.elements[3].x, panel.elements[2].x + WINDOWBUTTONLENGTH + 3);
+		assert_eq!(panel.elements[3].width, WINDOWBUTTONLENGTH);
+	}
+
+	#[test]
+	fn removeclosesthegap() {
+		let mut panel = bottom(1024);
+		panel.insertwindow(100);
+		panel.insertwindow(200);
+		panel.insertwindow(300);
+		assert_eq!(panel.removewindow(200), Some(2));
+		assert_eq!(panel.removewindow(200), None);
+		assert_eq!(panel.window(2), Some(300));
+		assert_eq!(panel.elements[2].x, panel.elements[1].x + WINDOWBUTTONLENGTH + 3);
+		assert_eq!(panel.elements[panel.notification()].kind, PanelElement::Notification);
+	}
+
+	#[test]
+	fn reflowshrinksbuttonsthatdonotfit() {
+		let mut panel = bottom(640);
+		for window in 1..=6 {
+			panel.insertwindow(window);
+		}
+		let windows = panel.windows();
+		let width = panel.elements[windows.start].width;
+		assert!(width < WINDOWBUTTONLENGTH);
+		let last = panel.elements[windows.end - 1];
+		assert!(last.x + last.width <= panel.elements[panel.notification()].x);
+		assert!(panel.elements[windows].iter().all(|element| element.width == width));
+	}
+
+	#[test]
+	fn capacityisnotfixed() {
+		let mut panel = bottom(4000);
+		for window in 0..300 {
+			panel.insertwindow(window);
+		}
+		assert_eq!(panel.windows().len(), 300);
+		assert_eq!(panel.windowindex(299), Some(300));
+	}
+
+	#[test]
+	fn buttonsneverreachthetray() {
+		//Right where full length buttons stop fitting once the gaps between them count.
+		for width in 400..=560 {
+			let mut panel = bottom(width);
+			for window in 1..=3 {
+				panel.insertwindow(window);
+				let last = panel.elements[panel.windows().end - 1];
+				assert!(last.x + last.width <= panel.elements[panel.notification()].x, "{} wide with {} windows", width, window);
+			}
+		}
+	}
+
+	#[test]
+	fn overflowingtaskbarpages() {
+		let mut panel = bottom(640);
+		for window in 1..=20 {
+			panel.insertwindow(window);
+		}
+		assert_eq!(panel.windows(), 1..21);
+		assert_eq!(panel.arrows(), 21..23);
+		assert_eq!(panel.elements[21].kind, PanelElement::Arrow(false));
+		let shown: Vec<usize> = panel.windows().filter(|&i| panel.elements[i].width > 0).collect();
+		assert_eq!(shown[0], 1);
+		assert!(panel.elements[shown[0]].width >= MINWINDOWBUTTONLENGTH);
+		let forward = panel.elements[22];
+		assert!(forward.x + forward.width <= panel.elements[panel.notification()].x);
+		assert_eq!(panel.hit(forward.x + 1, 10), Some(22));
+		assert!(!panel.canpage(false));
+		assert_eq!(panel.hidden(true), 20 - shown.len());
+		assert!(panel.page(true));
+		panel.reflow();
+		assert_eq!(panel.windows().find(|&i| panel.elements[i].width > 0), Some(1 + shown.len()));
+		while panel.page(true) {
+			panel.reflow();
+		}
+		assert!(panel.elements[20].width > 0);
+		for window in 1..=17 {
+			panel.removewindow(window);
+		}
+		assert!(panel.arrows().is_empty());
+		assert!(panel.elements[panel.windows()].iter().all(|element| element.width == WINDOWBUTTONLENGTH));
+	}
+
+	#[test]
+	fn movereordersandreflows() {
+		let mut panel = bottom(1024);
+		panel.insertwindow(100);
+		panel.insertwindow(200);
+		panel.insertwindow(300);
+		let x = panel.elements[1].x;
+		panel.moveelement(3, 1);
+		assert_eq!(panel.window(1), Some(300));
+		assert_eq!(panel.window(2), Some(100));
+		assert_eq!(panel.elements[1].x, x);
+		panel.moveelement(1, 4);
+		assert_eq!(panel.window(3), Some(300));
+	}
+
+	#[test]
+	fn groupsonlywhenoverflowing() {
+		let mut panel = bottom(640);
+		panel.insertwindow(1);
+		panel.insertwindow(2);
+		panel.groupwindows(true, |_| "XTerm".to_string());
+		assert!(panel.groups.is_empty());
+		for window in 3..=6 {
+			panel.insertwindow(window);
+		}
+		panel.groupwindows(true, |window| if window % 2 == 0 { "XTerm".to_string() } else { String::new() });
+		assert_eq!(panel.groups, vec![vec![2, 4, 6]]);
+		assert_eq!(panel.members(2), vec![2, 4, 6]);
+		assert_eq!(panel.members(1), vec![1]);
+		assert_eq!(panel.elements[4].width, 0);
+		panel.groupwindows(false, |_| "XTerm".to_string());
+		assert!(panel.groups.is_empty());
+		assert!(panel.elements[4].width > 0);
+	}
+
+	#[test]
+	fn hitfindselements() {
+		let mut panel = bottom(1024);
+		panel.addlink(link("a"));
+		panel.insertwindow(100);
+		assert_eq!(panel.hit(10, 1), None);
+		assert_eq!(panel.hit(10, 10), Some(0));
+		assert_eq!(panel.hit(LINKX + 1, 10), Some(1));
+		assert_eq!(panel.hit(panel.elements[2].x + 5, 10), Some(2));
+		assert_eq!(panel.hit(panel.elements[2].x - 1, 10), None);
+		assert_eq!(panel.hit(1020, 10), Some(3));
+	}
+
+	#[test]
+	fn pressingpopstherestout() {
+		let mut panel = bottom(1024);
+		panel.insertwindow(100);
+		panel.insertwindow(200);
+		assert_eq!(panel.setstate(1, ElementState::Pressed), Some(Redraw::WindowStates));
+		assert_eq!(panel.elements[2].state, ElementState::Reset);
+		assert_eq!(panel.setstate(1, ElementState::Pressed), None);
+		assert_eq!(panel.setstate(1, ElementState::Hover), None);
+		assert_eq!(panel.setstate(1, ElementState::Ready), Some(Redraw::Windows));
+		assert!(panel.elements[panel.windows()].iter().all(|element| element.state == ElementState::Ready));
+	}
+
+	#[test]
+	fn dropslots() {
+		let mut panel = bottom(1024);
+		panel.addlink(link("a"));
+		panel.addlink(link("b"));
+		assert_eq!(panel.dropslot(LINKX, 1), (1, LINKX - 1));
+		assert_eq!(panel.dropslot(LINKX + LINKWIDTH + 20, 1), (3, LINKX + 2 * LINKWIDTH));
+	}
+
+	#[test]
+	fn thicknesshasaminimum() {
+		assert_eq!(thickness(PanelEdge::Bottom, 10), 28);
+		assert_eq!(thickness(PanelEdge::Top, 40), 40);
+		assert_eq!(thickness(PanelEdge::Left, 28), 66);
+		assert_eq!(thickness(PanelEdge::Right, 100), 100);
+	}
+
+	#[test]
+	fn geometryfollowstheedge() {
+		let panel = Panel::new(PanelEdge::Bottom, 28, 800, 600);
+		assert_eq!(panel.geometry(800, 600, true), (0, 572, 800, 28));
+		assert_eq!(panel.geometry(800, 600, false), (0, 598, 800, 28));
+		assert_eq!(panel.workarea(800, 600, false), (0, 0, 800, 572));
+		assert_eq!(panel.workarea(800, 600, true), (0, 0, 800, 600));
+		let panel = Panel::new(PanelEdge::Top, 32, 800, 600);
+		assert_eq!(panel.geometry(800, 600, false), (0, -30, 800, 32));
+		assert_eq!(panel.workarea(800, 600, false), (0, 32, 800, 568));
+		let panel = Panel::new(PanelEdge::Left, 100, 800, 600);
+		assert_eq!(panel.geometry(800, 600, true), (0, 0, 100, 600));
+		assert_eq!(panel.geometry(800, 600, false), (-98, 0, 100, 600));
+		assert_eq!(panel.workarea(800, 600, false), (100, 0, 700, 600));
+		let panel = Panel::new(PanelEdge::Right, 100, 800, 600);
+		assert_eq!(panel.geometry(800, 600, false), (798, 0, 100, 600));
+		assert_eq!(panel.workarea(800, 600, false), (0, 0, 700, 600));
+	}
+
+	#[test]
+	fn besideopensoffthepanel() {
+		let mut panel = Panel::new(PanelEdge::Bottom, 28, 800, 600);
+		panel.insertwindow(100);
+		assert_eq!(panel.beside(0, 161, 201, 800, 600), (0, 572 - 201));
+		assert_eq!(panel.beside(1, 161, 201, 800, 600), (panel.elements[1].x, 572 - 201));
+		assert_eq!(panel.beside(1, 161, 1000, 800, 600).1, 0);
+		let panel = Panel::new(PanelEdge::Top, 28, 800, 600);
+		assert_eq!(panel.beside(0, 161, 201, 800, 600), (0, 28));
+		let panel = Panel::new(PanelEdge::Right, 80, 800, 600);
+		assert_eq!(panel.beside(0, 161, 201, 800, 600), (720 - 161, 0));
+		assert_eq!(panel.besidepoint(300, 100, 19, 800, 600), (720 - 100, 300));
+		assert_eq!(panel.besidepoint(590, 100, 19, 800, 600), (720 - 100, 600 - 19));
+	}
+
+	#[test]
+	fn tallerpanelscentretheirbuttons() {
+		let panel = Panel::new(PanelEdge::Bottom, 28, 800, 600);
+		assert_eq!(panel.rect(0), (CLICKX, 4, CLICKWIDTH, BUTTONHEIGHT));
+		assert_eq!(panel.clock(), (800, 20));
+		let panel = Panel::new(PanelEdge::Bottom, 40, 800, 600);
+		assert_eq!(panel.rect(0).1, 10);
+	}
+
+	#[test]
+	fn longerclocksmakeroom() {
+		let mut panel = Panel::new(PanelEdge::Bottom, 28, 800, 600);
+		let tray = panel.rect(panel.notification());
+		panel.setclock(8);
+		assert_eq!(panel.rect(panel.notification()), tray);
+		panel.setclock(19);
+		assert_eq!(panel.rect(panel.notification()).0, tray.0 - 66);
+		assert_eq!(panel.thickness, 28);
+		let mut panel = Panel::new(PanelEdge::Left, 66, 800, 600);
+		panel.setclock(19);
+		assert_eq!(panel.thickness, 132);
+	}
+
+	#[test]
+	fn sidepanelsstackdownwards() {
+		let mut panel = Panel::new(PanelEdge::Left, 100, 800, 600);
+		panel.addlink(link("a"));
+		panel.insertwindow(100);
+		panel.insertwindow(200);
+		assert_eq!(panel.rect(0), (3, CLICKX, 94, BUTTONHEIGHT));
+		assert_eq!(panel.rect(1), (3, CLICKX + BUTTONHEIGHT + 4, 94, BUTTONHEIGHT));
+		assert_eq!(panel.rect(3).1, panel.rect(2).1 + BUTTONHEIGHT + 3);
+		assert_eq!(panel.rect(3).2, 94);
+		assert_eq!(panel.hit(50, panel.rect(3).1 + 5), Some(3));
+		assert_eq!(panel.hit(98, panel.rect(3).1 + 5), None);
+		assert_eq!(panel.hit(0, 5), Some(0));
+		let notification = panel.elements[panel.notification()];
+		assert_eq!(notification.x + notification.width, 600 - 3);
+	}
+
+	#[test]
+	fn sidetrayiconswrap() {
+		let mut panel = Panel::new(PanelEdge::Right, 66, 800, 600);
+		for n in 0..4 {
+			panel.addtrayicon(link(&n.to_string()));
+		}
+		let (x, y, _, height) = panel.rect(panel.notification());
+		assert_eq!(height, 2 * TRAYICONWIDTH + 24);
+		assert_eq!(panel.trayicon(0), (x + 3, y + 3));
+		assert_eq!(panel.trayicon(2), (x + 3, y + 3 + TRAYICONWIDTH));
+		assert_eq!(panel.trayiconat(x + 3 + TRAYICONWIDTH + 1, y + 3 + TRAYICONWIDTH), Some(3));
+		assert_eq!(panel.trayiconat(x + 3, y + 3 + 2 * TRAYICONWIDTH), None);
+	}
+
+	#[test]
+	fn dockedtrayiconscomeandgo() {
+		let mut panel = bottom(1024);
+		panel.addtrayicon(link("volume"));
+		let (x, _, width, _) = panel.rect(panel.notification());
+		panel.addtrayicon(PanelIcon { window: Some(500), ..Default::default() });
+		assert_eq!(panel.trayicons[0].window, Some(500));
+		assert_eq!(panel.rect(panel.notification()).0, x - TRAYICONWIDTH);
+		assert_eq!(panel.rect(panel.notification()).2, width + TRAYICONWIDTH);
+		assert!(panel.removetrayicon(500));
+		assert!(!panel.removetrayicon(500));
+		assert_eq!(panel.rect(panel.notification()).0, x);
+		assert_eq!(panel.trayicons[0].label, "volume");
+	}
+
+	#[test]
+	fn sidebuttonsgroupinsteadofshrinking() {
+		let mut panel = Panel::new(PanelEdge::Left, 80, 800, 200);
+		for window in 1..=8 {
+			panel.insertwindow(window);
+		}
+		assert!(panel.elements[panel.windows()].iter().all(|element| element.width == BUTTONHEIGHT || element.width == 0));
+		assert_eq!(panel.arrows().len(), 2);
+		panel.groupwindows(true, |window| if window > 2 { "XTerm".to_string() } else { String::new() });
+		assert_eq!(panel.groups, vec![vec![3, 4, 5, 6, 7, 8]]);
+		assert_eq!(panel.elements[3].width, BUTTONHEIGHT);
+		assert!(panel.arrows().is_empty());
+	}
+}
@@ -25,7 +25,7 @@ use crate::trundle::drawtitletext;
 use crate::trundle::squishtext;
 use crate::trundle::drawbookerframe;
 use crate::drawdepressedbumpyframe;
-use crate::insertpanelwindow;
+use crate::panel::{ElementState, Panel, Redraw};
 use crate::focuswindow;
 use crate::drawpng;
 use crate::drawbumpyframe;
@@ -33,17 +33,17 @@ use crate::createwindow;
 use crate::WindowManager;
 
 
-pub fn startprogram(xconnection: &impl Connection, screen: &Screen, panel: Window, width: i16, height: i16, panelindex: &mut [u8; 6], panelitems: &mut [[u8; 1]; 128], panelcoordinates: &mut [[i16; 2]; 128], panelwindows: &mut [[u32; 1]; 128], panelicons: &mut [[String; 4]; 32], gc_highlight: Gcontext, gc_lowlight: Gcontext, gc_highbackground: Gcontext, gc_lowbackground: Gcontext, gc_titlebar: Gcontext, gc_titlebartext: Gcontext, wm: &mut WindowManager, poly_lowlight: &mut Vec<Segment>, poly_index: &mut Vec<u8>, poly_windoworcolour: &mut Vec<u32>) -> u8 { 
+pub fn startprogram(xconnection: &impl Connection, screen: &Screen, panel: Window, width: i16, height: i16, panelstate: &mut Panel, gc_highlight: Gcontext, gc_lowlight: Gcontext, gc_highbackground: Gcontext, gc_lowbackground: Gcontext, gc_titlebar: Gcontext, gc_titlebartext: Gcontext, gc_attention: Gcontext, gc_attentiontext: Gcontext, wm: &mut WindowManager, poly_lowlight: &mut Vec<Segment>, poly_index: &mut Vec<u8>, poly_windoworcolour: &mut Vec<u32>) -> Redraw { 
 	match createwindow(xconnection, screen, 50, 50, 560, 340, b"Booker", width, height, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, wm) {
 		Ok(booker) => {
-			insertpanelwindow(panelindex, booker, panelitems, panelcoordinates, panelwindows, panelicons);
+			panelstate.insertwindow(booker);
 			basicscreen(xconnection, booker, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground);
-			match focuswindow(wm, xconnection, panel, booker, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, poly_lowlight, poly_index, poly_windoworcolour) {
-				Ok(_) => 40,
-				Err(_) => 0,
+			match focuswindow(wm, xconnection, panel, booker, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, gc_attention, gc_attentiontext, poly_lowlight, poly_index, poly_windoworcolour) {
+				Ok(_) => Redraw::Windows,
+				Err(_) => Redraw::Click(ElementState::Ready),
 			}
 		},
-		Err(_) => 0,
+		Err(_) => Redraw::Click(ElementState::Ready),
 	}
 }
 
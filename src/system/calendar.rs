@@ -0,0 +1,335 @@
+use std::error::Error;
+use x11rb::{
+    connection::Connection,
+    protocol::xproto::*,
+    COPY_DEPTH_FROM_PARENT,
+};
+
+use crate::panel::Panel;
+use crate::system::clock::{civil, days, TimeZone};
+use crate::system::settings::{ClockHours, SETTINGS};
+use crate::trundle::{drawbumpyframe, drawdepressedframe, drawsystemframe, COLOURS, HIGHBACKGROUND_COLOUR};
+
+pub const ALARMS: &str = "alarms.txt";
+const PADDING: i16 = 6;
+const CELLWIDTH: i16 = 24;
+const CELLHEIGHT: i16 = 18;
+const BUTTON: i16 = 20;
+const WIDTH: i16 = CELLWIDTH * 7 + PADDING * 2;
+const WEEKDAYS: i16 = PADDING + BUTTON + 4; //Top of the row of day names.
+const GRID: i16 = WEEKDAYS + 16; //Top of the first week.
+const ALARMROW: i16 = GRID + CELLHEIGHT * 6 + 6;
+const HEIGHT: i16 = ALARMROW + BUTTON + PADDING;
+const MONTHS: [&str; 12] = ["January", "February", "March", "April", "May", "June", "July", "August", "September", "October", "November", "December"];
+
+//A month at a time, opened by clicking the clock. Weeks start on Monday.
+//Pick a day and a time along the bottom and Set puts an alarm there, or takes away the one already there.
+pub struct Calendar {
+	pub window: Window,
+	year: i64, //Month showing.
+	month: u32,
+	today: (i64, u32, u32),
+	selected: (i64, u32, u32), //Day the alarm goes on.
+	hour: u32,
+	minute: u32,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Target {
+	Previous,
+	Next,
+	Day(u32),
+	Hour,
+	Minute,
+	Set,
+}
+
+//What the calendar draws with.
+pub struct Palette {
+	pub gc_highlight: Gcontext,
+	pub gc_lowlight: Gcontext,
+	pub gc_highbackground: Gcontext,
+	pub gc_lowbackground: Gcontext,
+	pub gc_titlebar: Gcontext, //Today stands out in the focused title bar's colours.
+	pub gc_titlebartext: Gcontext,
+}
+
+//One-shot alarms, as seconds since 1970, soonest first. Kept in a file so they last through a restart.
+pub struct Alarms {
+	pub times: Vec<i64>,
+	path: String,
+}
+
+impl Calendar {
+	pub fn open<C: Connection>(xconnection: &C, screen: &Screen, panelstate: &Panel, local: i64) -> Result<Calendar, Box<dyn Error>> {
+		//Open this month next to the clock, with the alarm time at the start of the next hour.
+		let (clockx, clocky) = panelstate.clock();
+		let (x, y) = panelstate.besidepoint(panelstate.along(clockx - WIDTH, clocky), WIDTH + 1, HEIGHT + 1, screen.width_in_pixels as i16, screen.height_in_pixels as i16);
+
+		let window = xconnection.generate_id()?;
+		xconnection.create_window(COPY_DEPTH_FROM_PARENT, window, screen.root, x, y, WIDTH as u16 + 1, HEIGHT as u16 + 1, 0, WindowClass::INPUT_OUTPUT, 0, &CreateWindowAux::new().background_pixel(COLOURS[HIGHBACKGROUND_COLOUR]).override_redirect(1).event_mask(EventMask::EXPOSURE | EventMask::BUTTON_PRESS))?;
+		xconnection.map_window(window)?;
+		xconnection.configure_window(window, &ConfigureWindowAux::new().stack_mode(StackMode::ABOVE))?;
+		let today = civil(local.div_euclid(86400));
+		let (selected, hour) = nexthour(local);
+		Ok(Calendar { window, year: today.0, month: today.1, today, selected, hour, minute: 0 })
+	}
+
+	pub fn draw<C: Connection>(&self, xconnection: &C, alarms: &Alarms, timezone: &TimeZone, palette: &Palette) -> Result<(), Box<dyn Error>> {
+		let window = self.window;
+		let &Palette { gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext } = palette;
+		drawsystemframe(xconnection, window, WIDTH, 0, WIDTH, HEIGHT, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground);
+		let mut poly_lowlight = Vec::new();
+
+		//Month and the buttons either side.
+		for (target, label) in [(Target::Previous, "<"), (Target::Next, ">"), (Target::Hour, self.hourtext().as_str()), (Target::Minute, format!("{:02}", self.minute).as_str()), (Target::Set, if alarms.times.contains(&self.alarm(timezone)) { "Clear" } else { "Set" })] {
+			let (x, y, width, height) = rect(self.year, self.month, target);
+			drawbumpyframe(xconnection, window, x, y, width, height, gc_highlight, gc_highbackground, gc_lowbackground, &mut poly_lowlight)?;
+			xconnection.image_text8(window, gc_lowlight, x + (width - label.len() as i16 * 6) / 2, y + height - 6, label.as_bytes())?;
+		}
+		let title = format!("{} {}", MONTHS[self.month as usize - 1], self.year);
+		xconnection.image_text8(window, gc_lowlight, (WIDTH - title.len() as i16 * 6) / 2, PADDING + BUTTON - 6, title.as_bytes())?;
+		for (n, name) in ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"].iter().enumerate() {
+			xconnection.image_text8(window, gc_lowlight, PADDING + n as i16 * CELLWIDTH + 6, WEEKDAYS + 11, name.as_bytes())?;
+		}
+
+		//Days. Today stands out like a focused title bar, the selected day is sunk in, and days with an alarm are underlined.
+		for day in 1..=monthlength(self.year, self.month) {
+			let (x, y, _, _) = rect(self.year, self.month, Target::Day(day));
+			let label = format!("{:>2}", day);
+			if (self.year, self.month, day) == self.today {
+				xconnection.poly_fill_rectangle(window, gc_titlebar, &[Rectangle { x: x + 1, y: y + 1, width: CELLWIDTH as u16 - 2, height: CELLHEIGHT as u16 - 2 }])?;
+				xconnection.image_text8(window, gc_titlebartext, x + 6, y + 13, label.as_bytes())?;
+			} else {
+				xconnection.image_text8(window, gc_lowlight, x + 6, y + 13, label.as_bytes())?;
+			}
+			if (self.year, self.month, day) == self.selected {
+				drawdepressedframe(xconnection, window, x + CELLWIDTH - 1, y, CELLWIDTH - 1, CELLHEIGHT - 1, gc_highlight, gc_lowbackground)?;
+			}
+			if alarms.on(timezone, (self.year, self.month, day)) {
+				poly_lowlight.push(Segment { x1: x + 6, y1: y + 15, x2: x + 17, y2: y + 15 });
+			}
+		}
+
+		//Which day the alarm is for, between the time and Set.
+		let (_, month, day) = self.selected;
+		xconnection.image_text8(window, gc_lowlight, PADDING + 76, ALARMROW + BUTTON - 6, format!("{} {}", day, &MONTHS[month as usize - 1][..3]).as_bytes())?;
+		xconnection.poly_segment(window, gc_lowlight, &poly_lowlight)?;
+		Ok(())
+	}
+
+	pub fn click(&mut self, x: i16, y: i16, button: u8, alarms: &mut Alarms, timezone: &TimeZone, now: i64) -> bool {
+		//Left click or scrolling up goes forward, right click or scrolling down goes back. Returns whether it needs drawing again.
+		//Set won't put an alarm at now or before, as it would only go off straight away.
+		let Some(target) = target(self.year, self.month, x, y) else {
+			return false;
+		};
+		let back = button == 3 || button == 5;
+		match target {
+			Target::Previous => self.turn(-1),
+			Target::Next => self.turn(1),
+			Target::Day(day) => self.selected = (self.year, self.month, day),
+			Target::Hour => self.hour = (self.hour + if back { 23 } else { 1 }) % 24,
+			Target::Minute => self.minute = (self.minute + if back { 55 } else { 5 }) % 60,
+			Target::Set if button == 1 => {
+				let at = self.alarm(timezone);
+				if at <= now && !alarms.times.contains(&at) {
+					return false;
+				}
+				alarms.toggle(at);
+			}
+			Target::Set => return false,
+		}
+		true
+	}
+
+	pub fn close<C: Connection>(&self, xconnection: &C) {
+		let _ = xconnection.destroy_window(self.window);
+	}
+
+	fn turn(&mut self, months: i32) {
+		let index = self.year * 12 + self.month as i64 - 1 + months as i64;
+		(self.year, self.month) = (index.div_euclid(12), index.rem_euclid(12) as u32 + 1);
+	}
+
+	fn alarm(&self, timezone: &TimeZone) -> i64 {
+		//The alarm the bottom row describes, in seconds since 1970.
+		let (year, month, day) = self.selected;
+		utc(timezone, days(year, month, day) * 86400 + self.hour as i64 * 3600 + self.minute as i64 * 60)
+	}
+
+	fn hourtext(&self) -> String {
+		//Same hours as the clock.
+		match SETTINGS.clock {
+			ClockHours::Twelve => format!("{}{}", match self.hour % 12 { 0 => 12, hour => hour }, if self.hour < 12 { "am" } else { "pm" }),
+			ClockHours::TwentyFour => format!("{:02}", self.hour),
+		}
+	}
+}
+
+impl Alarms {
+	pub fn load(path: &str) -> Alarms {
+		//One time per line. No file, no alarms.
+		let mut times: Vec<i64> = std::fs::read_to_string(path).unwrap_or_default().lines().filter_map(|line| line.trim().parse().ok()).collect();
+		times.sort();
+		Alarms { times, path: path.to_string() }
+	}
+
+	pub fn next(&self) -> Option<i64> {
+		self.times.first().copied()
+	}
+
+	pub fn toggle(&mut self, at: i64) {
+		//Set an alarm at at, or take it away if there is one.
+		match self.times.binary_search(&at) {
+			Ok(n) => {
+				self.times.remove(n);
+			}
+			Err(n) => self.times.insert(n, at),
+		}
+		self.save();
+	}
+
+	pub fn due(&mut self, now: i64) -> Vec<i64> {
+		//Take out the alarms that have gone off, including any that went off while we weren't running.
+		let due: Vec<i64> = self.times.drain(..self.times.partition_point(|&at| at <= now)).collect();
+		if !due.is_empty() {
+			self.save();
+		}
+		due
+	}
+
+	pub fn on(&self, timezone: &TimeZone, (year, month, day): (i64, u32, u32)) -> bool {
+		self.times.iter().any(|&at| civil(timezone.localtime(at).div_euclid(86400)) == (year, month, day))
+	}
+
+	fn save(&self) {
+		let contents: String = self.times.iter().map(|at| format!("{}\n", at)).collect();
+		if let Err(error) = std::fs::write(&self.path, contents) {
+			eprintln!("Couldn't save the alarms to {}: {}", self.path, error);
+		}
+	}
+}
+
+fn utc(timezone: &TimeZone, local: i64) -> i64 {
+	//Local time back to UTC. The offset can change either side of a daylight saving change, so take it from a guess first.
+	let guess = local - timezone.offset(local);
+	local - timezone.offset(guess)
+}
+
+fn nexthour(local: i64) -> ((i64, u32, u32), u32) {
+	//The day and hour at the start of the next hour after local, which is tomorrow from 23:00.
+	let next = (local.div_euclid(3600) + 1) * 3600;
+	(civil(next.div_euclid(86400)), (next.rem_euclid(86400) / 3600) as u32)
+}
+
+fn monthlength(year: i64, month: u32) -> u32 {
+	let next = if month == 12 { days(year + 1, 1, 1) } else { days(year, month + 1, 1) };
+	(next - days(year, month, 1)) as u32
+}
+
+fn rect(year: i64, month: u32, target: Target) -> (i16, i16, i16, i16) {
+	//Where each part of the calendar is, as (x, y, width, height), with the days laid out for year and month.
+	match target {
+		Target::Previous => (PADDING, PADDING, BUTTON, BUTTON - 2),
+		Target::Next => (WIDTH - PADDING - BUTTON, PADDING, BUTTON, BUTTON - 2),
+		Target::Day(day) => {
+			let cell = (day as i64 - 1 + offset(year, month)) as i16;
+			(PADDING + cell % 7 * CELLWIDTH, GRID + cell / 7 * CELLHEIGHT, CELLWIDTH, CELLHEIGHT)
+		}
+		Target::Hour => (PADDING, ALARMROW, 36, BUTTON),
+		Target::Minute => (PADDING + 40, ALARMROW, 28, BUTTON),
+		Target::Set => (WIDTH - PADDING - 54, ALARMROW, 54, BUTTON),
+	}
+}
+
+fn target(year: i64, month: u32, x: i16, y: i16) -> Option<Target> {
+	//What's under x, y.
+	let inside = |(left, top, width, height): (i16, i16, i16, i16)| x >= left && x < left + width && y >= top && y < top + height;
+	if let Some(target) = [Target::Previous, Target::Next, Target::Hour, Target::Minute, Target::Set].into_iter().find(|&target| inside(rect(year, month, target))) {
+		return Some(target);
+	}
+	if !(PADDING..WIDTH - PADDING).contains(&x) || !(GRID..GRID + CELLHEIGHT * 6).contains(&y) {
+		return None;
+	}
+	let cell = ((y - GRID) / CELLHEIGHT * 7 + (x - PADDING) / CELLWIDTH) as i64;
+	let day = cell - offset(year, month) + 1;
+	(day >= 1 && day <= monthlength(year, month) as i64).then_some(Target::Day(day as u32))
+}
+
+fn offset(year: i64, month: u32) -> i64 {
+	//How many cells along the first of the month is. 1 January 1970 was a Thursday.
+	(days(year, month, 1) + 3).rem_euclid(7)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn monthsstartontherightday() {
+		assert_eq!(offset(2026, 10), 3); //Thursday.
+		assert_eq!(offset(2026, 6), 0); //Monday.
+		assert_eq!(offset(2026, 11), 6); //Sunday.
+		assert_eq!(monthlength(2024, 2), 29);
+		assert_eq!(monthlength(2026, 12), 31);
+	}
+
+	#[test]
+	fn clickinglandsonadayorabutton() {
+		//1 October 2026 is in the fourth cell of the first week.
+		let (x, y, _, _) = rect(2026, 10, Target::Day(1));
+		assert_eq!((x, y), (PADDING + CELLWIDTH * 3, GRID));
+		assert_eq!(target(2026, 10, x + 2, y + 2), Some(Target::Day(1)));
+		let (x, y, _, _) = rect(2026, 10, Target::Day(31));
+		assert_eq!(target(2026, 10, x + CELLWIDTH - 1, y + CELLHEIGHT - 1), Some(Target::Day(31)));
+		assert_eq!(target(2026, 10, PADDING + 1, GRID + 1), None);
+		assert_eq!(target(2026, 10, PADDING + 1, PADDING + 1), Some(Target::Previous));
+		assert_eq!(target(2026, 10, WIDTH - PADDING - 1, ALARMROW + 1), Some(Target::Set));
+	}
+
+	#[test]
+	fn alarmscomeandgo() {
+		let path = std::env::temp_dir().join(format!("tullamore-alarms-{}", std::process::id())).to_string_lossy().to_string();
+		let _ = std::fs::remove_file(&path);
+		let mut alarms = Alarms::load(&path);
+		alarms.toggle(300);
+		alarms.toggle(100);
+		alarms.toggle(200);
+		alarms.toggle(200);
+		assert_eq!(Alarms::load(&path).times, [100, 300]);
+		assert!(alarms.on(&TimeZone::utc(), (1970, 1, 1)));
+		assert_eq!(alarms.due(150), [100]);
+		assert_eq!(alarms.next(), Some(300));
+		assert_eq!(Alarms::load(&path).times, [300]);
+		let _ = std::fs::remove_file(&path);
+	}
+
+	#[test]
+	fn alarmsstartinthefuture() {
+		assert_eq!(nexthour(days(2026, 10, 19) * 86400 + 9 * 3600 + 59), ((2026, 10, 19), 10));
+		assert_eq!(nexthour(days(2026, 12, 31) * 86400 + 23 * 3600 + 30 * 60), ((2027, 1, 1), 0));
+
+		//Set does nothing for a time that has already gone.
+		let path = std::env::temp_dir().join(format!("tullamore-pastalarms-{}", std::process::id())).to_string_lossy().to_string();
+		let _ = std::fs::remove_file(&path);
+		let mut alarms = Alarms::load(&path);
+		let now = days(2026, 10, 19) * 86400 + 12 * 3600;
+		let mut calendar = Calendar { window: 0, year: 2026, month: 10, today: (2026, 10, 19), selected: (2026, 10, 19), hour: 12, minute: 0 };
+		let (x, y, _, _) = rect(2026, 10, Target::Set);
+		assert!(!calendar.click(x + 1, y + 1, 1, &mut alarms, &TimeZone::utc(), now));
+		calendar.minute = 5;
+		assert!(calendar.click(x + 1, y + 1, 1, &mut alarms, &TimeZone::utc(), now));
+		assert_eq!(alarms.times, [now + 300]);
+		let _ = std::fs::remove_file(&path);
+	}
+
+	#[test]
+	fn localtimesgobacktoutc() {
+		let sydney = TimeZone::posix("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+		let local = days(2027, 1, 1) * 86400 + 9 * 3600;
+		assert_eq!(sydney.localtime(utc(&sydney, local)), local);
+		let local = days(2027, 7, 1) * 86400 + 9 * 3600;
+		assert_eq!(utc(&sydney, local), local - 36000);
+	}
+}
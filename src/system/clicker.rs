@@ -31,7 +31,7 @@ use crate::trundle::drawsystemframe;
 use crate::trundle::drawclickbutton;
 use crate::drawdepressedbumpyframe;
 use crate::drawdepressedframe;
-use crate::insertpanelwindow;
+use crate::panel::Panel;
 use crate::focuswindow;
 use crate::drawpng;
 use crate::drawpngcover;
@@ -57,30 +57,17 @@ const ITEM: i16 = 20;
 const SEARCH: i16 = 30;
 const OFFSET: i16 = 3;
 
-pub fn startprogram(xconnection: &impl Connection, screen: &Screen, panel: Window, clickmenuitems: &[[String; 3]; 16], clickmenusize: &u8, screenwidth: &i16, screenheight: &i16, gc_highlight: Gcontext, gc_lowlight: Gcontext, gc_highbackground: Gcontext, gc_lowbackground: Gcontext, gc_titlebar: Gcontext, gc_titlebartext: Gcontext, wm: &mut WindowManager, poly_lowlight: &mut Vec<Segment>, poly_index: &mut Vec<u8>, poly_windoworcolour: &mut Vec<u32>) -> Window {
+pub fn startprogram(xconnection: &impl Connection, screen: &Screen, panel: Window, panelstate: &Panel, clickmenuitems: &[[String; 3]; 16], clickmenusize: &u8, screenwidth: &i16, screenheight: &i16, gc_highlight: Gcontext, gc_lowlight: Gcontext, gc_highbackground: Gcontext, gc_lowbackground: Gcontext, gc_titlebar: Gcontext, gc_titlebartext: Gcontext, gc_attention: Gcontext, gc_attentiontext: Gcontext, wm: &mut WindowManager, poly_lowlight: &mut Vec<Segment>, poly_index: &mut Vec<u8>, poly_windoworcolour: &mut Vec<u32>) -> Window {
 
-    let mut clickerheight: i16 = OFFSET + OFFSET;
-
-
-
-	//Loop through clickmenuitems to determine the size of height.
-	//No Divider = 20, Dividier = 6
-    for i in 0..(*clickmenusize as usize) {
-        let label = &clickmenuitems[i][0];
-        if label == "Divider" {
-            clickerheight += DIVIDER;
-		} else if label == "Search" {
-			clickerheight += SEARCH;
-        } else {
-            clickerheight += ITEM;
-        }
-    }
+    let clickerheight = clickmenuheight(clickmenuitems, clickmenusize);
 
 	const WIDTH: i16 = 160;
 	const STARTX: i16 = WIDTH;
 	const STARTY: i16 = 0;
 
-	match createframelesswindow(xconnection, screen, 0, screenheight - clickerheight - 29, WIDTH as u16 + 1, clickerheight as u16 + 1, b"Clicker", 500, 500, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, wm) {
+	//Opens off the end of the panel with the Click button.
+	let (clickerx, clickery) = panelstate.beside(0, WIDTH + 1, clickerheight + 1, *screenwidth, *screenheight);
+	match createframelesswindow(xconnection, screen, clickerx, clickery, WIDTH as u16 + 1, clickerheight as u16 + 1, b"Clicker", 500, 500, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, wm) {
 		Ok(clicker) => {
 			//Draw menu...
 			
@@ -88,7 +75,7 @@ pub fn startprogram(xconnection: &impl Connection, screen: &Screen, panel: Windo
 			drawclickmenu(&xconnection, clicker, clickmenuitems, clickmenusize, STARTX, STARTY, WIDTH, clickerheight, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext);
 			
 			
-			match focuswindow(wm, xconnection, panel, clicker, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, poly_lowlight, poly_index, poly_windoworcolour) {
+			match focuswindow(wm, xconnection, panel, clicker, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, gc_attention, gc_attentiontext, poly_lowlight, poly_index, poly_windoworcolour) {
 				Ok(_) => clicker,
 				Err(_) => 0,
 			}
@@ -97,6 +84,39 @@ pub fn startprogram(xconnection: &impl Connection, screen: &Screen, panel: Windo
 	}
 }
 
+fn itemheight(label: &str) -> i16 {
+	if label == "Divider" {
+		DIVIDER
+	} else if label == "Search" {
+		SEARCH
+	} else {
+		ITEM
+	}
+}
+
+pub fn clickmenuheight(clickmenuitems: &[[String; 3]; 16], clickmenusize: &u8) -> i16 {
+	//Loop through clickmenuitems to determine the size of height.
+	//No Divider = 20, Dividier = 6
+	let mut clickerheight: i16 = OFFSET + OFFSET;
+    for i in 0..(*clickmenusize as usize) {
+		clickerheight += itemheight(&clickmenuitems[i][0]);
+    }
+	clickerheight
+}
+
+pub fn clickmenuitem(clickmenuitems: &[[String; 3]; 16], clickmenusize: &u8, y: i16) -> Option<usize> {
+	//Work out which menu item is under y. Items are drawn from the bottom of the menu upwards, so walk them the same way.
+	let mut bottom = clickmenuheight(clickmenuitems, clickmenusize) - OFFSET;
+	for i in 0..(*clickmenusize as usize) {
+		let top = bottom - itemheight(&clickmenuitems[i][0]);
+		if y >= top && y < bottom {
+			return if clickmenuitems[i][0] == "Divider" { None } else { Some(i) };
+		}
+		bottom = top;
+	}
+	None
+}
+
 fn drawclickmenu<C: Connection>(xconnection: &C, clicker: u32, clickmenuitems: &[[String; 3]; 16], clickmenusize: &u8, startx: i16, starty: i16, clickerwidth: i16, clickerheight: i16, gc_highlight: u32, gc_lowlight: u32, gc_highbackground: u32, gc_lowbackground: u32, gc_titlebar: u32, gc_titlebartext: u32) {
 	
 	//Draw border and background.
@@ -148,11 +168,12 @@ fn drawclickmenu<C: Connection>(xconnection: &C, clicker: u32, clickmenuitems: &
     }
 }
 
-pub fn endprogram<C: Connection>(wm: &mut WindowManager, xconnection: &C, window: u32, system: Window, panelcoordinates: [[i16; 2]; 128], gc_highlight: u32, gc_lowlight: u32, gc_highbackground: u32, gc_lowbackground: u32,poly_lowlight: &mut Vec<Segment>) {
+pub fn endprogram<C: Connection>(wm: &mut WindowManager, xconnection: &C, window: u32, system: Window, panelstate: &Panel, gc_highlight: u32, gc_lowlight: u32, gc_highbackground: u32, gc_lowbackground: u32,poly_lowlight: &mut Vec<Segment>) {
 	//Remove the click menu.
 	wm.removewindow(&xconnection, 0, system);
 	//Return bottom left Click button to unclicked state.
-	drawclickbutton(&xconnection, window, panelcoordinates[0][0], 4, panelcoordinates[0][1], 21, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, poly_lowlight);
+	let (x, y, width, height) = panelstate.rect(0);
+	drawclickbutton(&xconnection, window, x, y, width, height, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, poly_lowlight);
 	//system = 0;
 	
 }
\ No newline at end of file
@@ -0,0 +1,389 @@
+use std::fs;
+
+use crate::system::settings::ClockHours;
+
+//Local time for the clock, worked out the way libc does it: a TZif file from $TZ or /etc/localtime,
+//then the POSIX TZ rule at the end of it (or in $TZ itself) for anything past its last transition.
+
+const ZONEINFO: &str = "/usr/share/zoneinfo";
+const DAYS: [&str; 7] = ["Thursday", "Friday", "Saturday", "Sunday", "Monday", "Tuesday", "Wednesday"];
+const MONTHS: [&str; 12] = ["January", "February", "March", "April", "May", "June", "July", "August", "September", "October", "November", "December"];
+
+#[derive(Clone, PartialEq, Debug)]
+struct Summer {
+	offset: i64, //Seconds east of UTC during daylight saving.
+	start: (Day, i64), //When it starts and ends, as a day and seconds into it, in the local time it replaces.
+	end: (Day, i64),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Day {
+	Julian(i64), //Jn: 1 to 365, never counting 29 February.
+	Zero(i64), //n: 0 to 365, counting it.
+	Month(u32, u32, u32), //Mm.w.d: day d (Sunday is 0) of week w (5 is the last) of month m.
+}
+
+#[derive(Clone, PartialEq, Debug)]
+struct Rule {
+	standard: i64, //Seconds east of UTC.
+	summer: Option<Summer>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct TimeZone {
+	transitions: Vec<(i64, usize)>, //When, and which offset from then on.
+	offsets: Vec<i64>, //Seconds east of UTC.
+	rule: Option<Rule>,
+}
+
+impl TimeZone {
+	pub fn utc() -> TimeZone {
+		TimeZone { transitions: Vec::new(), offsets: vec![0], rule: None }
+	}
+
+	pub fn local() -> TimeZone {
+		//$TZ names a file (:/path, or a name under /usr/share/zoneinfo) or is a rule itself. Without it, /etc/localtime.
+		let tz = std::env::var("TZ").ok().filter(|tz| !tz.is_empty());
+		let zone = match tz.as_deref().map(|tz| tz.strip_prefix(':').unwrap_or(tz)) {
+			Some(tz) => {
+				let path = if tz.starts_with('/') { tz.to_string() } else { format!("{}/{}", ZONEINFO, tz) };
+				fs::read(&path).ok().and_then(|data| TimeZone::tzif(&data)).or_else(|| TimeZone::posix(tz))
+			}
+			None => fs::read("/etc/localtime").ok().and_then(|data| TimeZone::tzif(&data)),
+		};
+		zone.unwrap_or_else(|| {
+			eprintln!("Can't work out the time zone, so the clock is in UTC");
+			TimeZone::utc()
+		})
+	}
+
+	pub fn posix(text: &str) -> Option<TimeZone> {
+		let rule = rule(text)?;
+		Some(TimeZone { transitions: Vec::new(), offsets: vec![rule.standard], rule: Some(rule) })
+	}
+
+	pub fn tzif(data: &[u8]) -> Option<TimeZone> {
+		//RFC 8536. Version 1 has 32 bit times. Later versions repeat everything with 64 bit times, then add a TZ rule.
+		let header = |at: usize| -> Option<(u8, [usize; 6])> {
+			if data.get(at..at + 4)? != b"TZif" {
+				return None;
+			}
+			let mut counts = [0; 6];
+			for (n, count) in counts.iter_mut().enumerate() {
+				*count = u32::from_be_bytes(data.get(at + 20 + n * 4..at + 24 + n * 4)?.try_into().ok()?) as usize;
+			}
+			Some((*data.get(at + 4)?, counts))
+		};
+		let (version, counts) = header(0)?;
+		let blocksize = |[isutcnt, isstdcnt, leapcnt, timecnt, typecnt, charcnt]: [usize; 6], timesize: usize| {
+			timecnt * timesize + timecnt + typecnt * 6 + charcnt + leapcnt * (timesize + 4) + isstdcnt + isutcnt
+		};
+		let (start, counts, timesize) = if version >= b'2' {
+			let second = 44 + blocksize(counts, 4);
+			(second + 44, header(second)?.1, 8)
+		} else {
+			(44, counts, 4)
+		};
+		let [_, _, _, timecnt, typecnt, _] = counts;
+
+		let mut at = start;
+		let mut times = Vec::with_capacity(timecnt);
+		for _ in 0..timecnt {
+			let bytes = data.get(at..at + timesize)?;
+			times.push(if timesize == 8 { i64::from_be_bytes(bytes.try_into().ok()?) } else { i32::from_be_bytes(bytes.try_into().ok()?) as i64 });
+			at += timesize;
+		}
+		let indices = data.get(at..at + timecnt)?;
+		at += timecnt;
+		//Each type is an offset, a daylight saving flag and an abbreviation. The clock only needs the offset.
+		let mut offsets = Vec::with_capacity(typecnt);
+		for _ in 0..typecnt {
+			offsets.push(i32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?) as i64);
+			at += 6;
+		}
+		if offsets.is_empty() || indices.iter().any(|&index| index as usize >= offsets.len()) {
+			return None;
+		}
+		let transitions = times.into_iter().zip(indices.iter().map(|&index| index as usize)).collect();
+
+		//The footer is the TZ rule between newlines. Empty means there isn't one.
+		let rule = if version >= b'2' {
+			let footer = data.get(start + blocksize(counts, timesize)..)?;
+			std::str::from_utf8(footer).ok().and_then(|footer| footer.strip_prefix('\n')).and_then(|footer| footer.split('\n').next()).and_then(rule)
+		} else {
+			None
+		};
+		Some(TimeZone { transitions, offsets, rule })
+	}
+
+	pub fn offset(&self, epoch: i64) -> i64 {
+		//Seconds to add to UTC to get local time at epoch.
+		//Past the last transition, or without any, the rule says. Before the first, the first type.
+		match self.transitions.partition_point(|&(when, _)| when <= epoch) {
+			n if n == self.transitions.len() && self.rule.is_some() => self.rule.as_ref().map(|rule| rule.offset(epoch)).unwrap_or(0),
+			0 => self.offsets[0],
+			n => self.offsets[self.transitions[n - 1].1],
+		}
+	}
+
+	pub fn localtime(&self, epoch: i64) -> i64 {
+		epoch + self.offset(epoch)
+	}
+}
+
+impl Rule {
+	fn offset(&self, epoch: i64) -> i64 {
+		let Some(Summer { offset: summer, start: (startday, starttime), end: (endday, endtime) }) = self.summer else {
+			return self.standard;
+		};
+		//Each change happens in the local time it changes from, so daylight saving starts in standard time and ends in summer time.
+		let (year, _, _) = civil((epoch + self.standard).div_euclid(86400));
+		let start = day(year, startday) * 86400 + starttime - self.standard;
+		let end = day(year, endday) * 86400 + endtime - summer;
+		let insummer = if start < end { epoch >= start && epoch < end } else { epoch < end || epoch >= start }; //South of the equator it spans new year.
+		if insummer { summer } else { self.standard }
+	}
+}
+
+fn rule(text: &str) -> Option<Rule> {
+	//std offset [dst [offset] [,start[/time],end[/time]]], like AEST-10AEDT,M10.1.0,M4.1.0/3. Offsets are hours west, so negate them.
+	let (_, rest) = name(text)?;
+	let (west, rest) = duration(rest)?;
+	let standard = -west;
+	if rest.is_empty() {
+		return Some(Rule { standard, summer: None });
+	}
+	let (_, rest) = name(rest)?;
+	let (summer, rest) = match duration(rest) {
+		Some((west, rest)) => (-west, rest),
+		None => (standard + 3600, rest),
+	};
+	//No dates means the US ones.
+	let rest = if rest.is_empty() { ",M3.2.0,M11.1.0" } else { rest };
+	let (start, end) = rest.strip_prefix(',')?.split_once(',')?;
+	Some(Rule { standard, summer: Some(Summer { offset: summer, start: change(start)?, end: change(end)? }) })
+}
+
+fn name(text: &str) -> Option<(&str, &str)> {
+	//Three or more letters, or anything in angle brackets like <+0530>.
+	if let Some(quoted) = text.strip_prefix('<') {
+		let (name, rest) = quoted.split_once('>')?;
+		return Some((name, rest));
+	}
+	let length = text.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(text.len());
+	(length >= 3).then(|| text.split_at(length))
+}
+
+fn duration(text: &str) -> Option<(i64, &str)> {
+	//[+-]hh[:mm[:ss]] in seconds.
+	let (sign, text) = match text.strip_prefix('-') {
+		Some(text) => (-1, text),
+		None => (1, text.strip_prefix('+').unwrap_or(text)),
+	};
+	let length = text.find(|c: char| !c.is_ascii_digit() && c != ':').unwrap_or(text.len());
+	let (clock, rest) = text.split_at(length);
+	let mut seconds = 0;
+	for (n, part) in clock.split(':').enumerate() {
+		if n > 2 {
+			return None;
+		}
+		seconds += part.parse::<i64>().ok()? * [3600, 60, 1][n];
+	}
+	Some((sign * seconds, rest))
+}
+
+fn change(text: &str) -> Option<(Day, i64)> {
+	//A date and an optional time, two in the morning if it isn't given.
+	let (date, time) = match text.split_once('/') {
+		Some((date, time)) => (date, duration(time).filter(|(_, rest)| rest.is_empty())?.0),
+		None => (text, 7200),
+	};
+	let date = if let Some(julian) = date.strip_prefix('J') {
+		Day::Julian(julian.parse().ok()?)
+	} else if let Some(month) = date.strip_prefix('M') {
+		let mut parts = month.split('.').map(|part| part.parse::<u32>().ok());
+		let (month, week, weekday) = (parts.next()??, parts.next()??, parts.next()??);
+		if !(1..=12).contains(&month) || !(1..=5).contains(&week) || weekday > 6 {
+			return None;
+		}
+		Day::Month(month, week, weekday)
+	} else {
+		Day::Zero(date.parse().ok()?)
+	};
+	Some((date, time))
+}
+
+fn day(year: i64, date: Day) -> i64 {
+	//Days since 1970 for a rule's date in year.
+	let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+	match date {
+		Day::Julian(n) => days(year, 1, 1) + n - 1 + if leap && n >= 60 { 1 } else { 0 },
+		Day::Zero(n) => days(year, 1, 1) + n,
+		Day::Month(month, week, weekday) => {
+			let first = days(year, month, 1);
+			let mut date = first + (weekday as i64 - (first + 4).rem_euclid(7)).rem_euclid(7) + (week as i64 - 1) * 7;
+			//Week 5 means the last one, which might be the fourth.
+			let next = if month == 12 { days(year + 1, 1, 1) } else { days(year, month + 1, 1) };
+			while date >= next {
+				date -= 7;
+			}
+			date
+		}
+	}
+}
+
+pub fn civil(days: i64) -> (i64, u32, u32) {
+	//Days since 1970 to (year, month, day), counting years from March so the leap day comes last.
+	let shifted = days + 719468;
+	let era = shifted.div_euclid(146097);
+	let dayofera = shifted.rem_euclid(146097);
+	let yearofera = (dayofera - dayofera / 1460 + dayofera / 36524 - dayofera / 146096) / 365;
+	let dayofyear = dayofera - (365 * yearofera + yearofera / 4 - yearofera / 100);
+	let monthfrommarch = (5 * dayofyear + 2) / 153;
+	let day = dayofyear - (153 * monthfrommarch + 2) / 5 + 1;
+	let month = if monthfrommarch < 10 { monthfrommarch + 3 } else { monthfrommarch - 9 };
+	(yearofera + era * 400 + if month <= 2 { 1 } else { 0 }, month as u32, day as u32)
+}
+
+pub fn days(year: i64, month: u32, day: u32) -> i64 {
+	//The other way.
+	let year = if month <= 2 { year - 1 } else { year };
+	let era = year.div_euclid(400);
+	let yearofera = year.rem_euclid(400);
+	let monthfrommarch = if month > 2 { month - 3 } else { month + 9 } as i64;
+	let dayofyear = (153 * monthfrommarch + 2) / 5 + day as i64 - 1;
+	let dayofera = yearofera * 365 + yearofera / 4 - yearofera / 100 + dayofyear;
+	era * 146097 + dayofera - 719468
+}
+
+pub fn fulldate(local: i64) -> String {
+	//The whole date for the clock's tooltip, like "Monday, 19 October 2026".
+	let days = local.div_euclid(86400);
+	let (year, month, day) = civil(days);
+	format!("{}, {} {} {}", DAYS[days.rem_euclid(7) as usize], day, MONTHS[month as usize - 1], year)
+}
+
+pub fn text(local: i64, hours: ClockHours, seconds: bool, date: bool) -> String {
+	//What the clock shows, like "3:07 PM", "15:07:42" or "Mon 19 Oct 15:07".
+	let days = local.div_euclid(86400);
+	let second = local.rem_euclid(86400);
+	let (hour, minute) = (second / 3600, second / 60 % 60);
+	let mut text = String::new();
+	if date {
+		let (_, month, day) = civil(days);
+		text += &format!("{} {} {} ", &DAYS[days.rem_euclid(7) as usize][..3], day, &MONTHS[month as usize - 1][..3]);
+	}
+	text += &match hours {
+		ClockHours::Twelve => format!("{}:{:02}", if hour % 12 == 0 { 12 } else { hour % 12 }, minute),
+		ClockHours::TwentyFour => format!("{:02}:{:02}", hour, minute),
+	};
+	if seconds {
+		text += &format!(":{:02}", second % 60);
+	}
+	if hours == ClockHours::Twelve {
+		text += if hour < 12 { " AM" } else { " PM" };
+	}
+	text
+}
+
+pub fn widest(hours: ClockHours, seconds: bool, date: bool) -> usize {
+	//The most characters text can come to, for a two digit day and hour. 10:10 on Sunday 30 December 2001.
+	text(days(2001, 12, 30) * 86400 + 10 * 3600 + 10 * 60, hours, seconds, date).len()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn datesgobothways() {
+		assert_eq!(civil(0), (1970, 1, 1));
+		assert_eq!(civil(days(2024, 2, 29)), (2024, 2, 29));
+		assert_eq!(days(2026, 10, 19), 20745);
+		assert_eq!(days(1969, 12, 31), -1);
+		assert_eq!(fulldate(days(2026, 10, 19) * 86400 + 3600), "Monday, 19 October 2026");
+	}
+
+	#[test]
+	fn clockformats() {
+		let afternoon = days(2026, 10, 9) * 86400 + 15 * 3600 + 7 * 60 + 42;
+		assert_eq!(text(afternoon, ClockHours::Twelve, false, false), "3:07 PM");
+		assert_eq!(text(afternoon, ClockHours::TwentyFour, true, false), "15:07:42");
+		assert_eq!(text(afternoon, ClockHours::TwentyFour, false, true), "Fri 9 Oct 15:07");
+		assert_eq!(text(days(2026, 10, 9) * 86400 + 30, ClockHours::Twelve, true, false), "12:00:30 AM");
+		assert_eq!(widest(ClockHours::Twelve, false, false), 8);
+		assert_eq!(widest(ClockHours::TwentyFour, true, true), 19);
+	}
+
+	#[test]
+	fn posixrulesbothhemispheres() {
+		let sydney = TimeZone::posix("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+		//Daylight saving from 2am on the first Sunday in October until 3am on the first Sunday in April.
+		let change = days(2026, 10, 4) * 86400 + 2 * 3600 - 10 * 3600;
+		assert_eq!(sydney.offset(change - 1), 36000);
+		assert_eq!(sydney.offset(change), 39600);
+		assert_eq!(sydney.offset(days(2027, 1, 1) * 86400), 39600);
+		let back = days(2027, 4, 4) * 86400 + 3 * 3600 - 11 * 3600;
+		assert_eq!(sydney.offset(back - 1), 39600);
+		assert_eq!(sydney.offset(back), 36000);
+
+		let newyork = TimeZone::posix("EST5EDT").unwrap();
+		assert_eq!(newyork.offset(days(2026, 7, 1) * 86400), -4 * 3600);
+		assert_eq!(newyork.offset(days(2026, 12, 1) * 86400), -5 * 3600);
+		//Last Sunday in March and October, at 1am UTC.
+		let europe = TimeZone::posix("CET-1CEST,M3.5.0,M10.5.0/3").unwrap();
+		assert_eq!(europe.offset(days(2026, 3, 29) * 86400 + 3600), 7200);
+		assert_eq!(europe.offset(days(2026, 3, 29) * 86400 + 3599), 3600);
+		assert_eq!(TimeZone::posix("<+0530>-5:30").unwrap().offset(0), 19800);
+		assert_eq!(TimeZone::posix("nonsense"), None);
+	}
+
+	fn tzif(version: u8, transitions: &[(i64, u8)], types: &[(i32, bool)], footer: &str) -> Vec<u8> {
+		//A TZif file with no leap seconds, abbreviations or indicators.
+		let mut data = Vec::new();
+		let block = |data: &mut Vec<u8>, timesize: usize| {
+			data.extend(b"TZif");
+			data.push(version);
+			data.extend([0; 15]);
+			for count in [0, 0, 0, transitions.len() as u32, types.len() as u32, 0] {
+				data.extend(count.to_be_bytes());
+			}
+			for &(when, _) in transitions {
+				data.extend(if timesize == 8 { when.to_be_bytes().to_vec() } else { (when as i32).to_be_bytes().to_vec() });
+			}
+			data.extend(transitions.iter().map(|&(_, index)| index));
+			for &(offset, dst) in types {
+				data.extend(offset.to_be_bytes());
+				data.extend([dst as u8, 0]);
+			}
+		};
+		block(&mut data, 4);
+		if version >= b'2' {
+			block(&mut data, 8);
+			data.extend(format!("\n{}\n", footer).bytes());
+		}
+		data
+	}
+
+	#[test]
+	fn tziftransitionsthenfooter() {
+		let data = tzif(b'2', &[(1000, 1), (2000, 0)], &[(3600, false), (7200, true)], "CET-1CEST,M3.5.0,M10.5.0/3");
+		let zone = TimeZone::tzif(&data).unwrap();
+		assert_eq!(zone.offset(0), 3600);
+		assert_eq!(zone.offset(1000), 7200);
+		assert_eq!(zone.offset(days(2026, 7, 1) * 86400), 7200);
+		assert_eq!(zone.offset(days(2026, 12, 1) * 86400), 3600);
+
+		let old = TimeZone::tzif(&tzif(b'\0', &[(1000, 1)], &[(0, false), (-18000, false)], "")).unwrap();
+		assert_eq!(old.localtime(5000), 5000 - 18000);
+		assert_eq!(TimeZone::tzif(&data[..60]), None);
+		assert_eq!(TimeZone::tzif(&tzif(b'2', &[(1000, 5)], &[(0, false)], "")), None);
+
+		//Cut off in the abbreviations, which come after everything the clock reads.
+		let mut cut = tzif(b'2', &[(1000, 1)], &[(0, false), (3600, false)], "");
+		let second = cut.windows(4).rposition(|bytes| bytes == b"TZif").unwrap();
+		cut[second + 40..second + 44].copy_from_slice(&4u32.to_be_bytes());
+		cut.truncate(cut.len() - 2);
+		assert_eq!(TimeZone::tzif(&cut), None);
+	}
+}
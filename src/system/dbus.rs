@@ -0,0 +1,700 @@
+use std::{
+	collections::VecDeque,
+	env,
+	error::Error,
+	fs,
+	io::{ErrorKind, Read, Write},
+	os::linux::net::SocketAddrExt,
+	os::unix::fs::MetadataExt,
+	os::unix::io::{AsRawFd, RawFd},
+	os::unix::net::{SocketAddr, UnixStream},
+};
+
+//Just enough of D-Bus to own a name on the session bus, answer method calls and send signals.
+//A whole D-Bus library would be most of Tullamore, so the wire format is done by hand here.
+
+//Message types.
+pub const METHOD_CALL: u8 = 1;
+pub const METHOD_RETURN: u8 = 2;
+pub const ERROR: u8 = 3;
+pub const SIGNAL: u8 = 4;
+
+//Message flags.
+pub const NO_REPLY_EXPECTED: u8 = 1;
+
+//Header fields.
+const PATH: u8 = 1;
+const INTERFACE: u8 = 2;
+const MEMBER: u8 = 3;
+const ERROR_NAME: u8 = 4;
+const REPLY_SERIAL: u8 = 5;
+const DESTINATION: u8 = 6;
+const SENDER: u8 = 7;
+const SIGNATURE: u8 = 8;
+
+//RequestName flags and replies.
+const DO_NOT_QUEUE: u32 = 4;
+const PRIMARY_OWNER: u32 = 1;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Value {
+	Byte(u8),
+	Bool(bool),
+	Int16(i16),
+	Uint16(u16),
+	Int32(i32),
+	Uint32(u32),
+	Int64(i64),
+	Uint64(u64),
+	Double(f64),
+	Str(String),
+	Path(String),
+	Signature(String),
+	Fd(u32), //Index into the file descriptors sent with the message. We never ask for any.
+	Array(String, Vec<Value>), //Element signature, then the elements. A dictionary is an array of DictEntry.
+	Struct(Vec<Value>),
+	DictEntry(Box<Value>, Box<Value>),
+	Variant(Box<Value>),
+}
+
+impl Value {
+	pub fn signature(&self) -> String {
+		match self {
+			Value::Byte(_) => "y".to_string(),
+			Value::Bool(_) => "b".to_string(),
+			Value::Int16(_) => "n".to_string(),
+			Value::Uint16(_) => "q".to_string(),
+			Value::Int32(_) => "i".to_string(),
+			Value::Uint32(_) => "u".to_string(),
+			Value::Int64(_) => "x".to_string(),
+			Value::Uint64(_) => "t".to_string(),
+			Value::Double(_) => "d".to_string(),
+			Value::Str(_) => "s".to_string(),
+			Value::Path(_) => "o".to_string(),
+			Value::Signature(_) => "g".to_string(),
+			Value::Fd(_) => "h".to_string(),
+			Value::Array(element, _) => format!("a{}", element),
+			Value::Struct(fields) => format!("({})", fields.iter().map(|field| field.signature()).collect::<String>()),
+			Value::DictEntry(key, value) => format!("{{{}{}}}", key.signature(), value.signature()),
+			Value::Variant(_) => "v".to_string(),
+		}
+	}
+
+	pub fn str(&self) -> Option<&str> {
+		match self {
+			Value::Str(text) | Value::Path(text) | Value::Signature(text) => Some(text),
+			_ => None,
+		}
+	}
+
+	pub fn u32(&self) -> Option<u32> {
+		match self {
+			Value::Byte(n) => Some(*n as u32),
+			Value::Uint16(n) => Some(*n as u32),
+			Value::Uint32(n) => Some(*n),
+			_ => None,
+		}
+	}
+
+	pub fn i32(&self) -> Option<i32> {
+		match self {
+			Value::Int16(n) => Some(*n as i32),
+			Value::Int32(n) => Some(*n),
+			_ => None,
+		}
+	}
+
+	pub fn strings(&self) -> Vec<String> {
+		//An array of strings, or nothing.
+		match self {
+			Value::Array(_, items) => items.iter().filter_map(|item| item.str().map(|text| text.to_string())).collect(),
+			_ => Vec::new(),
+		}
+	}
+
+	pub fn lookup(&self, key: &str) -> Option<&Value> {
+		//The value for key in a dictionary with string keys, with any variant unwrapped.
+		let Value::Array(_, entries) = self else {
+			return None;
+		};
+		entries.iter().find_map(|entry| match entry {
+			Value::DictEntry(k, v) if k.str() == Some(key) => Some(match v.as_ref() {
+				Value::Variant(inner) => inner.as_ref(),
+				other => other,
+			}),
+			_ => None,
+		})
+	}
+}
+
+fn alignment(code: u8) -> usize {
+	match code {
+		b'y' | b'g' | b'v' => 1,
+		b'n' | b'q' => 2,
+		b'x' | b't' | b'd' | b'(' | b'{' => 8,
+		_ => 4,
+	}
+}
+
+fn typelength(signature: &[u8], start: usize) -> Result<usize, Box<dyn Error>> {
+	//How many characters the single complete type at start takes up.
+	match signature.get(start) {
+		Some(b'a') => Ok(1 + typelength(signature, start + 1)?),
+		Some(&open) if open == b'(' || open == b'{' => {
+			let close = if open == b'(' { b')' } else { b'}' };
+			let mut end = start + 1;
+			while signature.get(end) != Some(&close) {
+				if end >= signature.len() {
+					return Err("Unterminated struct in signature".into());
+				}
+				end += typelength(signature, end)?;
+			}
+			Ok(end + 1 - start)
+		}
+		Some(_) => Ok(1),
+		None => Err("Signature ended early".into()),
+	}
+}
+
+struct Writer {
+	data: Vec<u8>,
+}
+
+impl Writer {
+	fn pad(&mut self, align: usize) {
+		self.data.resize(self.data.len().div_ceil(align) * align, 0);
+	}
+
+	fn write(&mut self, value: &Value) {
+		//Little endian, like everything Tullamore runs on.
+		self.pad(alignment(value.signature().as_bytes()[0]));
+		match value {
+			Value::Byte(n) => self.data.push(*n),
+			Value::Bool(b) => self.data.extend((*b as u32).to_le_bytes()),
+			Value::Int16(n) => self.data.extend(n.to_le_bytes()),
+			Value::Uint16(n) => self.data.extend(n.to_le_bytes()),
+			Value::Int32(n) => self.data.extend(n.to_le_bytes()),
+			Value::Uint32(n) | Value::Fd(n) => self.data.extend(n.to_le_bytes()),
+			Value::Int64(n) => self.data.extend(n.to_le_bytes()),
+			Value::Uint64(n) => self.data.extend(n.to_le_bytes()),
+			Value::Double(n) => self.data.extend(n.to_le_bytes()),
+			Value::Str(text) | Value::Path(text) => {
+				self.data.extend((text.len() as u32).to_le_bytes());
+				self.data.extend(text.as_bytes());
+				self.data.push(0);
+			}
+			Value::Signature(text) => {
+				self.data.push(text.len() as u8);
+				self.data.extend(text.as_bytes());
+				self.data.push(0);
+			}
+			Value::Array(element, items) => {
+				//The length doesn't count the padding up to the first element.
+				let at = self.data.len();
+				self.data.extend(0u32.to_le_bytes());
+				self.pad(alignment(element.as_bytes()[0]));
+				let start = self.data.len();
+				for item in items {
+					self.write(item);
+				}
+				let length = (self.data.len() - start) as u32;
+				self.data[at..at + 4].copy_from_slice(&length.to_le_bytes());
+			}
+			Value::Struct(fields) => {
+				for field in fields {
+					self.write(field);
+				}
+			}
+			Value::DictEntry(key, value) => {
+				self.write(key);
+				self.write(value);
+			}
+			Value::Variant(inner) => {
+				self.write(&Value::Signature(inner.signature()));
+				self.write(inner);
+			}
+		}
+	}
+}
+
+struct Reader<'a> {
+	data: &'a [u8],
+	position: usize,
+	big: bool, //Big endian. The bus passes messages on in whatever order the sender wrote them.
+}
+
+impl Reader<'_> {
+	fn align(&mut self, align: usize) {
+		self.position = self.position.div_ceil(align) * align;
+	}
+
+	fn take(&mut self, count: usize) -> Result<&[u8], Box<dyn Error>> {
+		let bytes = self.data.get(self.position..self.position + count).ok_or("Message ended early")?;
+		self.position += count;
+		Ok(bytes)
+	}
+
+	fn fixed<const N: usize>(&mut self) -> Result<[u8; N], Box<dyn Error>> {
+		self.align(N);
+		let mut bytes: [u8; N] = self.take(N)?.try_into()?;
+		if self.big {
+			bytes.reverse();
+		}
+		Ok(bytes)
+	}
+
+	fn u32(&mut self) -> Result<u32, Box<dyn Error>> {
+		Ok(u32::from_le_bytes(self.fixed()?))
+	}
+
+	fn text(&mut self, length: usize) -> Result<String, Box<dyn Error>> {
+		let text = String::from_utf8_lossy(self.take(length)?).to_string();
+		self.take(1)?;
+		Ok(text)
+	}
+
+	fn read(&mut self, signature: &[u8], start: usize) -> Result<Value, Box<dyn Error>> {
+		//The single complete type at signature[start].
+		Ok(match signature.get(start).ok_or("Signature ended early")? {
+			b'y' => Value::Byte(self.take(1)?[0]),
+			b'b' => Value::Bool(self.u32()? != 0),
+			b'n' => Value::Int16(i16::from_le_bytes(self.fixed()?)),
+			b'q' => Value::Uint16(u16::from_le_bytes(self.fixed()?)),
+			b'i' => Value::Int32(i32::from_le_bytes(self.fixed()?)),
+			b'u' => Value::Uint32(self.u32()?),
+			b'h' => Value::Fd(self.u32()?),
+			b'x' => Value::Int64(i64::from_le_bytes(self.fixed()?)),
+			b't' => Value::Uint64(u64::from_le_bytes(self.fixed()?)),
+			b'd' => Value::Double(f64::from_le_bytes(self.fixed()?)),
+			b's' => {
+				let length = self.u32()? as usize;
+				Value::Str(self.text(length)?)
+			}
+			b'o' => {
+				let length = self.u32()? as usize;
+				Value::Path(self.text(length)?)
+			}
+			b'g' => {
+				let length = self.take(1)?[0] as usize;
+				Value::Signature(self.text(length)?)
+			}
+			b'v' => {
+				let length = self.take(1)?[0] as usize;
+				let inner = self.text(length)?;
+				Value::Variant(Box::new(self.read(inner.as_bytes(), 0)?))
+			}
+			b'a' => {
+				let length = self.u32()? as usize;
+				let element = &signature[start + 1..start + 1 + typelength(signature, start + 1)?];
+				self.align(alignment(element[0]));
+				let end = self.position + length;
+				let mut items = Vec::new();
+				while self.position < end {
+					items.push(self.read(element, 0)?);
+				}
+				Value::Array(String::from_utf8_lossy(element).to_string(), items)
+			}
+			b'(' => {
+				self.align(8);
+				let end = start + typelength(signature, start)? - 1;
+				let mut fields = Vec::new();
+				let mut at = start + 1;
+				while at < end {
+					fields.push(self.read(signature, at)?);
+					at += typelength(signature, at)?;
+				}
+				Value::Struct(fields)
+			}
+			b'{' => {
+				self.align(8);
+				let key = self.read(signature, start + 1)?;
+				let value = self.read(signature, start + 2)?;
+				Value::DictEntry(Box::new(key), Box::new(value))
+			}
+			other => return Err(format!("Unknown type '{}' in signature", *other as char).into()),
+		})
+	}
+
+	fn readall(&mut self, signature: &str) -> Result<Vec<Value>, Box<dyn Error>> {
+		let signature = signature.as_bytes();
+		let mut values = Vec::new();
+		let mut at = 0;
+		while at < signature.len() {
+			values.push(self.read(signature, at)?);
+			at += typelength(signature, at)?;
+		}
+		Ok(values)
+	}
+}
+
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct Message {
+	pub kind: u8,
+	pub flags: u8,
+	pub serial: u32,
+	pub path: Option<String>,
+	pub interface: Option<String>,
+	pub member: Option<String>,
+	pub errorname: Option<String>,
+	pub replyserial: Option<u32>,
+	pub destination: Option<String>,
+	pub sender: Option<String>,
+	pub body: Vec<Value>,
+}
+
+impl Message {
+	pub fn call(destination: &str, path: &str, interface: &str, member: &str, body: Vec<Value>) -> Message {
+		Message { kind: METHOD_CALL, destination: Some(destination.to_string()), path: Some(path.to_string()), interface: Some(interface.to_string()), member: Some(member.to_string()), body, ..Default::default() }
+	}
+
+	pub fn signal(path: &str, interface: &str, member: &str, body: Vec<Value>) -> Message {
+		Message { kind: SIGNAL, path: Some(path.to_string()), interface: Some(interface.to_string()), member: Some(member.to_string()), body, ..Default::default() }
+	}
+
+	pub fn reply(call: &Message, body: Vec<Value>) -> Message {
+		Message { kind: METHOD_RETURN, replyserial: Some(call.serial), destination: call.sender.clone(), body, ..Default::default() }
+	}
+
+	pub fn error(call: &Message, name: &str, text: &str) -> Message {
+		Message { kind: ERROR, replyserial: Some(call.serial), destination: call.sender.clone(), errorname: Some(name.to_string()), body: vec![Value::Str(text.to_string())], ..Default::default() }
+	}
+
+	pub fn is(&self, interface: &str, member: &str) -> bool {
+		self.kind == METHOD_CALL && self.interface.as_deref().is_none_or(|name| name == interface) && self.member.as_deref() == Some(member)
+	}
+
+	pub fn encode(&self) -> Vec<u8> {
+		let mut body = Writer { data: Vec::new() };
+		for value in &self.body {
+			body.write(value);
+		}
+
+		let mut fields = Vec::new();
+		let mut field = |code: u8, value: Value| fields.push(Value::Struct(vec![Value::Byte(code), Value::Variant(Box::new(value))]));
+		if let Some(path) = &self.path { field(PATH, Value::Path(path.clone())); }
+		if let Some(interface) = &self.interface { field(INTERFACE, Value::Str(interface.clone())); }
+		if let Some(member) = &self.member { field(MEMBER, Value::Str(member.clone())); }
+		if let Some(name) = &self.errorname { field(ERROR_NAME, Value::Str(name.clone())); }
+		if let Some(serial) = self.replyserial { field(REPLY_SERIAL, Value::Uint32(serial)); }
+		if let Some(destination) = &self.destination { field(DESTINATION, Value::Str(destination.clone())); }
+		if let Some(sender) = &self.sender { field(SENDER, Value::Str(sender.clone())); }
+		if !self.body.is_empty() { field(SIGNATURE, Value::Signature(self.body.iter().map(|value| value.signature()).collect())); }
+
+		let mut message = Writer { data: vec![b'l', self.kind, self.flags, 1] };
+		message.data.extend((body.data.len() as u32).to_le_bytes());
+		message.data.extend(self.serial.to_le_bytes());
+		message.write(&Value::Array("(yv)".to_string(), fields));
+		message.pad(8);
+		message.data.extend(body.data);
+		message.data
+	}
+
+	pub fn decode(data: &[u8]) -> Result<Option<(Message, usize)>, Box<dyn Error>> {
+		//A whole message from the front of data, and how many bytes it took. None until all of it has arrived.
+		if data.len() < 16 {
+			return Ok(None);
+		}
+		let big = match data[0] {
+			b'l' => false,
+			b'B' => true,
+			other => return Err(format!("Bad byte order mark 0x{:x}", other).into()),
+		};
+		let mut reader = Reader { data, position: 4, big };
+		let bodylength = reader.u32()? as usize;
+		let serial = reader.u32()?;
+		let fieldslength = reader.u32()? as usize;
+		let total = (16 + fieldslength).div_ceil(8) * 8 + bodylength;
+		if data.len() < total {
+			return Ok(None);
+		}
+
+		let mut message = Message { kind: data[1], flags: data[2], serial, ..Default::default() };
+		reader.position = 12;
+		let mut signature = String::new();
+		if let Value::Array(_, fields) = reader.read(b"a(yv)", 0)? {
+			for field in fields {
+				let Value::Struct(parts) = field else { continue };
+				let (Some(Value::Byte(code)), Some(Value::Variant(value))) = (parts.first(), parts.get(1)) else { continue };
+				let text = value.str().map(|text| text.to_string());
+				match *code {
+					PATH => message.path = text,
+					INTERFACE => message.interface = text,
+					MEMBER => message.member = text,
+					ERROR_NAME => message.errorname = text,
+					REPLY_SERIAL => message.replyserial = value.u32(),
+					DESTINATION => message.destination = text,
+					SENDER => message.sender = text,
+					SIGNATURE => signature = text.unwrap_or_default(),
+					_ => {}
+				}
+			}
+		}
+		reader.align(8);
+		let mut body = Reader { data: data.get(reader.position..total).ok_or("Header fields run past the end of the message")?, position: 0, big };
+		message.body = body.readall(&signature)?;
+		Ok(Some((message, total)))
+	}
+}
+
+pub struct Bus {
+	stream: UnixStream,
+	buffer: Vec<u8>, //Bytes read that don't make a whole message yet.
+	queue: VecDeque<Message>, //Messages that turned up while waiting for a reply.
+	serial: u32,
+	pub name: String, //Our unique name, like :1.42.
+}
+
+impl Bus {
+	pub fn session() -> Result<Bus, Box<dyn Error>> {
+		let address = env::var("DBUS_SESSION_BUS_ADDRESS").map_err(|_| "DBUS_SESSION_BUS_ADDRESS isn't set")?;
+		Bus::connect(&address)
+	}
+
+	pub fn connect(address: &str) -> Result<Bus, Box<dyn Error>> {
+		//The address is any number of transport:key=value,key=value separated by semicolons. We can do unix sockets.
+		let mut stream = None;
+		for transport in address.split(';') {
+			let Some(options) = transport.strip_prefix("unix:") else { continue };
+			for option in options.split(',') {
+				let attempt = match option.split_once('=') {
+					Some(("path", path)) => UnixStream::connect(unescape(path)),
+					Some(("abstract", name)) => SocketAddr::from_abstract_name(unescape(name).as_bytes()).and_then(|socket| UnixStream::connect_addr(&socket)),
+					_ => continue,
+				};
+				if let Ok(connected) = attempt {
+					stream = Some(connected);
+				}
+			}
+			if stream.is_some() {
+				break;
+			}
+		}
+		let mut bus = Bus { stream: stream.ok_or_else(|| format!("Can't connect to the bus at '{}'", address))?, buffer: Vec::new(), queue: VecDeque::new(), serial: 0, name: String::new() };
+		bus.authenticate()?;
+		let hello = bus.call(Message::call("org.freedesktop.DBus", "/org/freedesktop/DBus", "org.freedesktop.DBus", "Hello", Vec::new()))?;
+		bus.name = hello.body.first().and_then(|name| name.str()).unwrap_or_default().to_string();
+		Ok(bus)
+	}
+
+	fn authenticate(&mut self) -> Result<(), Box<dyn Error>> {
+		//SASL EXTERNAL: the bus checks our uid against the socket's credentials. The uid goes as hex digits of its decimal form.
+		let uid = fs::metadata("/proc/self")?.uid().to_string();
+		let hex: String = uid.bytes().map(|byte| format!("{:02x}", byte)).collect();
+		self.stream.write_all(format!("\0AUTH EXTERNAL {}\r\n", hex).as_bytes())?;
+		let mut line = Vec::new();
+		let mut byte = [0u8];
+		while !line.ends_with(b"\r\n") {
+			if self.stream.read(&mut byte)? == 0 {
+				return Err("The bus hung up while we were logging in".into());
+			}
+			line.push(byte[0]);
+		}
+		if !line.starts_with(b"OK ") {
+			return Err(format!("The bus said no: {}", String::from_utf8_lossy(&line).trim()).into());
+		}
+		self.stream.write_all(b"BEGIN\r\n")?;
+		Ok(())
+	}
+
+	pub fn fd(&self) -> RawFd {
+		//For waiting on with poll. Once receive has come back with None, anything new will show up here.
+		self.stream.as_raw_fd()
+	}
+
+	pub fn send(&mut self, mut message: Message) -> Result<u32, Box<dyn Error>> {
+		self.serial += 1;
+		message.serial = self.serial;
+		self.stream.write_all(&message.encode())?;
+		Ok(self.serial)
+	}
+
+	pub fn call(&mut self, message: Message) -> Result<Message, Box<dyn Error>> {
+		//Send a method call and wait for its reply. Anything else that comes in meanwhile is kept for receive.
+		let serial = self.send(message)?;
+		loop {
+			while let Some((message, used)) = Message::decode(&self.buffer)? {
+				self.buffer.drain(..used);
+				if message.replyserial != Some(serial) {
+					self.queue.push_back(message);
+				} else if message.kind == ERROR {
+					let text = message.body.first().and_then(|text| text.str()).unwrap_or_default();
+					return Err(format!("{}: {}", message.errorname.unwrap_or_default(), text).into());
+				} else {
+					return Ok(message);
+				}
+			}
+			self.fill(true)?;
+		}
+	}
+
+	pub fn receive(&mut self) -> Result<Option<Message>, Box<dyn Error>> {
+		//The next message, without waiting for one.
+		if let Some(message) = self.queue.pop_front() {
+			return Ok(Some(message));
+		}
+		loop {
+			if let Some((message, used)) = Message::decode(&self.buffer)? {
+				self.buffer.drain(..used);
+				return Ok(Some(message));
+			}
+			if !self.fill(false)? {
+				return Ok(None);
+			}
+		}
+	}
+
+	fn fill(&mut self, block: bool) -> Result<bool, Box<dyn Error>> {
+		//Read whatever has arrived. False if nothing has and we weren't going to wait.
+		let mut chunk = [0u8; 4096];
+		self.stream.set_nonblocking(!block)?;
+		let result = self.stream.read(&mut chunk);
+		self.stream.set_nonblocking(false)?;
+		match result {
+			Ok(0) => Err("The bus hung up".into()),
+			Ok(count) => {
+				self.buffer.extend_from_slice(&chunk[..count]);
+				Ok(true)
+			}
+			Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(false),
+			Err(e) => Err(e.into()),
+		}
+	}
+
+	pub fn requestname(&mut self, name: &str) -> Result<bool, Box<dyn Error>> {
+		//Whether we got the name. Someone else having it already is a no.
+		let reply = self.call(Message::call("org.freedesktop.DBus", "/org/freedesktop/DBus", "org.freedesktop.DBus", "RequestName", vec![Value::Str(name.to_string()), Value::Uint32(DO_NOT_QUEUE)]))?;
+		Ok(reply.body.first().and_then(|result| result.u32()) == Some(PRIMARY_OWNER))
+	}
+
+	pub fn reply(&mut self, call: &Message, body: Vec<Value>) -> Result<(), Box<dyn Error>> {
+		if call.flags & NO_REPLY_EXPECTED == 0 {
+			self.send(Message::reply(call, body))?;
+		}
+		Ok(())
+	}
+
+	pub fn error(&mut self, call: &Message, name: &str, text: &str) -> Result<(), Box<dyn Error>> {
+		if call.flags & NO_REPLY_EXPECTED == 0 {
+			self.send(Message::error(call, name, text))?;
+		}
+		Ok(())
+	}
+}
+
+fn unescape(text: &str) -> String {
+	//Addresses escape anything unusual as %xx.
+	let bytes = text.as_bytes();
+	let mut out = Vec::new();
+	let mut i = 0;
+	while i < bytes.len() {
+		if bytes[i] == b'%' {
+			if let Some(byte) = text.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+				out.push(byte);
+				i += 3;
+				continue;
+			}
+		}
+		out.push(bytes[i]);
+		i += 1;
+	}
+	String::from_utf8_lossy(&out).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::system::poll;
+	use std::io::{BufRead, BufReader};
+	use std::process::{Command, Stdio};
+	use std::time::Duration;
+
+	fn notify() -> Message {
+		let hints = Value::Array("{sv}".to_string(), vec![
+			Value::DictEntry(Box::new(Value::Str("urgency".to_string())), Box::new(Value::Variant(Box::new(Value::Byte(2))))),
+			Value::DictEntry(Box::new(Value::Str("image-data".to_string())), Box::new(Value::Variant(Box::new(Value::Struct(vec![Value::Int32(1), Value::Int32(1), Value::Int32(4), Value::Bool(true), Value::Int32(8), Value::Int32(4), Value::Array("y".to_string(), vec![Value::Byte(1), Value::Byte(2), Value::Byte(3), Value::Byte(4)])]))))),
+		]);
+		Message::call("org.freedesktop.Notifications", "/org/freedesktop/Notifications", "org.freedesktop.Notifications", "Notify", vec![
+			Value::Str("test".to_string()), Value::Uint32(0), Value::Str(String::new()), Value::Str("Hello".to_string()), Value::Str("World".to_string()),
+			Value::Array("s".to_string(), vec![Value::Str("default".to_string()), Value::Str("Open".to_string())]),
+			hints, Value::Int32(-1),
+		])
+	}
+
+	#[test]
+	fn messagesroundtrip() {
+		let mut message = notify();
+		message.serial = 7;
+		let data = message.encode();
+		let (decoded, used) = Message::decode(&data).unwrap().unwrap();
+		assert_eq!(used, data.len());
+		assert_eq!(decoded, message);
+		assert_eq!(decoded.body[6].lookup("urgency"), Some(&Value::Byte(2)));
+		assert_eq!(decoded.body[5].strings(), vec!["default", "Open"]);
+		assert_eq!(Message::decode(&data[..data.len() - 1]).unwrap(), None);
+	}
+
+	#[test]
+	fn headerfieldsstayinthemessage() {
+		//The fields array says 8 bytes, but its one path runs on well past them and the message's end.
+		let mut data = b"l\x01\x00\x01".to_vec();
+		for n in [0u32, 1, 8] {
+			data.extend(n.to_le_bytes());
+		}
+		data.extend([PATH, 1, b'o', 0]);
+		data.extend(20u32.to_le_bytes());
+		data.extend(b"/aaaaaaaaaaaaaaaaaaa\0");
+		data.resize(48, 0);
+		assert!(Message::decode(&data).is_err());
+	}
+
+	#[test]
+	fn signaturesnest() {
+		assert_eq!(typelength(b"a{sv}i", 0).unwrap(), 5);
+		assert_eq!(typelength(b"(iiibiiay)", 0).unwrap(), 10);
+		assert!(typelength(b"(ii", 0).is_err());
+		assert_eq!(notify().body.iter().map(|value| value.signature()).collect::<String>(), "susssasa{sv}i");
+	}
+
+	#[test]
+	fn addressesunescape() {
+		assert_eq!(unescape("/tmp/dbus%2dtest"), "/tmp/dbus-test");
+		assert_eq!(unescape("100%"), "100%");
+	}
+
+	#[test]
+	fn callsgothrougharealbus() {
+		//Needs dbus-daemon. Without it there is nothing to test against.
+		let Ok(mut daemon) = Command::new("dbus-daemon").args(["--session", "--nofork", "--print-address"]).stdout(Stdio::piped()).stderr(Stdio::null()).spawn() else {
+			eprintln!("Skipping callsgothrougharealbus, as there is no dbus-daemon to run.");
+			return;
+		};
+		let mut address = String::new();
+		BufReader::new(daemon.stdout.take().unwrap()).read_line(&mut address).unwrap();
+
+		let mut server = Bus::connect(address.trim()).unwrap();
+		assert!(server.requestname("org.tullamore.Test").unwrap());
+		let mut client = Bus::connect(address.trim()).unwrap();
+		assert!(!client.requestname("org.tullamore.Test").unwrap());
+
+		let serial = client.send(Message { destination: Some("org.tullamore.Test".to_string()), ..notify() }).unwrap();
+		let call = waitfor(&mut server, |message| message.kind == METHOD_CALL);
+		assert!(call.is("org.freedesktop.Notifications", "Notify"));
+		assert_eq!(call.body[3], Value::Str("Hello".to_string()));
+		server.reply(&call, vec![Value::Uint32(42)]).unwrap();
+		let reply = waitfor(&mut client, |message| message.replyserial == Some(serial));
+		assert_eq!(reply.body, vec![Value::Uint32(42)]);
+		daemon.kill().unwrap();
+		daemon.wait().unwrap();
+	}
+
+	fn waitfor(bus: &mut Bus, wanted: impl Fn(&Message) -> bool) -> Message {
+		//The first message wanted, sleeping on the bus between them rather than spinning.
+		loop {
+			match bus.receive().unwrap() {
+				Some(message) if wanted(&message) => return message,
+				Some(_) => {}
+				None => assert_eq!(poll::wait(&[bus.fd()], Some(Duration::from_secs(5))).unwrap(), [true], "Nothing came over the bus"),
+			}
+		}
+	}
+}
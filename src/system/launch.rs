@@ -0,0 +1,40 @@
+use std::error::Error;
+use std::process::{Command, Stdio};
+
+use crate::panel::PanelIcon;
+
+//Links run a command line, unless it starts with tullamore: and names something built in, like tullamore:booker.
+pub const INTERNAL: &str = "tullamore:";
+
+pub fn internal(program: &str) -> Option<&str> {
+	//What a tullamore: link asks for, or None for an ordinary command.
+	program.strip_prefix(INTERNAL)
+}
+
+pub fn spawn(link: &PanelIcon, display: Option<&str>) -> Result<(), Box<dyn Error>> {
+	//Run the link's command through the shell, which puts it in the background and quits straight away.
+	//The program ends up belonging to init, so we never have to reap it and it outlives a restart.
+	let mut command = Command::new("/bin/sh");
+	command.arg("-c").arg(format!("({}) &", link.program)).stdin(Stdio::null());
+	if !link.directory.is_empty() {
+		command.current_dir(expandhome(&link.directory));
+	}
+	if let Some(display) = display {
+		//The display we manage, which isn't $DISPLAY when we were started with --display.
+		command.env("DISPLAY", display);
+	}
+	command.envs(link.environment.iter().map(|(name, value)| (name, value)));
+	let status = command.status()?;
+	if !status.success() {
+		return Err(format!("the shell quit with {}", status).into());
+	}
+	Ok(())
+}
+
+pub fn expandhome(directory: &str) -> String {
+	//~/ at the start means the home directory.
+	match (directory.strip_prefix("~/"), std::env::var("HOME")) {
+		(Some(rest), Ok(home)) => format!("{}/{}", home, rest),
+		_ => directory.to_string(),
+	}
+}
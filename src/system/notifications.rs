@@ -0,0 +1,333 @@
+use std::{
+	error::Error,
+	os::unix::io::RawFd,
+	path::Path,
+	time::{Duration, Instant},
+};
+use x11rb::{
+	connection::Connection,
+	protocol::xproto::*,
+	COPY_DEPTH_FROM_PARENT,
+};
+
+use crate::panel::Panel;
+use crate::system::settings::PanelEdge;
+use crate::system::dbus::{Bus, Message, Value, METHOD_CALL};
+use crate::trundle::{drawbumpyframe, drawpng, drawsystemframe, latin1, COLOURS, HIGHBACKGROUND_COLOUR};
+
+//org.freedesktop.Notifications (Desktop Notifications spec 1.2) on the session bus, shown as bubbles over the notification area.
+
+const NAME: &str = "org.freedesktop.Notifications";
+const OBJECT: &str = "/org/freedesktop/Notifications";
+
+const WIDTH: i16 = 260;
+const PADDING: i16 = 8;
+const ICON: i16 = 32;
+const LINE: i16 = 14;
+const MAXLINES: usize = 6; //Of body text. The rest gets cut off.
+const BUTTON: i16 = 18; //Height of an action button.
+const STACKGAP: i16 = 4; //Between bubbles.
+const TIMEOUT: u64 = 6000; //Milliseconds a bubble stays up when the program leaves it to us.
+
+//Why a bubble went away, for NotificationClosed.
+const EXPIRED: u32 = 1;
+const DISMISSED: u32 = 2;
+const CLOSED: u32 = 3;
+
+pub struct Bubble {
+	pub id: u32,
+	pub window: Window,
+	icon: Option<String>, //PNG to draw, if the program gave us one we can find.
+	summary: String,
+	lines: Vec<String>,
+	actions: Vec<(String, String)>, //(key, label). The "default" one is clicking the bubble itself, so it gets no button.
+	expires: Option<Instant>,
+	height: i16,
+}
+
+pub struct Notifications {
+	bus: Bus,
+	pub bubbles: Vec<Bubble>, //Oldest first. The newest sits next to the panel and pushes the others away from it.
+	lastid: u32,
+}
+
+impl Notifications {
+	pub fn start() -> Option<Notifications> {
+		//None if there is no session bus, or somebody else is already showing notifications.
+		let mut bus = match Bus::session() {
+			Ok(bus) => bus,
+			Err(e) => {
+				eprintln!("No notifications, as there is no session bus: {}", e);
+				return None;
+			}
+		};
+		match bus.requestname(NAME) {
+			Ok(true) => Some(Notifications { bus, bubbles: Vec::new(), lastid: 0 }),
+			Ok(false) => {
+				eprintln!("Another program is already showing notifications.");
+				None
+			}
+			Err(e) => {
+				eprintln!("Can't take {} on the session bus: {}", NAME, e);
+				None
+			}
+		}
+	}
+
+	pub fn wake(&self) -> Option<Instant> {
+		//When to next take a bubble down.
+		self.bubbles.iter().filter_map(|bubble| bubble.expires).min()
+	}
+
+	pub fn fd(&self) -> RawFd {
+		//Readable when something comes in on the bus.
+		self.bus.fd()
+	}
+
+	pub fn service<C: Connection>(&mut self, xconnection: &C, screen: &Screen, panelstate: &Panel, now: Instant) -> Result<(), Box<dyn Error>> {
+		//Answer anything waiting on the bus, then take down bubbles that have had their time.
+		let mut changed = false;
+		while let Some(message) = self.bus.receive()? {
+			changed |= self.handle(xconnection, screen, &message)?;
+		}
+		let expired: Vec<u32> = self.bubbles.iter().filter(|bubble| bubble.expires.is_some_and(|when| now >= when)).map(|bubble| bubble.id).collect();
+		for id in expired {
+			changed |= self.close(xconnection, id, EXPIRED)?;
+		}
+		if changed {
+			self.stack(xconnection, screen, panelstate)?;
+		}
+		Ok(())
+	}
+
+	fn handle<C: Connection>(&mut self, xconnection: &C, screen: &Screen, message: &Message) -> Result<bool, Box<dyn Error>> {
+		//One message off the bus. Returns whether the bubbles changed.
+		if message.path.as_deref() != Some(OBJECT) {
+			if message.kind == METHOD_CALL {
+				self.bus.error(message, "org.freedesktop.DBus.Error.UnknownObject", "Only /org/freedesktop/Notifications is here.")?;
+			}
+			return Ok(false);
+		}
+		if message.is(NAME, "GetCapabilities") {
+			let capabilities = ["actions", "body", "icon-static", "persistence"].iter().map(|capability| Value::Str(capability.to_string())).collect();
+			self.bus.reply(message, vec![Value::Array("s".to_string(), capabilities)])?;
+		} else if message.is(NAME, "GetServerInformation") {
+			let information = ["Tullamore", "Skelefits", env!("CARGO_PKG_VERSION"), "1.2"];
+			self.bus.reply(message, information.iter().map(|text| Value::Str(text.to_string())).collect())?;
+		} else if message.is(NAME, "Notify") {
+			let id = self.notify(xconnection, screen, &message.body)?;
+			self.bus.reply(message, vec![Value::Uint32(id)])?;
+			return Ok(true);
+		} else if message.is(NAME, "CloseNotification") {
+			let id = message.body.first().and_then(|id| id.u32()).unwrap_or(0);
+			let closed = self.close(xconnection, id, CLOSED)?;
+			self.bus.reply(message, Vec::new())?;
+			return Ok(closed);
+		} else if message.is("org.freedesktop.DBus.Peer", "Ping") {
+			self.bus.reply(message, Vec::new())?;
+		} else if message.kind == METHOD_CALL {
+			self.bus.error(message, "org.freedesktop.DBus.Error.UnknownMethod", &format!("No method {}.", message.member.as_deref().unwrap_or_default()))?;
+		}
+		Ok(false)
+	}
+
+	fn notify<C: Connection>(&mut self, xconnection: &C, screen: &Screen, body: &[Value]) -> Result<u32, Box<dyn Error>> {
+		//Notify(app_name, replaces_id, app_icon, summary, body, actions, hints, expire_timeout). Returns the bubble's id.
+		let text = |n: usize| body.get(n).and_then(|value| value.str()).unwrap_or_default().to_string();
+		let replaces = body.get(1).and_then(|id| id.u32()).unwrap_or(0);
+		let actions: Vec<String> = body.get(5).map(|actions| actions.strings()).unwrap_or_default();
+		let hints = body.get(6);
+		let timeout = body.get(7).and_then(|timeout| timeout.i32()).unwrap_or(-1);
+
+		//-1 is up to us, and critical ones stay until they are clicked. 0 is never.
+		let critical = hints.and_then(|hints| hints.lookup("urgency")).and_then(|urgency| urgency.u32()) == Some(2);
+		let expires = match timeout {
+			0 => None,
+			_ if timeout < 0 && critical => None,
+			_ if timeout < 0 => Some(Instant::now() + Duration::from_millis(TIMEOUT)),
+			milliseconds => Some(Instant::now() + Duration::from_millis(milliseconds as u64)),
+		};
+		let icon = findicon(&text(2)).or_else(|| hints.and_then(|hints| hints.lookup("image-path")).and_then(|path| findicon(path.str()?)));
+		let textwidth = WIDTH - PADDING - PADDING - if icon.is_some() { ICON + PADDING } else { 0 };
+		let lines = wrap(&text(4), (textwidth / 6) as usize, MAXLINES);
+		let actions: Vec<(String, String)> = actions.chunks(2).filter(|pair| pair.len() == 2).map(|pair| (pair[0].clone(), pair[1].clone())).collect();
+		let buttons = actions.iter().any(|(key, _)| key != "default");
+		let height = (PADDING + LINE + lines.len() as i16 * LINE + if buttons { BUTTON + PADDING } else { 0 } + PADDING).max(ICON + PADDING + PADDING);
+
+		//Replacing a bubble keeps its place in the stack.
+		if let Some(bubble) = self.bubbles.iter_mut().find(|bubble| replaces != 0 && bubble.id == replaces) {
+			(bubble.icon, bubble.summary, bubble.lines, bubble.actions, bubble.expires, bubble.height) = (icon, text(3), lines, actions, expires, height);
+			xconnection.configure_window(bubble.window, &ConfigureWindowAux::new().height(height as u32 + 1))?;
+			xconnection.clear_area(true, bubble.window, 0, 0, 0, 0)?;
+			return Ok(replaces);
+		}
+
+		self.lastid = self.lastid.checked_add(1).unwrap_or(1);
+		let window = xconnection.generate_id()?;
+		xconnection.create_window(COPY_DEPTH_FROM_PARENT, window, screen.root, 0, 0, WIDTH as u16 + 1, height as u16 + 1, 0, WindowClass::INPUT_OUTPUT, 0, &CreateWindowAux::new().background_pixel(COLOURS[HIGHBACKGROUND_COLOUR]).override_redirect(1).event_mask(EventMask::EXPOSURE | EventMask::BUTTON_PRESS))?;
+		self.bubbles.push(Bubble { id: self.lastid, window, icon, summary: text(3), lines, actions, expires, height });
+		Ok(self.lastid)
+	}
+
+	fn close<C: Connection>(&mut self, xconnection: &C, id: u32, reason: u32) -> Result<bool, Box<dyn Error>> {
+		//Take a bubble down and tell its program why. Returns whether there was one.
+		let Some(index) = self.bubbles.iter().position(|bubble| bubble.id == id) else {
+			return Ok(false);
+		};
+		let bubble = self.bubbles.remove(index);
+		xconnection.destroy_window(bubble.window)?;
+		self.bus.send(Message::signal(OBJECT, NAME, "NotificationClosed", vec![Value::Uint32(id), Value::Uint32(reason)]))?;
+		Ok(true)
+	}
+
+	fn stack<C: Connection>(&self, xconnection: &C, screen: &Screen, panelstate: &Panel) -> Result<(), Box<dyn Error>> {
+		//Line the bubbles up off the end of the notification area, newest nearest the panel.
+		let (x, y, width, height) = panelstate.rect(panelstate.notification());
+		let mut offset = 0;
+		for bubble in self.bubbles.iter().rev() {
+			let start = if panelstate.vertical() { y + height - bubble.height } else { x + width - WIDTH };
+			let (bubblex, bubbley) = panelstate.besidepoint(start, WIDTH + 1, bubble.height + 1, screen.width_in_pixels as i16, screen.height_in_pixels as i16);
+			let bubbley = if panelstate.edge == PanelEdge::Top { bubbley + offset } else { (bubbley - offset).max(0) };
+			xconnection.configure_window(bubble.window, &ConfigureWindowAux::new().x(bubblex as i32).y(bubbley as i32).stack_mode(StackMode::ABOVE))?;
+			xconnection.map_window(bubble.window)?;
+			offset += bubble.height + 1 + STACKGAP;
+		}
+		Ok(())
+	}
+
+	pub fn draw<C: Connection>(&self, xconnection: &C, window: Window, gc_highlight: Gcontext, gc_lowlight: Gcontext, gc_highbackground: Gcontext, gc_lowbackground: Gcontext) -> Result<(), Box<dyn Error>> {
+		let Some(bubble) = self.bubbles.iter().find(|bubble| bubble.window == window) else {
+			return Ok(());
+		};
+		drawsystemframe(xconnection, window, WIDTH, 0, WIDTH, bubble.height, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground);
+		let mut textx = PADDING;
+		if let Some(icon) = &bubble.icon {
+			drawpng(xconnection, window, icon, PADDING, PADDING, ICON as u16, ICON as u16, COLOURS[HIGHBACKGROUND_COLOUR])?;
+			textx += ICON + PADDING;
+		}
+		let columns = ((WIDTH - textx - PADDING) / 6) as usize;
+		xconnection.image_text8(window, gc_lowlight, textx, PADDING + LINE - 4, &latin1(&wrap(&bubble.summary, columns, 1).concat()))?;
+		for (n, line) in bubble.lines.iter().enumerate() {
+			xconnection.image_text8(window, gc_lowlight, textx, PADDING + LINE * (n as i16 + 2) - 4, &latin1(line))?;
+		}
+		let mut poly_lowlight = Vec::new();
+		for (label, (buttonx, buttony, buttonwidth)) in bubble.buttons() {
+			drawbumpyframe(xconnection, window, buttonx, buttony, buttonwidth, BUTTON, gc_highlight, gc_highbackground, gc_lowbackground, &mut poly_lowlight)?;
+			xconnection.image_text8(window, gc_lowlight, buttonx + 6, buttony + BUTTON - 5, &latin1(label))?;
+		}
+		xconnection.poly_segment(window, gc_lowlight, &poly_lowlight)?;
+		Ok(())
+	}
+
+	pub fn click<C: Connection>(&mut self, xconnection: &C, screen: &Screen, panelstate: &Panel, window: Window, x: i16, y: i16) -> Result<bool, Box<dyn Error>> {
+		//A click on a bubble runs the action under it, or the default one, then takes it down. Returns whether it was a bubble.
+		let Some(bubble) = self.bubbles.iter().find(|bubble| bubble.window == window) else {
+			return Ok(false);
+		};
+		let pressed = bubble.buttons().into_iter().find(|(_, (buttonx, buttony, buttonwidth))| x >= *buttonx && x < buttonx + buttonwidth && y >= *buttony && y < buttony + BUTTON);
+		let key = match pressed {
+			Some((label, _)) => bubble.actions.iter().find(|(key, name)| key != "default" && name == label).map(|(key, _)| key.clone()),
+			None => bubble.actions.iter().find(|(key, _)| key == "default").map(|(key, _)| key.clone()),
+		};
+		let id = bubble.id;
+		if let Some(key) = key {
+			self.bus.send(Message::signal(OBJECT, NAME, "ActionInvoked", vec![Value::Uint32(id), Value::Str(key)]))?;
+		}
+		self.close(xconnection, id, DISMISSED)?;
+		self.stack(xconnection, screen, panelstate)?;
+		Ok(true)
+	}
+
+	pub fn post<C: Connection>(&mut self, xconnection: &C, screen: &Screen, panelstate: &Panel, summary: &str, body: &str) -> Result<(), Box<dyn Error>> {
+		//One of our own, like an alarm going off, shown as if it came in over the bus. It stays up until it is clicked.
+		let body = [Value::Str("Tullamore".to_string()), Value::Uint32(0), Value::Str(String::new()), Value::Str(summary.to_string()), Value::Str(body.to_string()), Value::Array("s".to_string(), Vec::new()), Value::Array("{sv}".to_string(), Vec::new()), Value::Int32(0)];
+		self.notify(xconnection, screen, &body)?;
+		self.stack(xconnection, screen, panelstate)
+	}
+
+	pub fn closeall<C: Connection>(&mut self, xconnection: &C) {
+		//The bus went away, so there is nobody to tell.
+		for bubble in self.bubbles.drain(..) {
+			let _ = xconnection.destroy_window(bubble.window);
+		}
+	}
+}
+
+impl Bubble {
+	fn buttons(&self) -> Vec<(&str, (i16, i16, i16))> {
+		//Action buttons along the bottom, right to left from the corner, as (label, (x, y, width)).
+		let mut right = WIDTH - PADDING;
+		let mut buttons = Vec::new();
+		for (_, label) in self.actions.iter().filter(|(key, _)| key != "default").rev() {
+			let width = label.chars().count() as i16 * 6 + 12;
+			if right - width < PADDING {
+				break;
+			}
+			right -= width;
+			buttons.push((label.as_str(), (right, self.height - PADDING - BUTTON, width)));
+			right -= 4;
+		}
+		buttons
+	}
+}
+
+fn findicon(icon: &str) -> Option<String> {
+	//app_icon can be a file:// URI, a path, or an icon name that we look for as name.png next to our own icons.
+	let path = icon.strip_prefix("file://").unwrap_or(icon);
+	let path = if path.is_empty() || path.contains('/') { path.to_string() } else { format!("{}.png", path) };
+	Some(path).filter(|path| path.ends_with(".png") && Path::new(path).is_file())
+}
+
+fn wrap(text: &str, columns: usize, lines: usize) -> Vec<String> {
+	//Word wrap to columns characters a line. Past lines, the last line ends in ... instead.
+	let mut wrapped: Vec<String> = Vec::new();
+	for paragraph in text.lines() {
+		let mut line = String::new();
+		for word in paragraph.split_whitespace() {
+			let mut word = word.to_string();
+			while word.chars().count() > columns && columns > 0 {
+				//Too long for any line. Break it wherever.
+				if !line.is_empty() {
+					wrapped.push(std::mem::take(&mut line));
+				}
+				let split = word.char_indices().nth(columns).map_or(word.len(), |(at, _)| at);
+				wrapped.push(word[..split].to_string());
+				word = word[split..].to_string();
+			}
+			if line.is_empty() {
+				line = word;
+			} else if line.chars().count() + 1 + word.chars().count() <= columns {
+				line.push(' ');
+				line.push_str(&word);
+			} else {
+				wrapped.push(std::mem::replace(&mut line, word));
+			}
+		}
+		if !line.is_empty() {
+			wrapped.push(line);
+		}
+	}
+	if wrapped.len() > lines {
+		wrapped.truncate(lines);
+		if let Some(last) = wrapped.last_mut() {
+			let keep: String = last.chars().take(columns.saturating_sub(3)).collect();
+			*last = format!("{}...", keep);
+		}
+	}
+	wrapped
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn wrapbreaksonwords() {
+		assert_eq!(wrap("the quick brown fox", 10, 6), vec!["the quick", "brown fox"]);
+		assert_eq!(wrap("one\ntwo", 10, 6), vec!["one", "two"]);
+		assert_eq!(wrap("abcdefghijkl", 5, 6), vec!["abcde", "fghij", "kl"]);
+		assert_eq!(wrap("a b c d", 1, 2), vec!["a", "..."]);
+		assert_eq!(wrap("one two three four", 9, 1), vec!["one tw..."]);
+		assert_eq!(wrap("ünïcödé wörds", 7, 6), vec!["ünïcödé", "wörds"]);
+	}
+}
@@ -0,0 +1,70 @@
+use std::{io, os::unix::io::RawFd, time::Duration};
+
+//Waiting on several file descriptors at once, straight from libc so we don't need a crate for it.
+//The event loop sleeps here until X, the session bus or an applet has something for us, or the next timer is due.
+
+const POLLIN: i16 = 0x001;
+const F_GETFL: i32 = 3;
+const F_SETFL: i32 = 4;
+//The flag that makes reads not wait isn't the same number everywhere.
+#[cfg(not(any(target_arch = "mips", target_arch = "mips64", target_arch = "sparc", target_arch = "sparc64")))]
+const O_NONBLOCK: i32 = 0o4000;
+#[cfg(any(target_arch = "mips", target_arch = "mips64"))]
+const O_NONBLOCK: i32 = 0o200;
+#[cfg(any(target_arch = "sparc", target_arch = "sparc64"))]
+const O_NONBLOCK: i32 = 0x4000;
+
+#[repr(C)]
+struct PollFd {
+	fd: i32,
+	events: i16,
+	revents: i16,
+}
+
+extern "C" {
+	fn poll(fds: *mut PollFd, nfds: std::ffi::c_ulong, timeout: i32) -> i32;
+	fn fcntl(fd: i32, cmd: i32, ...) -> i32;
+}
+
+pub fn wait(fds: &[RawFd], timeout: Option<Duration>) -> io::Result<Vec<bool>> {
+	//Which of fds can be read from (or have hung up), after waiting up to timeout for any of them. None waits as long as it takes.
+	let mut pollfds: Vec<PollFd> = fds.iter().map(|&fd| PollFd { fd, events: POLLIN, revents: 0 }).collect();
+	//Round up, so a timer isn't woken for a moment early and has to wait again.
+	let timeout = timeout.map(|timeout| timeout.as_nanos().div_ceil(1_000_000).min(i32::MAX as u128) as i32).unwrap_or(-1);
+	if unsafe { poll(pollfds.as_mut_ptr(), pollfds.len() as std::ffi::c_ulong, timeout) } < 0 {
+		let error = io::Error::last_os_error();
+		//A signal isn't a problem. Whoever called us will look at the timers and come back.
+		return if error.kind() == io::ErrorKind::Interrupted { Ok(vec![false; fds.len()]) } else { Err(error) };
+	}
+	Ok(pollfds.iter().map(|pollfd| pollfd.revents != 0).collect())
+}
+
+pub fn nonblocking(fd: RawFd) -> io::Result<()> {
+	//So reading only takes what is already there. For pipes, which std can't do this for.
+	let flags = unsafe { fcntl(fd, F_GETFL) };
+	if flags < 0 || unsafe { fcntl(fd, F_SETFL, flags | O_NONBLOCK) } < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::{io::{Read, Write}, os::unix::{io::AsRawFd, net::UnixStream}, time::Instant};
+
+	#[test]
+	fn waitsforsomethingtoread() {
+		let (mut a, mut b) = UnixStream::pair().unwrap();
+		let started = Instant::now();
+		assert_eq!(wait(&[a.as_raw_fd(), b.as_raw_fd()], Some(Duration::from_millis(20))).unwrap(), [false, false]);
+		assert!(started.elapsed() >= Duration::from_millis(20));
+		b.write_all(b"x").unwrap();
+		assert_eq!(wait(&[a.as_raw_fd(), b.as_raw_fd()], None).unwrap(), [true, false]);
+
+		nonblocking(a.as_raw_fd()).unwrap();
+		let mut buffer = [0; 4];
+		assert_eq!(a.read(&mut buffer).unwrap(), 1);
+		assert_eq!(a.read(&mut buffer).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+	}
+}
@@ -0,0 +1,70 @@
+use std::error::Error;
+use x11rb::{
+    connection::Connection,
+    protocol::xproto::*,
+    COPY_DEPTH_FROM_PARENT,
+};
+
+use crate::panel::Panel;
+use crate::trundle::drawsystemframe;
+use crate::trundle::squishtext;
+use crate::window::drawwindowicon;
+use crate::trundle::{COLOURS, HIGHBACKGROUND_COLOUR};
+
+const ITEM: i16 = 20;
+const OFFSET: i16 = 3;
+const MINWIDTH: i16 = 120;
+const MAXWIDTH: i16 = 300;
+
+//A list of windows (or anything else with a label and an icon) that pops up over the panel.
+//Unlike the Clicker it isn't a managed window, so it never gets a taskbar button or the focus.
+pub struct Popup {
+	pub window: Window,
+	pub owner: usize, //Panel index of the button that opened it.
+	pub items: Vec<PopupItem>,
+	width: i16,
+	height: i16,
+}
+
+pub struct PopupItem {
+	pub label: String,
+	pub icon: Option<Vec<u32>>,
+	pub value: u32, //What the item stands for. For a taskbar group, the client window.
+}
+
+impl Popup {
+	pub fn open<C: Connection>(xconnection: &C, screen: &Screen, panelstate: &Panel, owner: usize, items: Vec<PopupItem>) -> Result<Popup, Box<dyn Error>> {
+		//Open the list next to the panel element at owner, kept on screen.
+		let longest = items.iter().map(|item| item.label.len() as i16).max().unwrap_or(0);
+		let width = (longest * 6 + 36).clamp(MINWIDTH, MAXWIDTH);
+		let height = items.len() as i16 * ITEM + OFFSET + OFFSET;
+		let (x, y) = panelstate.beside(owner, width + 1, height + 1, screen.width_in_pixels as i16, screen.height_in_pixels as i16);
+
+		let window = xconnection.generate_id()?;
+		xconnection.create_window(COPY_DEPTH_FROM_PARENT, window, screen.root, x, y, width as u16 + 1, height as u16 + 1, 0, WindowClass::INPUT_OUTPUT, 0, &CreateWindowAux::new().background_pixel(COLOURS[HIGHBACKGROUND_COLOUR]).override_redirect(1).event_mask(EventMask::EXPOSURE | EventMask::BUTTON_PRESS))?;
+		xconnection.map_window(window)?;
+		xconnection.configure_window(window, &ConfigureWindowAux::new().stack_mode(StackMode::ABOVE))?;
+		Ok(Popup { window, owner, items, width, height })
+	}
+
+	pub fn draw<C: Connection>(&self, xconnection: &C, gc_highlight: Gcontext, gc_lowlight: Gcontext, gc_highbackground: Gcontext, gc_lowbackground: Gcontext) {
+		drawsystemframe(xconnection, self.window, self.width, 0, self.width, self.height, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground);
+		for (i, item) in self.items.iter().enumerate() {
+			let top = OFFSET + i as i16 * ITEM;
+			drawwindowicon(xconnection, self.window, item.icon.as_deref(), OFFSET + 4, top + 2, COLOURS[HIGHBACKGROUND_COLOUR]);
+			let _ = xconnection.image_text8(self.window, gc_lowlight, OFFSET + 26, top + 14, squishtext(&item.label, self.width - OFFSET - 30, 6).as_bytes());
+		}
+	}
+
+	pub fn item(&self, y: i16) -> Option<&PopupItem> {
+		//The item under y, if any.
+		if y < OFFSET {
+			return None;
+		}
+		self.items.get(((y - OFFSET) / ITEM) as usize)
+	}
+
+	pub fn close<C: Connection>(&self, xconnection: &C) {
+		let _ = xconnection.destroy_window(self.window);
+	}
+}
@@ -0,0 +1,291 @@
+use lazy_static::lazy_static;
+
+use crate::panel::PanelIcon;
+
+//Settings are read once at startup from settings.txt, one "key = value" per line.
+//Lines starting with # are comments. Anything missing or unreadable falls back to the default.
+//A [link] line starts a quick-launch link, and the lines after it up to the next [link] describe it. [applet] works the same way for tray applets.
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DragMode {
+	Outline, //Draw a chunky XOR outline and only move the frame on release.
+	Opaque,  //Move the frame with the pointer.
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FocusMode {
+	Click,  //Click a window to focus and raise it.
+	Mouse,  //Focus follows the pointer, and the desktop takes focus when the pointer is over it.
+	Sloppy, //Focus follows the pointer, but stays put when the pointer is over the desktop.
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Placement {
+	None,    //Wherever the client asks, usually the top left corner.
+	Cascade, //Down and to the right from the top left, to the first step no other window is on.
+	Centre,  //Middle of the screen.
+	Pointer, //Centred under the pointer.
+	Smart,   //Wherever it overlaps other windows the least.
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ClockHours {
+	Twelve,     //3:07 PM
+	TwentyFour, //15:07
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PanelEdge {
+	Top,
+	Bottom,
+	Left,
+	Right,
+}
+
+pub struct Settings {
+	pub dragmode: DragMode,
+	pub snapdistance: i16, //How close (in pixels) a frame must get to an edge before it snaps. 0 turns snapping off.
+	pub focusmode: FocusMode,
+	pub autoraise: Option<u64>, //Milliseconds before a window focused by the pointer is raised. None leaves stacking to clicks.
+	pub placement: Placement, //For windows that don't ask for a position of their own.
+	pub grouping: bool, //Fold taskbar buttons of the same program into one when they don't all fit.
+	pub paneledge: PanelEdge,
+	pub panelsize: i16, //Height of the panel, or width down the side of the screen. Made bigger if things don't fit.
+	pub autohide: bool, //Slide the panel off screen until the pointer touches that edge.
+	pub clock: ClockHours,
+	pub clockseconds: bool,
+	pub clockdate: bool, //Day and month in front of the time, like Mon 19 Oct.
+	pub links: Vec<PanelIcon>, //Quick-launch links, in order. None means Tullamore's own.
+	pub applets: Vec<PanelIcon>, //Programs that drive a tray icon through their output. The icon, label and tooltip here are what shows until they say otherwise.
+	pub battery: bool, //Tray applets that can be turned off.
+	pub network: bool,
+	pub monitor: bool, //Off unless asked for, as it wakes us every second.
+	pub sysfsroot: String, //Where sysfs and procfs are, for the tray applets. Only ever somewhere else for testing.
+	pub procroot: String,
+	pub batterylow: u64, //Percentages for the battery applet to warn at.
+	pub batterycritical: u64,
+	pub cpuwarning: u64, //Where the system monitor's graph turns red: percent busy, percent of memory used and degrees Celsius.
+	pub memorywarning: u64,
+	pub temperaturewarning: i64,
+}
+
+impl Settings {
+	pub fn new() -> Self {
+		Settings {
+			dragmode: DragMode::Outline,
+			snapdistance: 10,
+			focusmode: FocusMode::Click,
+			autoraise: None,
+			placement: Placement::Smart,
+			grouping: true,
+			paneledge: PanelEdge::Bottom,
+			panelsize: 28,
+			autohide: false,
+			clock: ClockHours::Twelve,
+			clockseconds: false,
+			clockdate: false,
+			links: Vec::new(),
+			applets: Vec::new(),
+			battery: true,
+			network: true,
+			monitor: false,
+			sysfsroot: "/sys".to_string(),
+			procroot: "/proc".to_string(),
+			batterylow: 20,
+			batterycritical: 5,
+			cpuwarning: 90,
+			memorywarning: 90,
+			temperaturewarning: 80,
+		}
+	}
+}
+
+lazy_static! {
+	pub static ref SETTINGS: Settings = loadsettings("settings.txt");
+}
+
+pub fn loadsettings(file_path: &str) -> Settings {
+	let mut settings = Settings::new();
+	let contents = match std::fs::read_to_string(file_path) {
+		Ok(contents) => contents,
+		Err(_) => return settings,
+	};
+
+	let mut section = "";
+	for line in contents.lines() {
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') {
+			continue;
+		}
+		if line == "[link]" {
+			settings.links.push(PanelIcon::default());
+			section = "link";
+		} else if line == "[applet]" {
+			settings.applets.push(PanelIcon::default());
+			section = "applet";
+		} else if let Some((key, value)) = line.split_once('=') {
+			match section {
+				"link" => applylink(settings.links.last_mut().unwrap(), key.trim(), value.trim()),
+				"applet" => applylink(settings.applets.last_mut().unwrap(), key.trim(), value.trim()),
+				_ => applysetting(&mut settings, key.trim(), value.trim()),
+			}
+		} else {
+			eprintln!("Settings: ignoring line '{}'", line);
+		}
+	}
+
+	settings.links.retain(|link| {
+		if link.program.is_empty() {
+			eprintln!("Settings: ignoring link '{}', it has no command", link.label);
+		}
+		!link.program.is_empty()
+	});
+	settings.applets.retain(|applet| {
+		if applet.program.is_empty() {
+			eprintln!("Settings: ignoring applet '{}', it has no command", applet.label);
+		}
+		!applet.program.is_empty()
+	});
+	for applet in &mut settings.applets {
+		//No default icon. It stays out of the tray until it has one.
+		if applet.label.is_empty() {
+			applet.label = applet.program.clone();
+		}
+	}
+	for link in &mut settings.links {
+		if link.label.is_empty() {
+			link.label = link.program.clone();
+		}
+		if link.tooltip.is_empty() {
+			link.tooltip = link.label.clone();
+		}
+		if link.icon.is_empty() {
+			link.icon = "computer.png".to_string();
+		}
+	}
+	settings
+}
+
+fn applylink(link: &mut PanelIcon, key: &str, value: &str) {
+	match key {
+		"label" => link.label = value.to_string(),
+		"tooltip" => link.tooltip = value.to_string(),
+		"icon" => link.icon = value.to_string(),
+		"command" => link.program = value.to_string(),
+		"directory" => link.directory = value.to_string(),
+		"environment" => match value.split_once('=') {
+			Some((name, value)) => link.environment.push((name.trim().to_string(), value.trim().to_string())),
+			None => eprintln!("Settings: environment should be NAME=value, not '{}'", value),
+		},
+		_ => eprintln!("Settings: unknown link setting '{}'", key),
+	}
+}
+
+fn applysetting(settings: &mut Settings, key: &str, value: &str) {
+	match key {
+		"drag" => match value {
+			"outline" => settings.dragmode = DragMode::Outline,
+			"opaque" => settings.dragmode = DragMode::Opaque,
+			_ => eprintln!("Settings: unknown drag mode '{}'", value),
+		},
+		"snap" => match value.parse() {
+			Ok(distance) => settings.snapdistance = distance,
+			Err(_) => eprintln!("Settings: snap should be a number of pixels, not '{}'", value),
+		},
+		"focus" => match value {
+			"click" => settings.focusmode = FocusMode::Click,
+			"mouse" => settings.focusmode = FocusMode::Mouse,
+			"sloppy" => settings.focusmode = FocusMode::Sloppy,
+			_ => eprintln!("Settings: unknown focus mode '{}'", value),
+		},
+		"autoraise" => match value {
+			"off" => settings.autoraise = None,
+			_ => match value.parse() {
+				Ok(delay) => settings.autoraise = Some(delay),
+				Err(_) => eprintln!("Settings: autoraise should be off or a delay in milliseconds, not '{}'", value),
+			},
+		},
+		"placement" => match value {
+			"none" => settings.placement = Placement::None,
+			"cascade" => settings.placement = Placement::Cascade,
+			"centre" | "center" => settings.placement = Placement::Centre,
+			"pointer" => settings.placement = Placement::Pointer,
+			"smart" => settings.placement = Placement::Smart,
+			_ => eprintln!("Settings: unknown placement '{}'", value),
+		},
+		"group" => match value {
+			"overflow" => settings.grouping = true,
+			"never" => settings.grouping = false,
+			_ => eprintln!("Settings: group should be overflow or never, not '{}'", value),
+		},
+		"panel" => match value {
+			"top" => settings.paneledge = PanelEdge::Top,
+			"bottom" => settings.paneledge = PanelEdge::Bottom,
+			"left" => settings.paneledge = PanelEdge::Left,
+			"right" => settings.paneledge = PanelEdge::Right,
+			_ => eprintln!("Settings: panel should be top, bottom, left or right, not '{}'", value),
+		},
+		"panelsize" => match value.parse() {
+			Ok(size) => settings.panelsize = size,
+			Err(_) => eprintln!("Settings: panelsize should be a number of pixels, not '{}'", value),
+		},
+		"autohide" => match value {
+			"on" => settings.autohide = true,
+			"off" => settings.autohide = false,
+			_ => eprintln!("Settings: autohide should be on or off, not '{}'", value),
+		},
+		"clock" => match value {
+			"12" => settings.clock = ClockHours::Twelve,
+			"24" => settings.clock = ClockHours::TwentyFour,
+			_ => eprintln!("Settings: clock should be 12 or 24, not '{}'", value),
+		},
+		"clockseconds" => match value {
+			"on" => settings.clockseconds = true,
+			"off" => settings.clockseconds = false,
+			_ => eprintln!("Settings: clockseconds should be on or off, not '{}'", value),
+		},
+		"clockdate" => match value {
+			"on" => settings.clockdate = true,
+			"off" => settings.clockdate = false,
+			_ => eprintln!("Settings: clockdate should be on or off, not '{}'", value),
+		},
+		"battery" => match value {
+			"on" => settings.battery = true,
+			"off" => settings.battery = false,
+			_ => eprintln!("Settings: battery should be on or off, not '{}'", value),
+		},
+		"network" => match value {
+			"on" => settings.network = true,
+			"off" => settings.network = false,
+			_ => eprintln!("Settings: network should be on or off, not '{}'", value),
+		},
+		"monitor" => match value {
+			"on" => settings.monitor = true,
+			"off" => settings.monitor = false,
+			_ => eprintln!("Settings: monitor should be on or off, not '{}'", value),
+		},
+		"sysfsroot" => settings.sysfsroot = value.to_string(),
+		"procroot" => settings.procroot = value.to_string(),
+		"batterylow" => match value.parse() {
+			Ok(percent) => settings.batterylow = percent,
+			Err(_) => eprintln!("Settings: batterylow should be a percentage, not '{}'", value),
+		},
+		"batterycritical" => match value.parse() {
+			Ok(percent) => settings.batterycritical = percent,
+			Err(_) => eprintln!("Settings: batterycritical should be a percentage, not '{}'", value),
+		},
+		"cpuwarning" => match value.parse() {
+			Ok(percent) => settings.cpuwarning = percent,
+			Err(_) => eprintln!("Settings: cpuwarning should be a percentage, not '{}'", value),
+		},
+		"memorywarning" => match value.parse() {
+			Ok(percent) => settings.memorywarning = percent,
+			Err(_) => eprintln!("Settings: memorywarning should be a percentage, not '{}'", value),
+		},
+		"temperaturewarning" => match value.parse() {
+			Ok(degrees) => settings.temperaturewarning = degrees,
+			Err(_) => eprintln!("Settings: temperaturewarning should be in degrees Celsius, not '{}'", value),
+		},
+		_ => eprintln!("Settings: unknown setting '{}'", key),
+	}
+}
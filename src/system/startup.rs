@@ -0,0 +1,142 @@
+use std::{
+	error::Error,
+	os::unix::process::CommandExt,
+	process::Command,
+	thread,
+	time::{Duration, Instant},
+};
+use x11rb::{
+	connection::Connection,
+	protocol::{
+		Event,
+		xproto::*,
+	},
+	wrapper::ConnectionExt as _,
+	NONE,
+};
+
+pub struct Options {
+	pub display: Option<String>, //None means use $DISPLAY.
+	pub replace: bool,
+}
+
+//How the event loop finished.
+pub enum Exit {
+	Quit,
+	Restart, //Start a fresh copy of ourselves, which adopts the windows we hand back.
+}
+
+pub const USAGE: &str = "Usage: tullamore [--display <display>] [--replace]\n  --display <display>  X display to manage, instead of $DISPLAY.\n  --replace            Take over from a window manager that is already running.";
+
+pub fn parsearguments(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+	let mut options = Options { display: None, replace: false };
+	while let Some(arg) = args.next() {
+		if arg == "--replace" {
+			options.replace = true;
+		} else if arg == "--display" || arg == "-display" {
+			match args.next() {
+				Some(display) => options.display = Some(display),
+				None => return Err(format!("{} needs a display, like :0", arg)),
+			}
+		} else if let Some(display) = arg.strip_prefix("--display=") {
+			options.display = Some(display.to_string());
+		} else {
+			return Err(format!("Unknown option '{}'", arg));
+		}
+	}
+	Ok(options)
+}
+
+pub fn acquiremanager<C: Connection>(xconnection: &C, screen: &Screen, screenid: usize, replace: bool) -> Result<(Window, Atom), Box<dyn Error>> {
+	//ICCCM section 2.8: whoever owns WM_S<screen> is the window manager for that screen.
+	let selection = xconnection.intern_atom(false, format!("WM_S{}", screenid).as_bytes())?.reply()?.atom;
+	let previous = xconnection.get_selection_owner(selection)?.reply()?.owner;
+
+	if previous != NONE && !replace {
+		return Err(format!("Another window manager is already running on screen {} (WM_S{} is owned by window 0x{:x}). Start with --replace to take over from it.", screenid, screenid, previous).into());
+	}
+
+	//The selection needs a window of ours to own it. It never gets mapped.
+	let manager = xconnection.generate_id()?;
+	xconnection.create_window(0, manager, screen.root, -1, -1, 1, 1, 0, WindowClass::INPUT_ONLY, 0, &CreateWindowAux::new().override_redirect(1).event_mask(EventMask::PROPERTY_CHANGE))?;
+	xconnection.change_property8(PropMode::REPLACE, manager, AtomEnum::WM_NAME, AtomEnum::STRING, b"Tullamore")?;
+
+	if previous != NONE {
+		//Watch the old manager's window so we know when it has given up.
+		xconnection.change_window_attributes(previous, &ChangeWindowAttributesAux::default().event_mask(EventMask::STRUCTURE_NOTIFY))?;
+	}
+	xconnection.flush()?;
+
+	//Setting the selection needs a real timestamp, and the PropertyNotify from WM_NAME above carries one.
+	let time = propertytime(xconnection, manager)?;
+
+	xconnection.set_selection_owner(manager, selection, time)?;
+	if xconnection.get_selection_owner(selection)?.reply()?.owner != manager {
+		return Err(format!("Could not become the window manager for screen {}.", screenid).into());
+	}
+
+	if previous != NONE {
+		println!("Asking the running window manager to step aside...");
+		let deadline = Instant::now() + Duration::from_secs(5);
+		let mut gone = false;
+		while !gone && Instant::now() < deadline {
+			match xconnection.poll_for_event()? {
+				Some(Event::DestroyNotify(destroy)) if destroy.window == previous => gone = true,
+				Some(_) => {},
+				None => thread::sleep(Duration::from_millis(10)),
+			}
+		}
+		if !gone {
+			eprintln!("The previous window manager has not exited yet, carrying on anyway.");
+		}
+	}
+
+	//Tell everyone there is a new manager in town.
+	let manageratom = xconnection.intern_atom(false, b"MANAGER")?.reply()?.atom;
+	let message = ClientMessageEvent::new(32, screen.root, manageratom, [time, selection, manager, 0, 0]);
+	xconnection.send_event(false, screen.root, EventMask::STRUCTURE_NOTIFY, message)?;
+	xconnection.flush()?;
+
+	Ok((manager, selection))
+}
+
+pub fn propertytime<C: Connection>(xconnection: &C, window: Window) -> Result<Timestamp, Box<dyn Error>> {
+	//The server's time, from the PropertyNotify for a property we just changed on window. It has to be selecting PropertyChange.
+	//Anything else that turns up in the meantime is dropped, so this is only for before the event loop starts.
+	loop {
+		if let Event::PropertyNotify(notify) = xconnection.wait_for_event()? {
+			if notify.window == window {
+				return Ok(notify.time);
+			}
+		}
+	}
+}
+
+pub fn redirectroot<C: Connection>(xconnection: &C, root: Window, background: Option<u32>) -> Result<(), Box<dyn Error>> {
+	//Only one client may select SubstructureRedirect on the root. If this fails, somebody else is managing windows without owning WM_S<n>.
+	let aux = ChangeWindowAttributesAux::default().event_mask(EventMask::BUTTON_PRESS | EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY | EventMask::ENTER_WINDOW).background_pixel(background);
+	if xconnection.change_window_attributes(root, &aux)?.check().is_err() {
+		return Err("Another window manager is already running (could not redirect the root window). Close it first, or start with --replace if it supports being replaced.".into());
+	}
+	xconnection.clear_area(false, root, 0, 0, 0, 0)?;
+	Ok(())
+}
+
+pub fn grabmovebutton<C: Connection>(xconnection: &C, root: Window) -> Result<(), Box<dyn Error>> {
+	//Alt and the left button drags a window from anywhere in it, which is the only way to move windows without a title bar.
+	//Grabbed once on the root, with and without Caps Lock and Num Lock, so it wins over whatever the window under the pointer wants.
+	for locks in [ModMask::from(0u16), ModMask::LOCK, ModMask::M2, ModMask::LOCK | ModMask::M2] {
+		xconnection.grab_button(false, root, EventMask::BUTTON_PRESS | EventMask::BUTTON_RELEASE | EventMask::POINTER_MOTION, GrabMode::ASYNC, GrabMode::ASYNC, NONE, NONE, ButtonIndex::M1, ModMask::M1 | locks)?;
+	}
+	Ok(())
+}
+
+pub fn restart() -> Box<dyn Error> {
+	//exec only comes back if it failed. The new process keeps our arguments, so --display sticks.
+	let program = match std::env::current_exe() {
+		Ok(program) => program,
+		Err(e) => return e.into(),
+	};
+	println!("Restarting {}...", program.display());
+	Command::new(program).args(std::env::args().skip(1)).exec().into()
+}
@@ -0,0 +1,79 @@
+use std::time::{Duration, Instant};
+
+//Everything the event loop has to do later rather than in answer to an event. Each kind of timer has one slot on the wheel,
+//so setting it again moves it rather than adding another. The loop sleeps until the soonest, then runs whichever are due.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Timer {
+	Clock, //Next time the clock's text changes. Alarms go off on it too.
+	Raise, //Autoraise the frame the pointer is resting on.
+	Flash, //Urgent taskbar buttons change colour.
+	Tooltip, //The pointer has rested on the panel long enough.
+	Hide, //Tuck the autohidden panel away.
+	Notifications, //A notification bubble runs out of time.
+	Applets, //The battery and network applets take another look.
+	Monitor, //The system monitor's graph moves along.
+	External, //An applet from the settings that quit gets run again.
+}
+
+const TIMERS: [Timer; 9] = [Timer::Clock, Timer::Raise, Timer::Flash, Timer::Tooltip, Timer::Hide, Timer::Notifications, Timer::Applets, Timer::Monitor, Timer::External];
+
+pub struct Timers {
+	slots: [Option<Instant>; TIMERS.len()],
+}
+
+impl Timers {
+	pub fn new() -> Timers {
+		Timers { slots: [None; TIMERS.len()] }
+	}
+
+	pub fn set(&mut self, timer: Timer, when: Option<Instant>) {
+		//None stops it.
+		self.slots[timer as usize] = when;
+	}
+
+	pub fn after(&mut self, timer: Timer, milliseconds: u64) {
+		self.set(timer, Some(Instant::now() + Duration::from_millis(milliseconds)));
+	}
+
+	pub fn pending(&self, timer: Timer) -> bool {
+		self.slots[timer as usize].is_some()
+	}
+
+	pub fn next(&self) -> Option<Instant> {
+		self.slots.iter().flatten().min().copied()
+	}
+
+	pub fn due(&mut self, now: Instant) -> Vec<Timer> {
+		//Take the timers that are up off the wheel, in the order they are listed. Anything that repeats sets itself again.
+		TIMERS.into_iter().filter(|&timer| {
+			let due = self.slots[timer as usize].is_some_and(|when| when <= now);
+			if due {
+				self.slots[timer as usize] = None;
+			}
+			due
+		}).collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn soonestfirstandonlyonce() {
+		let mut timers = Timers::new();
+		assert_eq!(timers.next(), None);
+		let now = Instant::now();
+		timers.set(Timer::Hide, Some(now + Duration::from_millis(50)));
+		timers.set(Timer::Raise, Some(now + Duration::from_millis(10)));
+		timers.set(Timer::Monitor, Some(now + Duration::from_millis(30)));
+		timers.set(Timer::Monitor, Some(now + Duration::from_millis(100)));
+		assert_eq!(timers.next(), Some(now + Duration::from_millis(10)));
+		assert_eq!(timers.due(now + Duration::from_millis(60)), [Timer::Raise, Timer::Hide]);
+		assert_eq!(timers.due(now + Duration::from_millis(60)), []);
+		assert!(timers.pending(Timer::Monitor));
+		timers.set(Timer::Monitor, None);
+		assert_eq!(timers.next(), None);
+	}
+}
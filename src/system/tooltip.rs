@@ -0,0 +1,75 @@
+use std::error::Error;
+use x11rb::{
+    connection::Connection,
+    protocol::xproto::*,
+    COPY_DEPTH_FROM_PARENT,
+};
+
+use crate::panel::Panel;
+use crate::trundle::{latin1, squishtext, COLOURS, HIGHBACKGROUND_COLOUR};
+
+pub const DELAY: u64 = 600; //Milliseconds the pointer has to rest on something in the panel before its tooltip shows.
+const PADDING: i16 = 4;
+const HEIGHT: i16 = 18;
+const MAXWIDTH: i16 = 600; //Long window titles and applet tooltips get cut down to this.
+
+//A line of text about whatever the pointer is resting on in the panel.
+//Like the popup it is override-redirect, so it never takes the focus.
+pub struct Tooltip {
+	pub window: Window,
+	pub target: (usize, Option<usize>), //Panel index it is for, and which tray icon if it is the notification area.
+	text: Vec<u8>, //Latin-1, ready for the core font.
+	width: i16,
+}
+
+impl Tooltip {
+	pub fn open<C: Connection>(xconnection: &C, screen: &Screen, panelstate: &Panel, target: (usize, Option<usize>), at: i16, text: String) -> Result<Tooltip, Box<dyn Error>> {
+		//Open it just off the panel at at (along the panel), kept on screen.
+		let (text, width) = fit(&text, screen.width_in_pixels as i16);
+		let (x, y) = panelstate.besidepoint(at, width + 1, HEIGHT + 1, screen.width_in_pixels as i16, screen.height_in_pixels as i16);
+
+		let window = xconnection.generate_id()?;
+		xconnection.create_window(COPY_DEPTH_FROM_PARENT, window, screen.root, x, y, width as u16 + 1, HEIGHT as u16 + 1, 0, WindowClass::INPUT_OUTPUT, 0, &CreateWindowAux::new().background_pixel(COLOURS[HIGHBACKGROUND_COLOUR]).override_redirect(1).event_mask(EventMask::EXPOSURE))?;
+		xconnection.map_window(window)?;
+		xconnection.configure_window(window, &ConfigureWindowAux::new().stack_mode(StackMode::ABOVE))?;
+		Ok(Tooltip { window, target, text, width })
+	}
+
+	pub fn draw<C: Connection>(&self, xconnection: &C, gc_lowlight: Gcontext) {
+		//Thin dark border, text inside.
+		let _ = xconnection.poly_rectangle(self.window, gc_lowlight, &[Rectangle { x: 0, y: 0, width: self.width as u16, height: HEIGHT as u16 }]);
+		let _ = xconnection.image_text8(self.window, gc_lowlight, PADDING, HEIGHT - 5, &self.text);
+	}
+
+	pub fn close<C: Connection>(&self, xconnection: &C) {
+		let _ = xconnection.destroy_window(self.window);
+	}
+}
+
+fn fit(text: &str, screenwidth: i16) -> (Vec<u8>, i16) {
+	//The text cut down to fit on the screen (and in one image_text8), and the tooltip width that goes with it.
+	let text = squishtext(text, MAXWIDTH.min(screenwidth - 1) - PADDING - PADDING + 2, 6);
+	let text = latin1(&text);
+	let width = text.len() as i16 * 6 + PADDING + PADDING;
+	(text, width)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn longtextiscutdown() {
+		assert_eq!(fit("xterm", 800), (b"xterm".to_vec(), 5 * 6 + 8));
+		let (text, width) = fit(&"é".repeat(1000), 800);
+		assert!(width <= MAXWIDTH);
+		assert!(text.len() < 256 && text.ends_with(b"...") && text[0] == 0xe9);
+		let (_, width) = fit(&"x".repeat(1000), 200);
+		assert!(width < 200);
+	}
+
+	#[test]
+	fn latin1fallsback() {
+		assert_eq!(latin1("café ☕"), vec![b'c', b'a', b'f', 0xe9, b' ', b'?']);
+	}
+}
@@ -0,0 +1,161 @@
+use std::error::Error;
+use x11rb::{
+	connection::Connection,
+	protocol::xproto::*,
+	wrapper::ConnectionExt as _,
+	CURRENT_TIME,
+	NONE,
+};
+
+use crate::panel::{Panel, PanelIcon};
+use crate::system::startup::propertytime;
+use crate::trundle::{grabwindowtitle, COLOURS, HIGHBACKGROUND_COLOUR};
+
+x11rb::atom_manager! {
+	pub TrayAtoms: TrayAtomsCookie {
+		_NET_SYSTEM_TRAY_OPCODE,
+		_NET_SYSTEM_TRAY_ORIENTATION,
+		_XEMBED,
+		_XEMBED_INFO,
+		MANAGER,
+	}
+}
+
+//System tray opcodes (freedesktop System Tray spec). The balloon messages are ignored.
+const SYSTEM_TRAY_REQUEST_DOCK: u32 = 0;
+
+//XEmbed messages and _XEMBED_INFO flags.
+const XEMBED_EMBEDDED_NOTIFY: u32 = 0;
+const XEMBED_VERSION: u32 = 0;
+const XEMBED_MAPPED: u32 = 1 << 0;
+
+//Docked icons get the same space as our own tray icons.
+const ICON_SIZE: u16 = 16;
+
+//The notification area's half of the system tray protocol. Tray programs find us through _NET_SYSTEM_TRAY_S<screen>
+//and ask to dock, then we take their icon window into the panel with XEmbed.
+pub struct Tray {
+	pub manager: Window,
+	pub atoms: TrayAtoms,
+	pub hidden: Vec<Window>, //Docked, but asked not to be shown for now. Their place in the panel is given up until they are.
+}
+
+impl Tray {
+	pub fn acquire<C: Connection>(xconnection: &C, screen: &Screen, screenid: usize, vertical: bool) -> Result<Option<Tray>, Box<dyn Error>> {
+		//Own _NET_SYSTEM_TRAY_S<screen>, unless another tray already does. Then there is just no tray.
+		let atoms = TrayAtoms::new(xconnection)?.reply()?;
+		let selection = xconnection.intern_atom(false, format!("_NET_SYSTEM_TRAY_S{}", screenid).as_bytes())?.reply()?.atom;
+		let owner = xconnection.get_selection_owner(selection)?.reply()?.owner;
+		if owner != NONE {
+			eprintln!("Another system tray is already running (window 0x{:x}), so tray programs will dock there instead.", owner);
+			return Ok(None);
+		}
+
+		let manager = xconnection.generate_id()?;
+		xconnection.create_window(0, manager, screen.root, -1, -1, 1, 1, 0, WindowClass::INPUT_ONLY, 0, &CreateWindowAux::new().override_redirect(1).event_mask(EventMask::PROPERTY_CHANGE))?;
+		//0 is horizontal, 1 is vertical. It tells the icons which way the tray grows.
+		xconnection.change_property32(PropMode::REPLACE, manager, atoms._NET_SYSTEM_TRAY_ORIENTATION, AtomEnum::CARDINAL, &[vertical as u32])?;
+		xconnection.flush()?;
+		let time = propertytime(xconnection, manager)?;
+
+		xconnection.set_selection_owner(manager, selection, time)?;
+		if xconnection.get_selection_owner(selection)?.reply()?.owner != manager {
+			eprintln!("Could not become the system tray.");
+			xconnection.destroy_window(manager)?;
+			return Ok(None);
+		}
+
+		//Tray programs that started before us are waiting for this.
+		let message = ClientMessageEvent::new(32, screen.root, atoms.MANAGER, [time, selection, manager, 0, 0]);
+		xconnection.send_event(false, screen.root, EventMask::STRUCTURE_NOTIFY, message)?;
+		Ok(Some(Tray { manager, atoms, hidden: Vec::new() }))
+	}
+
+	pub fn dockrequest(&self, message: &ClientMessageEvent) -> Option<Window> {
+		//The icon window, if this is a tray program asking to dock.
+		let data = message.data.as_data32();
+		if message.window == self.manager && message.type_ == self.atoms._NET_SYSTEM_TRAY_OPCODE && data[1] == SYSTEM_TRAY_REQUEST_DOCK && data[2] != NONE {
+			Some(data[2])
+		} else {
+			None
+		}
+	}
+
+	pub fn docked(&self, panelstate: &Panel, window: Window) -> bool {
+		self.hidden.contains(&window) || panelstate.trayicons.iter().any(|icon| icon.window == Some(window))
+	}
+
+	pub fn dock<C: Connection>(&mut self, xconnection: &C, panel: Window, panelstate: &mut Panel, window: Window) -> Result<(), Box<dyn Error>> {
+		//Take the icon into the panel and tell it so. It only gets shown if _XEMBED_INFO says it wants to be.
+		if self.docked(panelstate, window) {
+			return Ok(());
+		}
+		//An icon that is gone already makes the first request fail, and that's the end of it.
+		if xconnection.change_window_attributes(window, &ChangeWindowAttributesAux::new().event_mask(EventMask::STRUCTURE_NOTIFY | EventMask::PROPERTY_CHANGE).background_pixel(COLOURS[HIGHBACKGROUND_COLOUR]))?.check().is_err() {
+			return Ok(());
+		}
+		//In the save set, so it goes back to the root rather than dying with the panel if we exit.
+		xconnection.change_save_set(SetMode::INSERT, window)?;
+		xconnection.unmap_window(window)?;
+		xconnection.reparent_window(window, panel, 0, 0)?;
+		xconnection.configure_window(window, &ConfigureWindowAux::new().width(ICON_SIZE as u32).height(ICON_SIZE as u32).border_width(0))?;
+		let message = ClientMessageEvent::new(32, window, self.atoms._XEMBED, [CURRENT_TIME, XEMBED_EMBEDDED_NOTIFY, 0, panel, XEMBED_VERSION]);
+		xconnection.send_event(false, window, EventMask::NO_EVENT, message)?;
+
+		self.hidden.push(window);
+		self.update(xconnection, panelstate, window)?;
+		Ok(())
+	}
+
+	pub fn update<C: Connection>(&mut self, xconnection: &C, panelstate: &mut Panel, window: Window) -> Result<bool, Box<dyn Error>> {
+		//Show or hide a docked icon to match its _XEMBED_INFO. Returns whether the tray changed.
+		let Some(mapped) = self.mapped(xconnection, window) else {
+			return Ok(false);
+		};
+		let shown = panelstate.trayicons.iter().any(|icon| icon.window == Some(window));
+		if mapped && !shown {
+			self.hidden.retain(|&hidden| hidden != window);
+			let title = grabwindowtitle(xconnection, window).ok().flatten().unwrap_or_default();
+			panelstate.addtrayicon(PanelIcon { label: title.clone(), tooltip: title, window: Some(window), ..Default::default() });
+			place(xconnection, panelstate)?;
+			xconnection.map_window(window)?;
+		} else if !mapped && shown {
+			xconnection.unmap_window(window)?;
+			panelstate.removetrayicon(window);
+			place(xconnection, panelstate)?;
+			self.hidden.push(window);
+		} else {
+			return Ok(false);
+		}
+		Ok(true)
+	}
+
+	pub fn undock<C: Connection>(&mut self, xconnection: &C, panelstate: &mut Panel, window: Window) -> Result<bool, Box<dyn Error>> {
+		//The icon was destroyed or taken somewhere else. Returns whether it was one of ours.
+		let hidden = self.hidden.len();
+		self.hidden.retain(|&other| other != window);
+		if panelstate.removetrayicon(window) {
+			place(xconnection, panelstate)?;
+			return Ok(true);
+		}
+		Ok(self.hidden.len() != hidden)
+	}
+
+	fn mapped<C: Connection>(&self, xconnection: &C, window: Window) -> Option<bool> {
+		//_XEMBED_INFO is the version, then flags. Icons without it are always shown. None if the window is gone.
+		let reply = xconnection.get_property(false, window, self.atoms._XEMBED_INFO, self.atoms._XEMBED_INFO, 0, 2).ok()?.reply().ok()?;
+		let info: Vec<u32> = reply.value32().map(|values| values.collect()).unwrap_or_default();
+		Some(info.get(1).is_none_or(|flags| flags & XEMBED_MAPPED != 0))
+	}
+}
+
+pub fn place<C: Connection>(xconnection: &C, panelstate: &Panel) -> Result<(), Box<dyn Error>> {
+	//Move the docked icons to their places in the notification area, after it has been reflowed.
+	for (n, icon) in panelstate.trayicons.iter().enumerate() {
+		if let Some(window) = icon.window {
+			let (x, y) = panelstate.trayicon(n);
+			xconnection.configure_window(window, &ConfigureWindowAux::new().x(x as i32).y(y as i32).width(ICON_SIZE as u32).height(ICON_SIZE as u32))?;
+		}
+	}
+	Ok(())
+}
@@ -3,7 +3,7 @@ use std::{
     fs::File,
     io::{self, Read, BufReader},
     thread,
-    time::{SystemTime, Duration},
+    time::Duration,
 };
 use lazy_static::lazy_static;
 use png::Decoder;
@@ -24,6 +24,8 @@ pub const HIGHLIGHT_COLOUR: usize = 2;
 pub const LOWLIGHT_COLOUR: usize = 3;
 pub const WALLPAPER_COLOUR: usize = 4;
 pub const TITLEBAR_COLOUR: usize = 5;
+pub const ATTENTION_COLOUR: usize = 6;
+pub const WARNING_COLOUR: usize = 7;
 
 lazy_static! {
 	pub static ref COLOURS: Vec<Option<u32>> = loadcolours("colours.txt", [
@@ -33,6 +35,8 @@ lazy_static! {
         0x000000, //LOWLIGHT_COLOUR
         0x008080, //WALLPAPER_COLOUR
 		0x0000A8, //TITLEBAR_COLOUR
+		0xFF8000, //ATTENTION_COLOUR
+		0xFF0000, //WARNING_COLOUR
     ]);
 	
     //let mut depressedborder = Element {
@@ -48,12 +52,13 @@ pub fn squishtext(text: &str, width: i16, length: i16) -> String {
     } else {
         let characters = ((width - 2) / length) as usize;
         
-        if text.len() <= characters {
+        //By characters rather than bytes, so a title in UTF-8 can't be cut in the middle of one.
+        if text.chars().count() <= characters {
             text.to_string()
         } else if characters > 3 {
-            format!("{}...", &text[0..(characters - 3)])
+            format!("{}...", text.chars().take(characters - 3).collect::<String>())
         } else if characters > 0 {
-            text[0..characters].to_string()
+            text.chars().take(characters).collect()
         } else {
             String::new()
         }
@@ -61,6 +66,11 @@ pub fn squishtext(text: &str, width: i16, length: i16) -> String {
 }
 
 
+pub fn latin1(text: &str) -> Vec<u8> {
+	//The core font only has Latin-1. Anything else shows as a question mark.
+	text.chars().map(|c| if (c as u32) < 256 { c as u8 } else { b'?' }).collect()
+}
+
 pub fn loadcolours<const N: usize>(file_path: &str, default: [u32; N]) -> Vec<Option<u32>> {
     match std::fs::read_to_string(file_path) {
         Ok(contents) => {
@@ -291,6 +301,26 @@ pub fn drawbumpyframe<C: Connection>(xconnection: &C, window: u32, startx: i16,
 	Ok(())
 }
 
+pub fn drawpanelarrow<C: Connection>(xconnection: &C, window: u32, startx: i16, starty: i16, framewidth: i16, frameheight: i16, forward: bool, vertical: bool, enabled: bool, gc_highlight: u32, gc_lowlight: u32, gc_highbackground: u32, gc_lowbackground: u32, poly_lowlight: &mut Vec<Segment>) -> Result<(), Box<dyn Error>> {
+	//Taskbar arrow for paging through the buttons, pointing the way it goes. Greyed out with nowhere further to go.
+	drawbumpyframe(&xconnection, window, startx, starty, framewidth, frameheight, gc_highlight, gc_highbackground, gc_lowbackground, poly_lowlight)?;
+	let (middlex, middley) = (startx + framewidth / 2, starty + frameheight / 2);
+	let way = if forward { 1 } else { -1 };
+	let triangle = |offset: i16| if vertical {
+		[Point { x: middlex - 4 + offset, y: middley - 2 * way + offset }, Point { x: middlex + 4 + offset, y: middley - 2 * way + offset }, Point { x: middlex + offset, y: middley + 2 * way + offset }]
+	} else {
+		[Point { x: middlex - 2 * way + offset, y: middley - 4 + offset }, Point { x: middlex - 2 * way + offset, y: middley + 4 + offset }, Point { x: middlex + 2 * way + offset, y: middley + offset }]
+	};
+	if enabled {
+		xconnection.fill_poly(window, gc_lowlight, PolyShape::CONVEX, CoordMode::ORIGIN, &triangle(0))?;
+	} else {
+		//Etched, like a disabled button.
+		xconnection.fill_poly(window, gc_highlight, PolyShape::CONVEX, CoordMode::ORIGIN, &triangle(1))?;
+		xconnection.fill_poly(window, gc_lowbackground, PolyShape::CONVEX, CoordMode::ORIGIN, &triangle(0))?;
+	}
+	Ok(())
+}
+
 pub fn drawsystemframe<C: Connection>(xconnection: &C, window: u32, startx: i16, starty: i16, framewidth: i16, frameheight: i16, gc_highlight: u32, gc_lowlight: u32, gc_highbackground: u32, gc_lowbackground: u32) {
 	drawdepressedframe(&xconnection, window, startx, starty, framewidth, frameheight, gc_lowlight, gc_highbackground);
 	drawdepressedframe(&xconnection, window, startx - 1, starty + 1, framewidth - 2, frameheight - 2, gc_lowbackground, gc_highlight);
@@ -313,75 +343,11 @@ pub fn drawdepressedframe<C: Connection>(xconnection: &C, window: u32, startx: i
     Ok(())
 }
 
-pub fn drawclock<C: Connection>(xconnection: &C, window: u32, gc_lowlight: u32, width: i16, clockheight: i16) -> Result<(u64, u8, u8), Box<dyn Error>> {
-    //Clock separator.
-    xconnection.poly_point(CoordMode::PREVIOUS, window, gc_lowlight, &[ 
-        Point { x: width - 42, y: clockheight - 2 }, 
-        Point { x: 0, y: -4 } 
-    ])?;
-    
-    let epoch = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
-    let mut pminute = ((epoch / 60) % 60) as u8;
-    let mut phour = ((epoch / 3600) % 24) as u8;
-    let mut chour = phour;
-    
-    if phour > 12 {
-        chour -= 12;
-    }
-    if phour == 0 {
-        chour = 12;
-    }
-    
-    //Hour side padding... this'll need fixing.
-    let thour = if chour < 10 { //TODO: We probably want to adjust the width, rather than enter a space.
-        format!(" {}", chour)
-    } else {
-        chour.to_string()
-    };
-    
-    //AM or PM!
-    if phour < 12 { 
-        xconnection.image_text8(window, gc_lowlight, width - 25, clockheight, "AM".as_bytes())?;
-    } else {
-        xconnection.image_text8(window, gc_lowlight, width - 25, clockheight, "PM".as_bytes())?;
-    };
-    
-    //Hours!
-    xconnection.image_text8(window, gc_lowlight, width - 55, clockheight, thour.as_bytes())?;
-    
-    //println!("{}:{}", phour, pminute);
-
-	(phour, pminute) = updateclock(&xconnection, window, gc_lowlight, phour, 255, pminute, width, clockheight)?;
-    
-    Ok((epoch, pminute, phour))
-}
-
-pub fn updateclock<C: Connection>(xconnection: &C, window: u32, gc: u32, mut phour: u8, pminute: u8, cminute: u8, width: i16, height: i16) -> Result<(u8, u8), Box<dyn Error>> {
-	//println!("UpdateClock Called!");
-	if cminute != pminute {
-		//println!("cminute != pminute");
-		//Draw minute.
-		let mut tminute = cminute.to_string();
-		if cminute < 10 {
-			tminute = format!("0{}", tminute);
-		}
-		xconnection.image_text8(window, gc, width - 40, height, tminute.as_bytes());
-		if cminute == 0 {
-			//Draw hour.
-			phour = phour + 1;
-			if phour > 24 {
-				phour = 0;
-				//Draw AM.
-				xconnection.image_text8(window, gc, width - 25, height, "AM".as_bytes());
-			} else {
-				//Draw PM.
-				xconnection.image_text8(window, gc, width - 25, height, "PM".as_bytes());
-			}
-			xconnection.image_text8(window, gc, width - 55, height, phour.to_string().as_bytes());
-		}
-		//println!("tminute {}", tminute);
-	}
-    Ok((phour, cminute))
+pub fn drawclock<C: Connection>(xconnection: &C, window: u32, gc_lowlight: u32, right: i16, baseline: i16, text: &str, characters: usize) -> Result<(), Box<dyn Error>> {
+	//Right aligned, padded out to the longest the text gets so a shorter one covers up what was there.
+	let padded = format!("{:>1$}", text, characters);
+	xconnection.image_text8(window, gc_lowlight, right - 10 - characters as i16 * 6, baseline, padded.as_bytes())?;
+	Ok(())
 }
 
 
@@ -800,4 +766,27 @@ pub fn drawpng<C: Connection>(xconnection: &C, window: u32, filename: &str, x: i
 
 pub fn drawpngcover<C: Connection>(xconnection: &C, window: u32, filename: &str, x: i16, y: i16, width: u16, height: u16, colour: Option<u32>) -> Result<(), Box<dyn Error>> {
     drawpnginternal(xconnection, window, filename, x, y, width, height, colour, 1)
+}
+
+pub fn drawargb<C: Connection>(xconnection: &C, window: u32, pixels: &[u32], x: i16, y: i16, size: u16, colour: Option<u32>) -> Result<(), Box<dyn Error>> {
+    //Window icons come from the client as straight ARGB, already scaled to size x size. Blended over colour the same way drawpng does it.
+    let bgcolour = colour.unwrap_or(0x00000000);
+    let mut imagedata = Vec::with_capacity(pixels.len() * 4);
+    for &pixel in pixels {
+        let alpha = pixel >> 24;
+        let blend = |shift: u32| -> u8 {
+            ((((pixel >> shift) & 0xFF) * alpha + ((bgcolour >> shift) & 0xFF) * (255 - alpha)) / 255) as u8
+        };
+        imagedata.extend_from_slice(&[blend(0), blend(8), blend(16), 255]);
+    }
+
+    let pixmap = xconnection.generate_id()?;
+    xconnection.create_pixmap(24, pixmap, window, size, size)?;
+    let gcimage = xconnection.generate_id()?;
+    xconnection.create_gc(gcimage, pixmap, &CreateGCAux::default().background(colour))?;
+    xconnection.put_image(ImageFormat::Z_PIXMAP, pixmap, gcimage, size, size, 0, 0, 0, 24, &imagedata)?;
+    xconnection.copy_area(pixmap, window, gcimage, 0, 0, x, y, size, size)?;
+    xconnection.free_pixmap(pixmap)?;
+    xconnection.free_gc(gcimage)?;
+    Ok(())
 }
\ No newline at end of file
@@ -26,48 +26,259 @@ use crate::trundle::drawtitlebar;
 use crate::trundle::grabwindowtitle;
 use crate::trundle::drawtitletext;
 use crate::trundle::squishtext;
+use crate::trundle::drawargb;
 use crate::drawdepressedbumpyframe;
 use crate::drawpng;
 use crate::drawbumpyframe;
 use crate::WindowManager;
+use crate::panel::ElementState;
+use crate::system::settings::Placement;
 
 
 use crate::trundle::{
     COLOURS,
     HIGHBACKGROUND_COLOUR,
     LOWBACKGROUND_COLOUR,
-    HIGHLIGHT_COLOUR,
     LOWLIGHT_COLOUR,
     WALLPAPER_COLOUR,
-    TITLEBAR_COLOUR
+    TITLEBAR_COLOUR,
+    ATTENTION_COLOUR
 };
 
+x11rb::atom_manager! {
+	//Atoms we look up once at startup. Default is all zeroes, which matches nothing until desktop() fills them in.
+	#[derive(Default)]
+	pub Atoms: AtomsCookie {
+		_NET_WM_STATE,
+		_NET_WM_STATE_DEMANDS_ATTENTION,
+		_NET_WM_ICON,
+		_MOTIF_WM_HINTS,
+	}
+}
+
+//WM_HINTS flags (ICCCM section 4.1.2.4).
+const ICON_PIXMAP_HINT: u32 = 1 << 2;
+const ICON_MASK_HINT: u32 = 1 << 5;
+const URGENCY_HINT: u32 = 1 << 8; //This window wants the user's attention.
+
+//WM_NORMAL_HINTS flags (ICCCM section 4.1.2.3). Either means the window has been put somewhere on purpose.
+const US_POSITION: u32 = 1 << 0;
+const P_POSITION: u32 = 1 << 1;
+
+//_MOTIF_WM_HINTS is flags, functions, decorations, input mode and status. This flag says decorations is filled in.
+const MWM_HINTS_DECORATIONS: u32 = 1 << 1;
+
+//Window icons are cached at this size, for the taskbar and title bars.
+pub const ICON_SIZE: usize = 16;
+
+pub fn windowurgent<C: Connection>(xconnection: &C, atoms: &Atoms, window: Window) -> bool {
+	//Either the old ICCCM urgency hint or the EWMH demands attention state will do.
+	let hints = xconnection.get_property(false, window, AtomEnum::WM_HINTS, AtomEnum::WM_HINTS, 0, 1).ok().and_then(|cookie| cookie.reply().ok());
+	if hints.and_then(|reply| reply.value32().and_then(|mut flags| flags.next())).is_some_and(|flags| flags & URGENCY_HINT != 0) {
+		return true;
+	}
+	netwmstate(xconnection, atoms, window).contains(&atoms._NET_WM_STATE_DEMANDS_ATTENTION)
+}
+
+pub fn netwmstate<C: Connection>(xconnection: &C, atoms: &Atoms, window: Window) -> Vec<Atom> {
+	xconnection.get_property(false, window, atoms._NET_WM_STATE, AtomEnum::ATOM, 0, 1024).ok()
+		.and_then(|cookie| cookie.reply().ok())
+		.and_then(|reply| reply.value32().map(|states| states.collect()))
+		.unwrap_or_default()
+}
+
+pub fn setnetwmstate<C: Connection>(xconnection: &C, atoms: &Atoms, window: Window, state: Atom, on: bool) {
+	//_NET_WM_STATE belongs to the window manager, clients ask for changes with a client message.
+	let mut states = netwmstate(xconnection, atoms, window);
+	if states.contains(&state) == on {
+		return;
+	}
+	if on {
+		states.push(state);
+	} else {
+		states.retain(|&other| other != state);
+	}
+	let _ = xconnection.change_property32(PropMode::REPLACE, window, atoms._NET_WM_STATE, AtomEnum::ATOM, &states);
+}
+
+pub fn userplaced<C: Connection>(xconnection: &C, window: Window) -> bool {
+	//Did the user or the program choose where this window goes?
+	xconnection.get_property(false, window, AtomEnum::WM_NORMAL_HINTS, AtomEnum::WM_SIZE_HINTS, 0, 1).ok()
+		.and_then(|cookie| cookie.reply().ok())
+		.and_then(|reply| reply.value32().and_then(|mut flags| flags.next()))
+		.is_some_and(|flags| flags & (US_POSITION | P_POSITION) != 0)
+}
+
+pub fn windowclass<C: Connection>(xconnection: &C, window: Window) -> String {
+	//The class half of WM_CLASS ("instance\0class\0"), which is the same for every window of a program.
+	xconnection.get_property(false, window, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 256).ok()
+		.and_then(|cookie| cookie.reply().ok())
+		.and_then(|reply| reply.value.split(|&byte| byte == 0).nth(1).map(|class| String::from_utf8_lossy(class).into_owned()))
+		.unwrap_or_default()
+}
+
+pub fn wantsdecorations<C: Connection>(xconnection: &C, atoms: &Atoms, window: Window) -> bool {
+	//Programs that draw their own title bar ask for no decorations at all. Anything else gets a frame.
+	let hints: Vec<u32> = xconnection.get_property(false, window, atoms._MOTIF_WM_HINTS, AtomEnum::ANY, 0, 5).ok()
+		.and_then(|cookie| cookie.reply().ok())
+		.and_then(|reply| reply.value32().map(|hints| hints.collect()))
+		.unwrap_or_default();
+	!(hints.len() >= 3 && hints[0] & MWM_HINTS_DECORATIONS != 0 && hints[2] == 0)
+}
+
+pub fn windowicon<C: Connection>(xconnection: &C, atoms: &Atoms, window: Window) -> Option<Vec<u32>> {
+	//ARGB icon scaled to ICON_SIZE, or None to use the default. _NET_WM_ICON first, as it has colour and alpha.
+	neticon(xconnection, atoms, window).or_else(|| pixmapicon(xconnection, window))
+}
+
+fn neticon<C: Connection>(xconnection: &C, atoms: &Atoms, window: Window) -> Option<Vec<u32>> {
+	//_NET_WM_ICON is any number of width, height, then width * height ARGB pixels, one after the other.
+	let reply = xconnection.get_property(false, window, atoms._NET_WM_ICON, AtomEnum::CARDINAL, 0, u32::MAX).ok()?.reply().ok()?;
+	let data: Vec<u32> = reply.value32()?.collect();
+	pickicon(&data)
+}
+
+fn pickicon(data: &[u32]) -> Option<Vec<u32>> {
+	//The best of the icons in a _NET_WM_ICON, scaled to ICON_SIZE.
+	let mut best: Option<(usize, usize, &[u32])> = None;
+	let mut i = 0;
+	while i + 2 <= data.len() {
+		let (width, height) = (data[i] as usize, data[i + 1] as usize);
+		let end = match width.checked_mul(height).map(|pixels| i + 2 + pixels) {
+			Some(end) if width > 0 && height > 0 && end <= data.len() => end,
+			_ => break, //Broken icon, keep what we have.
+		};
+		//The smallest icon at least ICON_SIZE big scales down best. Failing that, the biggest one.
+		let size = width.min(height);
+		let better = match best {
+			None => true,
+			Some((bestwidth, bestheight, _)) => {
+				let bestsize = bestwidth.min(bestheight);
+				if size >= ICON_SIZE { bestsize < ICON_SIZE || size < bestsize } else { bestsize < ICON_SIZE && size > bestsize }
+			}
+		};
+		if better {
+			best = Some((width, height, &data[i + 2..end]));
+		}
+		i = end;
+	}
+	best.map(|(width, height, pixels)| scaleicon(pixels, width, height))
+}
+
+fn pixmapicon<C: Connection>(xconnection: &C, window: Window) -> Option<Vec<u32>> {
+	//Older clients hand over an icon pixmap, and maybe a mask, in WM_HINTS.
+	let reply = xconnection.get_property(false, window, AtomEnum::WM_HINTS, AtomEnum::WM_HINTS, 0, 9).ok()?.reply().ok()?;
+	let hints: Vec<u32> = reply.value32()?.collect();
+	let flags = *hints.first()?;
+	if flags & ICON_PIXMAP_HINT == 0 || hints.len() < 4 {
+		return None;
+	}
+	let (width, height, depth, pixels) = readpixmap(xconnection, hints[3])?;
+	let mask = if flags & ICON_MASK_HINT != 0 && hints.len() > 7 {
+		readpixmap(xconnection, hints[7]).filter(|&(maskwidth, maskheight, _, _)| maskwidth == width && maskheight == height)
+	} else {
+		None
+	};
+
+	let argb: Vec<u32> = pixels.iter().enumerate().map(|(i, &pixel)| {
+		//Bitmaps draw their set bits in black on white.
+		let colour = if depth == 1 { if pixel != 0 { 0x000000 } else { 0xFFFFFF } } else { pixel & 0xFFFFFF };
+		let opaque = mask.as_ref().is_none_or(|(_, _, _, maskpixels)| maskpixels[i] != 0);
+		if opaque { colour | 0xFF000000 } else { colour }
+	}).collect();
+	Some(scaleicon(&argb, width, height))
+}
+
+fn readpixmap<C: Connection>(xconnection: &C, pixmap: Pixmap) -> Option<(usize, usize, u8, Vec<u32>)> {
+	//One u32 per pixel. Bitmaps come back as 0 or 1, anything deeper as the raw pixel value.
+	let geom = xconnection.get_geometry(pixmap).ok()?.reply().ok()?;
+	let (width, height) = (geom.width as usize, geom.height as usize);
+	if width == 0 || height == 0 {
+		return None;
+	}
+	let setup = xconnection.setup();
+	if geom.depth == 1 {
+		let image = xconnection.get_image(ImageFormat::XY_PIXMAP, pixmap, 0, 0, geom.width, geom.height, 1).ok()?.reply().ok()?;
+		let pad = setup.bitmap_format_scanline_pad as usize;
+		let stride = width.div_ceil(pad) * pad / 8;
+		let lsbfirst = setup.bitmap_format_bit_order == ImageOrder::LSB_FIRST;
+		let mut pixels = Vec::with_capacity(width * height);
+		for y in 0..height {
+			for x in 0..width {
+				let byte = *image.data.get(y * stride + x / 8)?;
+				let bit = if lsbfirst { x % 8 } else { 7 - x % 8 };
+				pixels.push(((byte >> bit) & 1) as u32);
+			}
+		}
+		Some((width, height, 1, pixels))
+	} else {
+		//Only the usual 32 bits per pixel layout, which is what depth 24 and 32 use everywhere we run.
+		let format = setup.pixmap_formats.iter().find(|format| format.depth == geom.depth)?;
+		if format.bits_per_pixel != 32 {
+			return None;
+		}
+		let image = xconnection.get_image(ImageFormat::Z_PIXMAP, pixmap, 0, 0, geom.width, geom.height, !0).ok()?.reply().ok()?;
+		if image.data.len() < width * height * 4 {
+			return None;
+		}
+		let msbfirst = setup.image_byte_order == ImageOrder::MSB_FIRST;
+		let pixels = image.data.chunks_exact(4).take(width * height).map(|bytes| {
+			let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+			if msbfirst { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) }
+		}).collect();
+		Some((width, height, geom.depth, pixels))
+	}
+}
+
+fn scaleicon(pixels: &[u32], width: usize, height: usize) -> Vec<u32> {
+	//Nearest neighbour, like drawpng. Cheap, and icons are already drawn for small sizes.
+	let mut scaled = Vec::with_capacity(ICON_SIZE * ICON_SIZE);
+	for y in 0..ICON_SIZE {
+		for x in 0..ICON_SIZE {
+			scaled.push(pixels[(y * height / ICON_SIZE) * width + (x * width / ICON_SIZE)]);
+		}
+	}
+	scaled
+}
+
+pub fn drawwindowicon<C: Connection>(xconnection: &C, window: Window, icon: Option<&[u32]>, x: i16, y: i16, colour: Option<u32>) {
+	//The window's own icon, or the stock one if it didn't give us one.
+	match icon {
+		Some(pixels) => drawargb(xconnection, window, pixels, x, y, ICON_SIZE as u16, colour),
+		None => drawpng(xconnection, window, "computer.png", x, y, ICON_SIZE as u16, ICON_SIZE as u16, colour),
+	};
+}
+
 
 
-pub fn redrawframes<C: Connection>(xconnection: &C, wm: &WindowManager, panel: Window, gc_highlight: u32, gc_lowlight: u32, gc_highbackground: u32, gc_lowbackground: u32, gc_titlebar: u32, gc_titlebartext: u32, poly_lowlight: &mut Vec<Segment>) {
+
+pub fn redrawframes<C: Connection>(xconnection: &C, wm: &WindowManager, panel: Window, gc_highlight: u32, gc_lowlight: u32, gc_highbackground: u32, gc_lowbackground: u32, gc_titlebar: u32, gc_titlebartext: u32, gc_attention: u32, gc_attentiontext: u32, poly_lowlight: &mut Vec<Segment>) {
     for state in wm.windows.values() {
-        if state.frame != panel {
+        if state.frame != panel && state.decorated {
 			//println!("[{}] Frame Details - Window: {:?}, Frame: {:?}, Title: '{}', Original Size: {}x{}, BORDER: {}, TITLEBAR: {}, Final Size: {}x{}", "Skelefits", state.window, state.frame, state.title, state.width, state.height, border, titlebar, state.width + (2 * border as i16), state.height + ((2 * border as i16) + titlebar as i16));
-            updateborder(xconnection, state.frame, state.window, state.width + (2 * BORDER as i16), state.height + ((2 * BORDER as i16) + TITLEBAR as i16), gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, poly_lowlight);
+            updateborder(xconnection, state.frame, state.window, state.width + (2 * BORDER as i16), state.height + ((2 * BORDER as i16) + TITLEBAR as i16), state.map == 2, state.urgent, state.icon.as_deref(), gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, gc_attention, gc_attentiontext, poly_lowlight);
         }
     }
 }
 
-pub fn createwmborder<C: Connection>(xconnection: &C, screen: &Screen, wm: &WindowManager, target: Window, width: u16, height: u16, screen_width: i16, screen_height: i16, gc_highlight: u32, gc_lowlight: u32, gc_highbackground: u32, gc_lowbackground: u32, gc_titlebar: u32, gc_titlebartext: u32, poly_lowlight: &mut Vec<Segment>) -> Result<Window, Box<dyn Error>> {
+pub fn createwmborder<C: Connection>(xconnection: &C, screen: &Screen, wm: &WindowManager, target: Window, width: u16, height: u16, screen_width: i16, screen_height: i16, gc_highlight: u32, gc_lowlight: u32, gc_highbackground: u32, gc_lowbackground: u32, gc_titlebar: u32, gc_titlebartext: u32, gc_attention: u32, gc_attentiontext: u32, poly_lowlight: &mut Vec<Segment>) -> Result<Window, Box<dyn Error>> {
     if let Some(state) = wm.getwindow(&target) {
         let fwidth = (width + BORDER + BORDER) as i16;
         let fheight = (height + TITLEBAR + BORDER + BORDER) as i16;
 
+        //Put the frame around where the client already is, so windows we hand back on exit come back to the same spot.
         let fx = (state.x - BORDER as i16).max(0).min(screen_width - fwidth);
-        let fy = (state.y - (TITLEBAR as i16 - BORDER as i16)).max(0).min(screen_height - fheight);
+        let fy = (state.y - (TITLEBAR + BORDER) as i16).max(0).min(screen_height - fheight);
 
         let frame = xconnection.generate_id()?;
-        xconnection.create_window(COPY_DEPTH_FROM_PARENT, frame, screen.root, fx, fy, fwidth as u16, fheight as u16, 0, WindowClass::INPUT_OUTPUT, 0, &CreateWindowAux::new().background_pixel(COLOURS[HIGHBACKGROUND_COLOUR]).event_mask(EventMask::EXPOSURE | EventMask::BUTTON_PRESS | EventMask::POINTER_MOTION | EventMask::BUTTON_RELEASE));
+        xconnection.create_window(COPY_DEPTH_FROM_PARENT, frame, screen.root, fx, fy, fwidth as u16, fheight as u16, 0, WindowClass::INPUT_OUTPUT, 0, &CreateWindowAux::new().background_pixel(COLOURS[HIGHBACKGROUND_COLOUR]).event_mask(EventMask::EXPOSURE | EventMask::BUTTON_PRESS | EventMask::POINTER_MOTION | EventMask::BUTTON_RELEASE | EventMask::ENTER_WINDOW));
         xconnection.configure_window(target, &ConfigureWindowAux::new().border_width(0));
+        //If we die, the X server puts the client back on the root instead of destroying it with the frame.
+        xconnection.change_save_set(SetMode::INSERT, target);
         xconnection.reparent_window(target, frame, BORDER as i16, (BORDER + TITLEBAR) as i16);
         xconnection.map_window(frame);
         xconnection.map_window(target);
-        updateborder(xconnection, frame, target, fwidth, fheight, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, poly_lowlight);
+        updateborder(xconnection, frame, target, fwidth, fheight, state.map == 2, state.urgent, state.icon.as_deref(), gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground, gc_titlebar, gc_titlebartext, gc_attention, gc_attentiontext, poly_lowlight);
         xconnection.flush();
         Ok(frame)
     } else {
@@ -75,6 +286,32 @@ pub fn createwmborder<C: Connection>(xconnection: &C, screen: &Screen, wm: &Wind
     }
 }
 
+pub fn ownwindow<C: Connection>(xconnection: &C, window: Window) -> bool {
+	//Windows Tullamore created itself (panel, Clicker, Booker...) have ids from our own range.
+	let setup = xconnection.setup();
+	window & !setup.resource_id_mask == setup.resource_id_base
+}
+
+pub fn releasewindows<C: Connection>(xconnection: &C, wm: &WindowManager, root: Window) {
+	//Hand every client back to the root where it currently sits, so it outlives us and a restarted Tullamore can adopt it again.
+	for state in wm.windows.values() {
+		if state.frame == 0 || state.frame == state.window || ownwindow(xconnection, state.window) {
+			//Our own windows go away with the connection anyway.
+			continue;
+		}
+		let (x, y) = match xconnection.get_geometry(state.frame).ok().and_then(|cookie| cookie.reply().ok()) {
+			Some(geom) => (geom.x + BORDER as i16, geom.y + (BORDER + TITLEBAR) as i16),
+			None => (state.x, state.y),
+		};
+		let _ = xconnection.change_save_set(SetMode::INSERT, state.window);
+		let _ = xconnection.reparent_window(state.window, root, x, y);
+		//Minimised windows too, otherwise nobody would ever see them again.
+		let _ = xconnection.map_window(state.window);
+		let _ = xconnection.destroy_window(state.frame);
+	}
+	let _ = xconnection.flush();
+}
+
 pub fn createborder(xconnection: &impl x11rb::connection::Connection, screen: &x11rb::protocol::xproto::Screen, target: u32, width: i16, height: i16, gc_highlight: u32, gc_lowlight: u32, gc_highbackground: u32, gc_lowbackground: u32, gc_titlebar: u32, gc_titlebartext: u32) -> Result<u32, Box<dyn std::error::Error>> {
     if let Ok(geom) = xconnection.get_geometry(target)?.reply() {
 
@@ -88,7 +325,7 @@ pub fn createborder(xconnection: &impl x11rb::connection::Connection, screen: &x
 
 		//Create frame and put the target into into it.
 		let frame = xconnection.generate_id()?;
-		xconnection.create_window( COPY_DEPTH_FROM_PARENT, frame, screen.root, fx, fy, fwidth, fheight, 0, WindowClass::INPUT_OUTPUT, 0, &CreateWindowAux::new().background_pixel(COLOURS[HIGHBACKGROUND_COLOUR]).event_mask(EventMask::EXPOSURE | EventMask::BUTTON_PRESS | EventMask::POINTER_MOTION | EventMask::BUTTON_RELEASE),)?;
+		xconnection.create_window( COPY_DEPTH_FROM_PARENT, frame, screen.root, fx, fy, fwidth, fheight, 0, WindowClass::INPUT_OUTPUT, 0, &CreateWindowAux::new().background_pixel(COLOURS[HIGHBACKGROUND_COLOUR]).event_mask(EventMask::EXPOSURE | EventMask::BUTTON_PRESS | EventMask::POINTER_MOTION | EventMask::BUTTON_RELEASE | EventMask::ENTER_WINDOW),)?;
 		//Set the target's frame to 0, in case it has one for some reason.
 		xconnection.configure_window(target, &ConfigureWindowAux::new().border_width(0))?;
 
@@ -104,15 +341,17 @@ pub fn createborder(xconnection: &impl x11rb::connection::Connection, screen: &x
     }
 }
 
-pub fn updateborder<C: x11rb::connection::Connection>(xconnection: &C, frame: u32, target: u32, width: i16, height: i16, gc_highlight: u32, gc_lowlight: u32, gc_highbackground: u32, gc_lowbackground: u32, gc_titlebar: u32, gc_titlebartext: u32, poly_lowlight: &mut Vec<Segment>) {
+pub fn updateborder<C: x11rb::connection::Connection>(xconnection: &C, frame: u32, target: u32, width: i16, height: i16, focused: bool, urgent: bool, icon: Option<&[u32]>, gc_highlight: u32, gc_lowlight: u32, gc_highbackground: u32, gc_lowbackground: u32, gc_titlebar: u32, gc_titlebartext: u32, gc_attention: u32, gc_attentiontext: u32, poly_lowlight: &mut Vec<Segment>) {
     const TITLE_INSET: i16 = 8;
+    const ICON_INSET: i16 = 6;
     const TEXT_Y_OFFSET: i16 = 1;
-    let root = xconnection.setup().roots[0].root;
-    let focused = if let Ok(root_tree) = xconnection.query_tree(root).expect("WHY AM I HERE?").reply() { root_tree.children.iter().rev().nth(1).map_or(false, |&w| w == frame) } else { false };
+	//Focus comes from the WindowManager rather than the stacking order, as sloppy focus can focus a window without raising it.
 
 	//println!("{}x{}", width, height);
     windowborder(xconnection, frame, width, height, gc_highlight, gc_lowlight, gc_highbackground, gc_lowbackground);
-    let gc = if focused { gc_titlebar } else { gc_lowbackground };
+	//Windows asking for attention get their title bar in the attention colour until they are focused.
+	let attention = urgent && !focused;
+    let gc = if focused { gc_titlebar } else if attention { gc_attention } else { gc_lowbackground };
 	
 	
 	
@@ -125,8 +364,15 @@ pub fn updateborder<C: x11rb::connection::Connection>(xconnection: &C, frame: u3
 	
 	
 	
-    let gc = if focused { gc_titlebartext } else { gc_highbackground };
-	drawtitletext(xconnection, frame, gc, target, TITLE_INSET, TITLEBAR as i16 - TEXT_Y_OFFSET);
+	//The application's icon sits at the left of the title bar, pushing the title along.
+	let mut textx = TITLE_INSET;
+	if let Some(pixels) = icon {
+		let colour = if focused { COLOURS[TITLEBAR_COLOUR] } else if attention { COLOURS[ATTENTION_COLOUR] } else { COLOURS[LOWBACKGROUND_COLOUR] };
+		drawargb(xconnection, frame, pixels, ICON_INSET, BORDER as i16 + 1, ICON_SIZE as u16, colour);
+		textx = ICON_INSET + ICON_SIZE as i16 + 3;
+	}
+    let gc = if focused { gc_titlebartext } else if attention { gc_attentiontext } else { gc_highbackground };
+	drawtitletext(xconnection, frame, gc, target, textx, TITLEBAR as i16 - TEXT_Y_OFFSET);
 }
 
 pub fn updategui<C: x11rb::connection::Connection>(xconnection: &C, frame: u32, gc_lowlight: u32, poly_lowlight: &mut Vec<Segment>) {
@@ -138,8 +384,6 @@ pub fn drawpanelwindows<C: Connection>(xconnection: &C, window: u32, startx: i16
     const WINDOW_TARGET_WIDTH: i16 = 160;
     const WINDOW_MIN_WIDTH: i16 = 30;
     const WINDOW_SPACING: i16 = 3;
-    const ICON_WIDTH: u16 = 16;
-    const ICON_HEIGHT: u16 = 16;
     let mut windows: Vec<(Window, String, Window)> = wm.windows.values().filter(|state| state.map == 2 || state.map == 3).map(|state| (state.frame, state.title.clone(), state.window)).collect();
     if windows.is_empty() {  } else {
 		windows.sort_by_key(|(frame, _, _)| wm.windows.values().find(|state| state.frame == *frame).map(|state| state.order).unwrap_or(0));
@@ -157,11 +401,11 @@ pub fn drawpanelwindows<C: Connection>(xconnection: &C, window: u32, startx: i16
 			};
 			if Some(*frameid) == focused {
 				drawdepressedbumpyframe(xconnection, window, offset, 4, finalwidth, 21, gc_highlight, gc_highbackground, gc_lowbackground, gc_highcheckers, poly_lowlight);
-				drawpng(xconnection, window, "computer.png", offset + 4, 8, ICON_WIDTH, ICON_HEIGHT, COLOURS[HIGHBACKGROUND_COLOUR]);
+				drawwindowicon(xconnection, window, wm.getwindow(clientid).and_then(|state| state.icon.as_deref()), offset + 4, 8, COLOURS[HIGHBACKGROUND_COLOUR]);
 				if !display_title.is_empty() { xconnection.image_text8(window, gc_lowlight, offset + 22, 20, display_title.as_bytes()); }
 			} else {
 				drawbumpyframe(xconnection, window, offset, 4, finalwidth, 21, gc_highlight, gc_highbackground, gc_lowbackground, poly_lowlight);
-				drawpng(xconnection, window, "computer.png", offset + 4, 7, ICON_WIDTH, ICON_HEIGHT, COLOURS[HIGHBACKGROUND_COLOUR]);
+				drawwindowicon(xconnection, window, wm.getwindow(clientid).and_then(|state| state.icon.as_deref()), offset + 4, 7, COLOURS[HIGHBACKGROUND_COLOUR]);
 				if !display_title.is_empty() { xconnection.image_text8(window, gc_lowlight, offset + 22, 19, display_title.as_bytes()); }
 			}
 			offset += finalwidth + WINDOW_SPACING;
@@ -171,23 +415,198 @@ pub fn drawpanelwindows<C: Connection>(xconnection: &C, window: u32, startx: i16
 	}
 }
 
-pub fn drawwindowbuttons<C: Connection>(xconnection: &C, panel: Window, u8_panelitem: u8, str_title: &str, x: i16, width: i16, gc_highlight: u32, gc_lowlight: u32, gc_highbackground: u32, gc_lowbackground: u32, gc_highcheckers: u32, poly_lowlight: &mut Vec<Segment>) {
-	println!("u8_panelitem {}", u8_panelitem);	
-    if u8_panelitem == 43 {
-        drawdepressedbumpyframe(xconnection, panel, x, 4, width, 21, gc_highlight, gc_highbackground, gc_lowbackground, gc_highcheckers, poly_lowlight);
+pub fn drawwindowbuttons<C: Connection>(xconnection: &C, panel: Window, state: ElementState, str_title: &str, icon: Option<&[u32]>, x: i16, y: i16, width: i16, urgent: bool, gc_highlight: u32, gc_lowlight: u32, gc_highbackground: u32, gc_lowbackground: u32, gc_highcheckers: u32, gc_attention: u32, gc_attentiontext: u32, poly_lowlight: &mut Vec<Segment>) {
+    if state == ElementState::Engaged {
+        drawdepressedbumpyframe(xconnection, panel, x, y, width, 21, gc_highlight, gc_highbackground, gc_lowbackground, gc_highcheckers, poly_lowlight);
+		if width >= 20 {
+			drawwindowicon(xconnection, panel, icon, x + 4, y + 4, COLOURS[HIGHBACKGROUND_COLOUR]);
+			xconnection.image_text8(panel, gc_lowlight, x + 24, y + 16, squishtext(str_title, width - 28, 6).as_bytes());
+		}
+    } else if urgent && state != ElementState::Pressed {
+		//Lit up in the attention colour. The caller flips urgent on and off to make it flash.
+		drawbumpyframe(xconnection, panel, x, y, width, 21, gc_highlight, gc_attention, gc_lowbackground, poly_lowlight);
 		if width >= 20 {
-			drawpng(xconnection, panel, "computer.png", x + 4, 8, 16, 16, COLOURS[HIGHBACKGROUND_COLOUR]);
-			xconnection.image_text8(panel, gc_lowlight, x + 24, 20, squishtext(str_title, width - 28, 6).as_bytes());
+			drawwindowicon(xconnection, panel, icon, x + 4, y + 3, COLOURS[ATTENTION_COLOUR]);
+			xconnection.image_text8(panel, gc_attentiontext, x + 24, y + 15, squishtext(str_title, width - 28, 6).as_bytes());
 		}
     } else {
-		if u8_panelitem == 41 {
-			drawdepressedbumpyframe(xconnection, panel, x, 4, width, 21, gc_highlight, gc_highbackground, gc_lowbackground, 0, poly_lowlight);
+		if state == ElementState::Pressed {
+			drawdepressedbumpyframe(xconnection, panel, x, y, width, 21, gc_highlight, gc_highbackground, gc_lowbackground, 0, poly_lowlight);
 		} else {
-			drawbumpyframe(xconnection, panel, x, 4, width, 21, gc_highlight, gc_highbackground, gc_lowbackground, poly_lowlight);
+			drawbumpyframe(xconnection, panel, x, y, width, 21, gc_highlight, gc_highbackground, gc_lowbackground, poly_lowlight);
 		}
 		if width >= 20 {
-			drawpng(xconnection, panel, "computer.png", x + 4, 7, 16, 16, COLOURS[HIGHBACKGROUND_COLOUR]);
-			xconnection.image_text8(panel, gc_lowlight, x + 24, 19, squishtext(str_title, width - 28, 6).as_bytes());
+			drawwindowicon(xconnection, panel, icon, x + 4, y + 3, COLOURS[HIGHBACKGROUND_COLOUR]);
+			xconnection.image_text8(panel, gc_lowlight, x + 24, y + 15, squishtext(str_title, width - 28, 6).as_bytes());
 		}
     }
-}
\ No newline at end of file
+}
+pub fn snapframe(x: i16, y: i16, width: i16, height: i16, area: (i16, i16, i16, i16), targets: &[(i16, i16, i16, i16)], distance: i16) -> (i16, i16) {
+	//Pull a frame at x, y onto nearby edges of the work area (the screen less the panel) and the edges of other frames.
+	//Area and targets are (x, y, width, height). The closest edge within distance wins on each axis.
+	if distance <= 0 {
+		return (x, y);
+	}
+
+	let (areax, areay, areawidth, areaheight) = area;
+	let mut xedges: Vec<i16> = vec![areax, areax + areawidth - width]; //Left and right, or the side of the panel.
+	let mut yedges: Vec<i16> = vec![areay, areay + areaheight - height]; //Top and bottom, or the panel.
+
+	for &(tx, ty, tw, th) in targets {
+		//Only edges we could actually line up against, so frames in other parts of the screen don't tug.
+		if y < ty + th + distance && y + height > ty - distance {
+			xedges.extend([tx + tw, tx - width, tx, tx + tw - width]);
+		}
+		if x < tx + tw + distance && x + width > tx - distance {
+			yedges.extend([ty + th, ty - height, ty, ty + th - height]);
+		}
+	}
+
+	let closest = |position: i16, edges: &[i16]| -> i16 {
+		edges.iter().copied().filter(|edge| (edge - position).abs() <= distance).min_by_key(|edge| (edge - position).abs()).unwrap_or(position)
+	};
+
+	(closest(x, &xedges), closest(y, &yedges))
+}
+
+pub fn placeframe(placement: Placement, width: i16, height: i16, area: (i16, i16, i16, i16), frames: &[(i16, i16, i16, i16)], pointer: (i16, i16)) -> Option<(i16, i16)> {
+	//Where a new frame of width x height should go in the work area. Area and the frames already on screen are (x, y, width, height).
+	//None leaves the window where it asked to be.
+	let (areax, areay, areawidth, areaheight) = area;
+	let clamp = |x: i16, y: i16| -> (i16, i16) { (x.min(areax + areawidth - width).max(areax), y.min(areay + areaheight - height).max(areay)) };
+	match placement {
+		Placement::None => None,
+		Placement::Centre => Some(clamp(areax + (areawidth - width) / 2, areay + (areaheight - height) / 2)),
+		Placement::Pointer => Some(clamp(pointer.0 - width / 2, pointer.1 - height / 2)),
+		Placement::Cascade => {
+			//Step down and right from the top left until no other frame has its corner there. If that runs off the work area, back to the top left.
+			let step = (TITLEBAR + BORDER) as i16;
+			let taken = |x: i16, y: i16| frames.iter().any(|&(fx, fy, _, _)| (fx - x).abs() < step / 2 && (fy - y).abs() < step / 2);
+			let mut position = (areax, areay);
+			while taken(position.0, position.1) {
+				position = (position.0 + step, position.1 + step);
+				if position.0 + width > areax + areawidth || position.1 + height > areay + areaheight {
+					position = (areax, areay);
+					break;
+				}
+			}
+			Some(clamp(position.0, position.1))
+		}
+		Placement::Smart => {
+			//Try every spot that lines up with a screen edge or another frame, and keep the one with the least overlap.
+			//Ties go to the highest, then leftmost, spot.
+			let mut xs: Vec<i16> = vec![areax, areax + areawidth - width];
+			let mut ys: Vec<i16> = vec![areay, areay + areaheight - height];
+			for &(fx, fy, fw, fh) in frames {
+				xs.extend([fx + fw, fx - width]);
+				ys.extend([fy + fh, fy - height]);
+			}
+			xs.retain(|&x| x >= areax && x + width <= areax + areawidth);
+			ys.retain(|&y| y >= areay && y + height <= areay + areaheight);
+			if xs.is_empty() || ys.is_empty() {
+				//Bigger than the screen. Top left at least shows the title bar.
+				return Some((areax, areay));
+			}
+
+			let overlap = |x: i16, y: i16| -> i32 {
+				frames.iter().map(|&(fx, fy, fw, fh)| {
+					let across = ((x + width).min(fx + fw) - x.max(fx)).max(0) as i32;
+					let down = ((y + height).min(fy + fh) - y.max(fy)).max(0) as i32;
+					across * down
+				}).sum()
+			};
+
+			ys.iter().flat_map(|&y| xs.iter().map(move |&x| (x, y))).min_by_key(|&(x, y)| (overlap(x, y), y, x))
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn iconsscaletoiconsize() {
+		//Quarters in four colours come out as quarters.
+		let big: Vec<u32> = (0..32 * 32).map(|i| ((i % 32 >= 16) as u32) + 2 * ((i / 32 >= 16) as u32)).collect();
+		let scaled = scaleicon(&big, 32, 32);
+		assert_eq!(scaled.len(), ICON_SIZE * ICON_SIZE);
+		assert_eq!((scaled[0], scaled[ICON_SIZE - 1], scaled[ICON_SIZE * (ICON_SIZE - 1)], scaled[ICON_SIZE * ICON_SIZE - 1]), (0, 1, 2, 3));
+		assert_eq!(scaled[7], 0);
+		assert_eq!(scaled[8], 1);
+
+		//Small ones are blown up a pixel at a time, and wide ones squashed.
+		let small: Vec<u32> = (0..8 * 8).collect();
+		let scaled = scaleicon(&small, 8, 8);
+		assert_eq!(&scaled[..4], [0, 0, 1, 1]);
+		assert_eq!(scaled[ICON_SIZE * 2], 8);
+		let wide: Vec<u32> = (0..32 * 8).map(|i| i % 32).collect();
+		assert_eq!(&scaleicon(&wide, 32, 8)[..3], [0, 2, 4]);
+	}
+
+	#[test]
+	fn neticonpicksthesmallestbigenoughicon() {
+		//Each icon is filled with its own width, so the scaled one says which it came from.
+		let icons = |sizes: &[usize]| -> Vec<u32> {
+			sizes.iter().flat_map(|&size| [size as u32, size as u32].into_iter().chain(std::iter::repeat_n(size as u32, size * size))).collect()
+		};
+		assert_eq!(pickicon(&icons(&[8, 48, 24, 32])).unwrap()[0], 24);
+		assert_eq!(pickicon(&icons(&[16, 8])).unwrap()[0], 16);
+		assert_eq!(pickicon(&icons(&[8, 12])).unwrap()[0], 12);
+
+		//An icon that says it is bigger than what's left is left out, along with anything after it.
+		let mut broken = icons(&[12]);
+		broken.extend([64, 64, 1, 2, 3]);
+		assert_eq!(pickicon(&broken).unwrap()[0], 12);
+		assert_eq!(pickicon(&[0, 0]), None);
+		assert_eq!(pickicon(&[]), None);
+	}
+
+	#[test]
+	fn framessnaptoedgesandotherframes() {
+		let area = (0, 0, 1000, 800);
+		//The work area's edges, on both sides.
+		assert_eq!(snapframe(5, 695, 200, 100, area, &[], 10), (0, 700));
+		assert_eq!(snapframe(793, 3, 200, 100, area, &[], 10), (800, 0));
+		assert_eq!(snapframe(50, 50, 200, 100, area, &[], 10), (50, 50));
+
+		//Beside another frame, or lined up with it, but only when they are level with each other.
+		let other = [(300, 300, 200, 200)];
+		assert_eq!(snapframe(505, 350, 200, 100, area, &other, 10), (500, 350));
+		assert_eq!(snapframe(96, 296, 200, 100, area, &other, 10), (100, 300));
+		assert_eq!(snapframe(505, 650, 200, 100, area, &other, 10), (505, 650));
+
+		//The distance counts, and the nearest edge wins.
+		assert_eq!(snapframe(10, 50, 200, 100, area, &[], 10), (0, 50));
+		assert_eq!(snapframe(11, 50, 200, 100, area, &[], 10), (11, 50));
+		assert_eq!(snapframe(8, 50, 200, 100, area, &[(0, 0, 6, 800)], 10), (6, 50));
+		assert_eq!(snapframe(3, 3, 200, 100, area, &[], 0), (3, 3));
+	}
+
+	#[test]
+	fn cascadestepsdownandwraps() {
+		let area = (0, 0, 400, 300);
+		let step = (TITLEBAR + BORDER) as i16;
+		assert_eq!(placeframe(Placement::Cascade, 200, 150, area, &[], (0, 0)), Some((0, 0)));
+		assert_eq!(placeframe(Placement::Cascade, 200, 150, area, &[(0, 0, 200, 150), (step, step, 200, 150)], (0, 0)), Some((step * 2, step * 2)));
+		//A gap in the steps gets filled first, and a frame nearly on a step still takes it.
+		assert_eq!(placeframe(Placement::Cascade, 200, 150, area, &[(2, 1, 200, 150), (step * 2, step * 2, 200, 150)], (0, 0)), Some((step, step)));
+
+		//Once the next step would hang off the bottom, it starts again at the top left.
+		let frames: Vec<(i16, i16, i16, i16)> = (0..10).map(|n| (n * step, n * step, 200, 150)).collect();
+		assert_eq!(placeframe(Placement::Cascade, 200, 150, area, &frames, (0, 0)), Some((0, 0)));
+	}
+
+	#[test]
+	fn smartplacementfindstheleastoverlap() {
+		let area = (0, 20, 1000, 780);
+		assert_eq!(placeframe(Placement::Smart, 300, 300, area, &[], (0, 0)), Some((0, 20)));
+		//Beside the first window, then under it once the top row is full.
+		assert_eq!(placeframe(Placement::Smart, 300, 300, area, &[(0, 20, 400, 400)], (0, 0)), Some((400, 20)));
+		assert_eq!(placeframe(Placement::Smart, 300, 300, area, &[(0, 20, 400, 400), (400, 20, 600, 400)], (0, 0)), Some((0, 420)));
+		//Nowhere free, so wherever covers the least.
+		assert_eq!(placeframe(Placement::Smart, 600, 600, area, &[(0, 20, 500, 780), (500, 20, 500, 700)], (0, 0)), Some((400, 200)));
+		//Too big for the screen.
+		assert_eq!(placeframe(Placement::Smart, 1200, 300, area, &[], (0, 0)), Some((0, 20)));
+	}
+}